@@ -0,0 +1,47 @@
+use crate::socket::{Command, Controllers};
+use log::{debug, warn};
+use std::process::{self, Stdio};
+use std::thread;
+use std::time::Duration;
+
+/// Runs the command printing the focused application's window class on an interval, so
+/// keystrokes never wait on it, and sends the class to the keyboards
+pub fn watch(command: String, interval: Duration, controllers: Controllers) {
+    thread::spawn(move || {
+        let mut current = None;
+        loop {
+            let app = focused_app(&command);
+            if app != current {
+                debug!("Focused app: {:?}", app);
+                current = app.clone();
+            }
+            // Sent every time so keyboards plugged in since get it too
+            controllers
+                .lock()
+                .unwrap()
+                .retain(|tx| tx.send(Command::App(app.clone())).is_ok());
+            thread::sleep(interval);
+        }
+    });
+}
+
+fn focused_app(command: &str) -> Option<String> {
+    let output = process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => {
+            let app = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+            (!app.is_empty()).then_some(app)
+        }
+        Ok(_) => None,
+        Err(e) => {
+            warn!("Failed to run app_command {}: {}", command, e);
+            None
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use anyhow::{Result, bail};
+use std::env;
+use std::path::PathBuf;
+
+/// Command line: `oxidekeys [config] [--record file] [--replay file] [--list-devices] [--dry-run]
+/// [--device name]`
+#[derive(Debug, Default)]
+pub(crate) struct Args {
+    pub config: Option<PathBuf>,
+    /// Records the first keyboard's key events to this file
+    pub record: Option<PathBuf>,
+    /// Runs recorded key events through the pipeline instead of grabbing keyboards
+    pub replay: Option<PathBuf>,
+    /// Prints the input devices to configure and exits
+    pub list_devices: bool,
+    /// Logs the keys that would be sent instead of sending them, as `no_emit` does
+    pub dry_run: bool,
+    /// Grabs only the keyboard with this name
+    pub device: Option<String>,
+}
+
+impl Args {
+    pub(crate) fn parse() -> Result<Self> {
+        let mut args = Self::default();
+        let mut iter = env::args().skip(1);
+
+        while let Some(arg) = iter.next() {
+            let mut value = || match iter.next() {
+                Some(value) => Ok(value),
+                None => bail!("{} needs a value", arg),
+            };
+            match arg.as_str() {
+                "--record" => args.record = Some(PathBuf::from(value()?)),
+                "--replay" => args.replay = Some(PathBuf::from(value()?)),
+                "--device" => args.device = Some(value()?),
+                "--list-devices" => args.list_devices = true,
+                "--dry-run" => args.dry_run = true,
+                _ if arg.starts_with("--") => bail!("Unknown option {}", arg),
+                _ if args.config.is_none() => args.config = Some(PathBuf::from(arg)),
+                _ => bail!("Unexpected argument {}", arg),
+            }
+        }
+
+        Ok(args)
+    }
+}
@@ -1,14 +1,20 @@
-use anyhow::Result;
-use evdev::KeyCode;
-use log::{info, trace};
+use anyhow::{Context, Result, bail};
+use evdev::{KeyCode, LedCode};
+use indexmap::IndexMap;
+use log::{LevelFilter, info, trace, warn};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::{env, fs};
+use serde_yaml::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-pub(crate) fn config() -> Result<Config> {
-    let config_path = match env::args().nth(1) {
-        Some(arg_path) => PathBuf::from(arg_path),
+/// Config format this build reads and writes, older ones are migrated when loaded
+const CONFIG_VERSION: u64 = 2;
+
+pub fn config(path: Option<&Path>) -> Result<Config> {
+    let config_path = match path {
+        Some(arg_path) => arg_path.to_path_buf(),
         None => dirs::config_dir()
             .unwrap_or_else(|| std::path::PathBuf::from("~/.config"))
             .join("oxidekeys")
@@ -18,13 +24,24 @@ pub(crate) fn config() -> Result<Config> {
     let config = if !config_path.exists() {
         let config = Config::default();
         fs::create_dir_all(config_path.parent().unwrap())?;
-        let serialized = serde_yaml::to_string(&config)?;
+        let serialized = if is_toml(&config_path) {
+            toml::to_string(&config)?
+        } else {
+            serde_yaml::to_string(&config)?
+        };
         fs::write(&config_path, serialized)?;
         info!("Default config written to {}", config_path.display());
         config
     } else {
         let config_content = fs::read_to_string(&config_path)?;
-        serde_yaml::from_str(&config_content)?
+        let mut value = read_value(&config_path, &config_content)?;
+        let migrated = migrate(&mut value);
+        let mut config = parse(&config_path, value.clone())?;
+        if !migrated.is_empty() {
+            write_migrated(&config_path, &value)?;
+            config.migrated = migrated;
+        }
+        config
     };
 
     trace!("Config: {:#?}", config);
@@ -32,10 +49,443 @@ pub(crate) fn config() -> Result<Config> {
     Ok(config)
 }
 
-pub(crate) type Keyboards = HashMap<String, KeyboardConfig>;
-pub(crate) type Mappings = HashMap<KeyCode, RemapAction>;
-pub(crate) type Layers = HashMap<String, HashMap<KeyCode, HashMap<KeyCode, Vec<KeyCode>>>>;
-pub(crate) type Features = HashMap<String, bool>;
+fn is_toml(config_path: &Path) -> bool {
+    config_path.extension().is_some_and(|ext| ext == "toml")
+}
+
+/// Reads the config file's value tree, TOML is read into the same tree as YAML so both
+/// formats share one migration and validation pass
+fn read_value(config_path: &Path, config_content: &str) -> Result<Value> {
+    let value = if is_toml(config_path) {
+        toml::from_str(config_content)
+            .with_context(|| format!("Failed to parse {}", config_path.display()))?
+    } else {
+        serde_yaml::from_str(config_content)
+            .with_context(|| format!("Failed to parse {}", config_path.display()))?
+    };
+    Ok(value)
+}
+
+/// Upgrades a config written for an older format to the current one, returning what changed.
+/// A config without a `version` predates it
+fn migrate(value: &mut Value) -> Vec<String> {
+    let mut migrated = Vec::new();
+    let Some(config) = value.as_mapping_mut() else {
+        return migrated;
+    };
+    let version = config.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version > CONFIG_VERSION {
+        warn!(
+            "Config version {} is newer than this build's {}, it may not load as intended",
+            version, CONFIG_VERSION
+        );
+        return migrated;
+    }
+
+    // Up to version 1 the settings every keyboard shares were at the top level, they're under
+    // `globals` now. Anything else is left for validation to report
+    if version < 2 {
+        for (name, global) in [
+            ("no_emit", "no_emit"),
+            ("term", "term"),
+            ("hrm_term", "hrm_term"),
+            ("repeat_delay", "repeat_delay_ms"),
+            ("repeat_rate", "repeat_rate_ms"),
+        ] {
+            let Some(setting) = config.remove(name) else {
+                continue;
+            };
+            let globals = config
+                .entry("globals".into())
+                .or_insert_with(|| Value::Mapping(Default::default()));
+            if let Value::Mapping(globals) = globals
+                && !globals.contains_key(global)
+            {
+                globals.insert(global.into(), setting);
+            }
+            if name == global {
+                migrated.push(format!("{} moved under globals", name));
+            } else {
+                migrated.push(format!("{} moved under globals as {}", name, global));
+            }
+        }
+    }
+
+    if !migrated.is_empty() {
+        config.insert("version".into(), CONFIG_VERSION.into());
+    }
+    migrated
+}
+
+/// Writes a migrated config back over the old one, which is kept alongside with `.bak` on
+/// the end as comments don't survive
+fn write_migrated(config_path: &Path, value: &Value) -> Result<()> {
+    let mut backup = config_path.as_os_str().to_owned();
+    backup.push(".bak");
+    fs::copy(config_path, &backup)?;
+    let serialized = if is_toml(config_path) {
+        toml::to_string(value)?
+    } else {
+        serde_yaml::to_string(value)?
+    };
+    fs::write(config_path, serialized)?;
+    Ok(())
+}
+
+/// Loads a config from YAML the way `config` loads a file, without writing anything back
+#[cfg(test)]
+pub(crate) fn from_yaml(yaml: &str) -> Result<Config> {
+    let mut value = serde_yaml::from_str(yaml)?;
+    migrate(&mut value);
+    parse(Path::new("config.yml"), value)
+}
+
+/// Parses the config, reporting every invalid keycode and setting together
+fn parse(config_path: &Path, value: Value) -> Result<Config> {
+    // Unknown keycodes are collected up front, serde only reports the first without its keyboard
+    let mut errors = Vec::new();
+    if let Some(passthrough) = value.get("passthrough") {
+        check_keycodes(passthrough, "passthrough", &mut errors);
+    }
+    if let Some(templates) = value.get("templates") {
+        check_keycodes(templates, "templates", &mut errors);
+    }
+    if let Some(keyboard) = value.get("default_keyboard") {
+        check_keycodes(keyboard, "default_keyboard", &mut errors);
+    }
+    if let Some(keyboards) = value.get("keyboards").and_then(Value::as_mapping) {
+        for (name, keyboard) in keyboards {
+            let name = name.as_str().unwrap_or_default();
+            check_keycodes(keyboard, &format!("keyboard '{}'", name), &mut errors);
+        }
+    }
+    if let Some(profiles) = value.get("profiles").and_then(Value::as_mapping) {
+        for (profile_name, profile) in profiles {
+            let profile_name = profile_name.as_str().unwrap_or_default();
+            let Some(keyboards) = profile.get("keyboards").and_then(Value::as_mapping) else {
+                continue;
+            };
+            for (name, keyboard) in keyboards {
+                let name = name.as_str().unwrap_or_default();
+                let location = format!("profile '{}', keyboard '{}'", profile_name, name);
+                check_keycodes(keyboard, &location, &mut errors);
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        let mut config: Config = serde_yaml::from_value(value)
+            .with_context(|| format!("Failed to parse {}", config_path.display()))?;
+        expand_templates(&mut config);
+        errors = validate(&config);
+        if errors.is_empty() {
+            return Ok(config);
+        }
+    }
+
+    bail!(
+        "Invalid config {}:\n  {}",
+        config_path.display(),
+        errors.join("\n  ")
+    )
+}
+
+/// Merges the template each layer uses into its keys, the keys a layer maps itself win
+fn expand_templates(config: &mut Config) {
+    let templates = &config.templates;
+    let keyboards = config
+        .keyboards
+        .values_mut()
+        .chain(config.default_keyboard.as_mut())
+        .chain(
+            config
+                .profiles
+                .values_mut()
+                .flat_map(|profile| profile.keyboards.values_mut()),
+        );
+    for layer in keyboards.flat_map(|keyboard| keyboard.layers.values_mut()) {
+        let Some(template) = layer.template.as_ref().and_then(|name| templates.get(name)) else {
+            continue;
+        };
+        let uses_keys = layer.default || !layer.chord.is_empty();
+        let mappings = layer
+            .triggers
+            .values_mut()
+            .chain(uses_keys.then_some(&mut layer.keys));
+        for mapping in mappings {
+            for (key, action) in template {
+                mapping.entry(*key).or_insert_with(|| action.clone());
+            }
+        }
+    }
+}
+
+/// Recursively checks every keycode name under a keyboard, skipping free-form strings
+fn check_keycodes(value: &Value, location: &str, errors: &mut Vec<String>) {
+    match value {
+        Value::String(name)
+            if (name.starts_with("KEY_") || name.starts_with("BTN_"))
+                && KeyCode::from_str(name).is_err() =>
+        {
+            errors.push(format!("{}: unknown keycode '{}'", location, name));
+        }
+        Value::Sequence(values) => {
+            for value in values {
+                check_keycodes(value, location, errors);
+            }
+        }
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping {
+                let location = match (location.strip_suffix("layers"), key.as_str()) {
+                    (Some(keyboard), Some(layer)) => format!("{}layer '{}'", keyboard, layer),
+                    (None, Some("layers")) => format!("{}, layers", location),
+                    (_, Some("text" | "unicode" | "exec" | "custom")) => continue,
+                    _ => location.to_owned(),
+                };
+                check_keycodes(key, &location, errors);
+                check_keycodes(value, &location, errors);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Adds every keycode in a config value to `keycodes`, leaving out text and commands
+fn collect_keycodes(value: &Value, keycodes: &mut BTreeSet<KeyCode>) {
+    match value {
+        Value::String(name) => keycodes.extend(KeyCode::from_str(name).ok()),
+        Value::Sequence(values) => {
+            for value in values {
+                collect_keycodes(value, keycodes);
+            }
+        }
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping {
+                if matches!(key.as_str(), Some("text" | "unicode" | "exec" | "custom")) {
+                    continue;
+                }
+                collect_keycodes(key, keycodes);
+                collect_keycodes(value, keycodes);
+            }
+        }
+        Value::Tagged(tagged) => collect_keycodes(&tagged.value, keycodes),
+        _ => {}
+    }
+}
+
+/// Checks the parsed config for mistakes serde can't catch
+fn validate(config: &Config) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    let defaults = config
+        .profiles
+        .values()
+        .filter(|profile| profile.default)
+        .count();
+    if defaults > 1 {
+        errors.push(format!("{} profiles are marked default", defaults));
+    }
+
+    let keyboards = config
+        .keyboards
+        .iter()
+        .map(|(name, keyboard)| (format!("keyboard '{}'", name), keyboard))
+        .chain(
+            config
+                .default_keyboard
+                .iter()
+                .map(|keyboard| ("default_keyboard".to_owned(), keyboard)),
+        )
+        .chain(config.profiles.iter().flat_map(|(profile_name, profile)| {
+            profile.keyboards.iter().map(move |(name, keyboard)| {
+                let location = format!("profile '{}', keyboard '{}'", profile_name, name);
+                (location, keyboard)
+            })
+        }));
+
+    for (keyboard_location, keyboard) in keyboards {
+        for (key, remap) in &keyboard.mappings {
+            let location = format!("{}, mapping {:?}", keyboard_location, key);
+            if remap.tap.as_ref().is_some_and(Vec::is_empty) {
+                errors.push(format!("{}: tap is empty", location));
+            }
+            if remap.hold.as_ref().is_some_and(Vec::is_empty) {
+                errors.push(format!("{}: hold is empty", location));
+            }
+            if let Some(long_tap) = &remap.long_tap {
+                if long_tap.is_empty() {
+                    errors.push(format!("{}: long_tap is empty", location));
+                }
+                if remap
+                    .term
+                    .or(keyboard.term)
+                    .or(config.globals.term)
+                    .is_none()
+                {
+                    errors.push(format!("{}: long_tap needs a term", location));
+                }
+                if remap.eager == Some(true) {
+                    errors.push(format!("{}: long_tap can't be eager", location));
+                }
+            }
+            if remap.autofire.is_some()
+                && (remap.tap.is_some()
+                    || remap.hold.is_some()
+                    || remap.layer.is_some()
+                    || remap.chord.is_some())
+            {
+                errors.push(format!(
+                    "{}: autofire can't be combined with tap, hold, layer or chord",
+                    location
+                ));
+            }
+            if let Some(chord) = &remap.chord {
+                if chord.is_empty() {
+                    errors.push(format!("{}: chord is empty", location));
+                }
+                if remap.tap.is_some() || remap.hold.is_some() || remap.layer.is_some() {
+                    errors.push(format!(
+                        "{}: chord can't be combined with tap, hold or layer",
+                        location
+                    ));
+                }
+            }
+            if remap.disable == Some(true)
+                && (remap.tap.is_some()
+                    || remap.hold.is_some()
+                    || remap.layer.is_some()
+                    || remap.chord.is_some()
+                    || remap.autofire.is_some())
+            {
+                errors.push(format!(
+                    "{}: disable can't be combined with tap, hold, layer, chord or autofire",
+                    location
+                ));
+            }
+            if remap.swap_hands == Some(true)
+                && (remap.hold.is_some()
+                    || remap.layer.is_some()
+                    || remap.chord.is_some()
+                    || remap.autofire.is_some())
+            {
+                errors.push(format!(
+                    "{}: swap_hands can't be combined with hold, layer, chord or autofire",
+                    location
+                ));
+            }
+            if let Some(layer) = &remap.layer
+                && !keyboard.layers.contains_key(layer)
+            {
+                errors.push(format!("{}: layer '{}' does not exist", location, layer));
+            }
+            if let Some(taps) = &remap.taps
+                && (taps.is_empty() || taps.iter().any(Vec::is_empty))
+            {
+                errors.push(format!("{}: taps has an empty sequence", location));
+            }
+            if remap.when_app.is_some() && config.app_command.is_none() {
+                errors.push(format!("{}: when_app needs an app_command", location));
+            }
+            if let Some(profile) = &remap.profile
+                && !config.profiles.contains_key(profile)
+            {
+                errors.push(format!(
+                    "{}: profile '{}' does not exist",
+                    location, profile
+                ));
+            }
+        }
+
+        for (layer_name, layer) in &keyboard.layers {
+            if let Some(template) = &layer.template
+                && !config.templates.contains_key(template)
+            {
+                errors.push(format!(
+                    "{}, layer '{}': template '{}' does not exist",
+                    keyboard_location, layer_name, template
+                ));
+            }
+            let remaps = layer
+                .triggers
+                .values()
+                .chain([&layer.keys])
+                .flat_map(|keys| keys.iter())
+                .filter_map(|(key, action)| match action {
+                    LayerAction::Remap(remap) => Some((key, remap)),
+                    _ => None,
+                });
+            for (key, remap) in remaps {
+                if remap.layer.is_some()
+                    || remap.taps.is_some()
+                    || remap.chord.is_some()
+                    || remap.disable.is_some()
+                    || remap.swap_hands.is_some()
+                {
+                    errors.push(format!(
+                        "{}, layer '{}', key {:?}: layer, taps, chord, disable and swap_hands can't be used on a layer",
+                        keyboard_location, layer_name, key
+                    ));
+                }
+            }
+            for trigger in layer.triggers.keys() {
+                if keyboard.mappings.contains_key(trigger) {
+                    errors.push(format!(
+                        "{}, layer '{}': trigger {:?} is also mapped",
+                        keyboard_location, layer_name, trigger
+                    ));
+                }
+            }
+            if layer.tap.as_ref().is_some_and(Vec::is_empty) {
+                errors.push(format!(
+                    "{}, layer '{}': tap is empty",
+                    keyboard_location, layer_name
+                ));
+            }
+            if !layer.chord.is_empty() && layer.chord.len() < 2 {
+                errors.push(format!(
+                    "{}, layer '{}': chord needs at least two keys",
+                    keyboard_location, layer_name
+                ));
+            }
+            for key in &layer.chord {
+                if keyboard
+                    .layers
+                    .values()
+                    .any(|l| l.triggers.contains_key(key))
+                {
+                    errors.push(format!(
+                        "{}, layer '{}': chord key {:?} is also a trigger",
+                        keyboard_location, layer_name, key
+                    ));
+                }
+            }
+        }
+
+        for combo in &keyboard.combos {
+            if combo.keys.is_empty() || (combo.output.is_empty() == combo.text.is_none()) {
+                errors.push(format!(
+                    "{}, combo {:?}: keys and either output or text must be set",
+                    keyboard_location, combo.keys
+                ));
+            }
+        }
+    }
+
+    errors
+}
+
+/// Expands a leading `~` to the user's home directory
+pub fn expand_path(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+pub type Keyboards = HashMap<String, KeyboardConfig>;
+pub type Mappings = HashMap<KeyCode, RemapAction>;
+pub type Layers = IndexMap<String, Layer>;
+pub type Features = HashMap<String, bool>;
 
 fn default_mappings() -> Mappings {
     HashMap::from([
@@ -77,109 +527,138 @@ fn default_mappings() -> Mappings {
 }
 
 fn default_layers() -> Layers {
-    HashMap::from([
+    IndexMap::from([
         (
             "Symbols & Navigation".into(),
-            HashMap::from([(
+            IndexMap::from([(
                 KeyCode::KEY_RIGHTALT,
                 HashMap::from([
                     // Vim Arrows
-                    (KeyCode::KEY_H, vec![KeyCode::KEY_LEFT]),
-                    (KeyCode::KEY_J, vec![KeyCode::KEY_DOWN]),
-                    (KeyCode::KEY_K, vec![KeyCode::KEY_UP]),
-                    (KeyCode::KEY_L, vec![KeyCode::KEY_RIGHT]),
+                    (KeyCode::KEY_H, vec![KeyCode::KEY_LEFT].into()),
+                    (KeyCode::KEY_J, vec![KeyCode::KEY_DOWN].into()),
+                    (KeyCode::KEY_K, vec![KeyCode::KEY_UP].into()),
+                    (KeyCode::KEY_L, vec![KeyCode::KEY_RIGHT].into()),
                     // (
-                    (KeyCode::KEY_I, vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_9]),
+                    (
+                        KeyCode::KEY_I,
+                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_9].into(),
+                    ),
                     // )
-                    (KeyCode::KEY_D, vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_0]),
+                    (
+                        KeyCode::KEY_D,
+                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_0].into(),
+                    ),
                     // {
                     (
                         KeyCode::KEY_X,
-                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_LEFTBRACE],
+                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_LEFTBRACE].into(),
                     ),
                     // }
                     (
                         KeyCode::KEY_B,
-                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_RIGHTBRACE],
+                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_RIGHTBRACE].into(),
                     ),
                     // [
-                    (KeyCode::KEY_Y, vec![KeyCode::KEY_LEFTBRACE]),
+                    (KeyCode::KEY_Y, vec![KeyCode::KEY_LEFTBRACE].into()),
                     // ]
-                    (KeyCode::KEY_F, vec![KeyCode::KEY_RIGHTBRACE]),
+                    (KeyCode::KEY_F, vec![KeyCode::KEY_RIGHTBRACE].into()),
                     // /
-                    (KeyCode::KEY_SEMICOLON, vec![KeyCode::KEY_SLASH]),
+                    (KeyCode::KEY_SEMICOLON, vec![KeyCode::KEY_SLASH].into()),
                     // \
-                    (KeyCode::KEY_Z, vec![KeyCode::KEY_BACKSLASH]),
+                    (KeyCode::KEY_Z, vec![KeyCode::KEY_BACKSLASH].into()),
                     // `
-                    (KeyCode::KEY_APOSTROPHE, vec![KeyCode::KEY_GRAVE]),
+                    (KeyCode::KEY_APOSTROPHE, vec![KeyCode::KEY_GRAVE].into()),
                     // !
                     (
                         KeyCode::KEY_COMMA,
-                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_1],
+                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_1].into(),
                     ),
                     // ?
                     (
                         KeyCode::KEY_DOT,
-                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_SLASH],
+                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_SLASH].into(),
                     ),
                     // @
-                    (KeyCode::KEY_P, vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_2]),
+                    (
+                        KeyCode::KEY_P,
+                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_2].into(),
+                    ),
                     // =
-                    (KeyCode::KEY_A, vec![KeyCode::KEY_EQUAL]),
+                    (KeyCode::KEY_A, vec![KeyCode::KEY_EQUAL].into()),
                     // |
                     (
                         KeyCode::KEY_O,
-                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_BACKSLASH],
+                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_BACKSLASH].into(),
                     ),
                     // ^
-                    (KeyCode::KEY_E, vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_6]),
+                    (
+                        KeyCode::KEY_E,
+                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_6].into(),
+                    ),
                     // _
                     (
                         KeyCode::KEY_U,
-                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_MINUS],
+                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_MINUS].into(),
                     ),
                     // #
-                    (KeyCode::KEY_Q, vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_3]),
+                    (
+                        KeyCode::KEY_Q,
+                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_3].into(),
+                    ),
                     // $
-                    (KeyCode::KEY_T, vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_4]),
+                    (
+                        KeyCode::KEY_T,
+                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_4].into(),
+                    ),
                     // &
-                    (KeyCode::KEY_N, vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_7]),
+                    (
+                        KeyCode::KEY_N,
+                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_7].into(),
+                    ),
                     // -
-                    (KeyCode::KEY_S, vec![KeyCode::KEY_MINUS]),
+                    (KeyCode::KEY_S, vec![KeyCode::KEY_MINUS].into()),
                     // +
                     (
                         KeyCode::KEY_M,
-                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_EQUAL],
+                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_EQUAL].into(),
                     ),
                     // %
-                    (KeyCode::KEY_W, vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_5]),
+                    (
+                        KeyCode::KEY_W,
+                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_5].into(),
+                    ),
                     // *
-                    (KeyCode::KEY_V, vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_8]),
+                    (
+                        KeyCode::KEY_V,
+                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_8].into(),
+                    ),
                     // ~
                     (
                         KeyCode::KEY_G,
-                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_GRAVE],
+                        vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_GRAVE].into(),
                     ),
                 ]),
-            )]),
+            )])
+            .into(),
         ),
         (
             "Numbers".into(),
-            HashMap::from([(
+            IndexMap::from([(
                 KeyCode::KEY_F23,
                 HashMap::from([
-                    (KeyCode::KEY_S, vec![KeyCode::KEY_0]),
-                    (KeyCode::KEY_M, vec![KeyCode::KEY_1]),
-                    (KeyCode::KEY_W, vec![KeyCode::KEY_2]),
-                    (KeyCode::KEY_V, vec![KeyCode::KEY_3]),
-                    (KeyCode::KEY_H, vec![KeyCode::KEY_4]),
-                    (KeyCode::KEY_T, vec![KeyCode::KEY_5]),
-                    (KeyCode::KEY_N, vec![KeyCode::KEY_6]),
-                    (KeyCode::KEY_G, vec![KeyCode::KEY_7]),
-                    (KeyCode::KEY_C, vec![KeyCode::KEY_8]),
-                    (KeyCode::KEY_R, vec![KeyCode::KEY_9]),
+                    (KeyCode::KEY_S, vec![KeyCode::KEY_0].into()),
+                    (KeyCode::KEY_M, vec![KeyCode::KEY_1].into()),
+                    (KeyCode::KEY_W, vec![KeyCode::KEY_2].into()),
+                    (KeyCode::KEY_V, vec![KeyCode::KEY_3].into()),
+                    (KeyCode::KEY_H, vec![KeyCode::KEY_4].into()),
+                    (KeyCode::KEY_T, vec![KeyCode::KEY_5].into()),
+                    (KeyCode::KEY_N, vec![KeyCode::KEY_6].into()),
+                    (KeyCode::KEY_G, vec![KeyCode::KEY_7].into()),
+                    (KeyCode::KEY_C, vec![KeyCode::KEY_8].into()),
+                    (KeyCode::KEY_R, vec![KeyCode::KEY_9].into()),
                 ]),
-            )]),
+            )])
+            .into(),
         ),
     ])
 }
@@ -192,56 +671,584 @@ fn default_keyboards() -> Keyboards {
             mappings: default_mappings(),
             layers: default_layers(),
             double_tap_timeout: default_double_tap_timeout(),
+            tapdance_timeout: default_tapdance_timeout(),
+            combos: Vec::new(),
+            combo_term: default_combo_term(),
+            toggle_term: default_toggle_term(),
+            tapping_toggle: default_tapping_toggle(),
+            passthrough: Vec::new(),
+            max_hold: default_max_hold(),
+            same_frame: false,
+            term: None,
+            repeat_delay_ms: None,
+            repeat_rate_ms: None,
+            unicode_prefix: default_unicode_prefix(),
+            unicode_suffix: default_unicode_suffix(),
+            mouse_interval: default_mouse_interval(),
+            hrm_term: None,
+            hrm_term_left: None,
+            hrm_term_right: None,
+            streak_term: None,
+            bilateral_combinations: default_bilateral_combinations(),
+            auto_shift_term: default_auto_shift_term(),
+            auto_shift_keys: None,
+            hands: HashMap::new(),
+            compose: HashMap::new(),
+            features: HashMap::new(),
         },
     )])
 }
 
-fn default_layout() -> Option<String> {
-    Some("dvorak".to_string())
+fn default_layout() -> Option<LayoutConfig> {
+    Some(LayoutConfig::Named("dvorak".to_string()))
 }
 
 fn default_double_tap_timeout() -> Option<u16> {
     Some(144)
 }
 
+fn default_tapdance_timeout() -> Option<u16> {
+    Some(200)
+}
+
+fn default_combo_term() -> Option<u16> {
+    Some(50)
+}
+
+fn default_toggle_term() -> Option<u16> {
+    Some(200)
+}
+
+fn default_tapping_toggle() -> Option<u8> {
+    Some(5)
+}
+
+fn default_max_hold() -> Option<u16> {
+    Some(10000)
+}
+
+fn default_unicode_prefix() -> Option<Vec<KeyCode>> {
+    Some(vec![
+        KeyCode::KEY_LEFTCTRL,
+        KeyCode::KEY_LEFTSHIFT,
+        KeyCode::KEY_U,
+    ])
+}
+
+fn default_unicode_suffix() -> Option<Vec<KeyCode>> {
+    Some(vec![KeyCode::KEY_ENTER])
+}
+
+fn default_mouse_interval() -> Option<u16> {
+    Some(16)
+}
+
+fn default_bilateral_combinations() -> Option<bool> {
+    Some(false)
+}
+
+fn default_auto_shift_term() -> Option<u16> {
+    Some(175)
+}
+
 fn default_features() -> Features {
     HashMap::from([
         ("dual_function".to_owned(), true),
         ("layers".to_owned(), true),
+        ("tapdance".to_owned(), true),
+        ("combos".to_owned(), true),
+        ("caps_word".to_owned(), true),
+        ("keylock".to_owned(), true),
+        ("compose".to_owned(), true),
+        ("auto_shift".to_owned(), false),
+        ("repeat_key".to_owned(), true),
+        ("mouse_keys".to_owned(), true),
+        ("swap_hands".to_owned(), true),
+        ("exec".to_owned(), true),
     ])
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub(crate) struct Config {
+pub struct Config {
+    /// Format the config was written for, older ones are migrated when loaded
+    #[serde(default)]
+    pub version: u64,
     #[serde(default = "default_features")]
     pub features: HashMap<String, bool>,
     #[serde(default = "default_keyboards")]
     pub keyboards: Keyboards,
+    /// Config of the keyboards that have none of their own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_keyboard: Option<KeyboardConfig>,
+    /// Grabs every keyboard, not only the configured ones
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub auto_grab: bool,
+    /// Milliseconds to wait for keys to be released before grabbing a keyboard anyway
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grab_timeout: Option<u16>,
+    /// Unix socket accepting layer and status commands
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub socket: Option<PathBuf>,
+    /// Command printing the focused application's window class, for `when_app`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_command: Option<String>,
+    /// Milliseconds between runs of the `app_command`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_interval: Option<u16>,
+    /// Logs to a rotated file instead of stderr
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log: Option<LogConfig>,
+    /// Named sets of keyboards and features to switch between at runtime
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub profiles: HashMap<String, Profile>,
+    /// Keys every keyboard passes through untouched, whatever maps them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub passthrough: Vec<KeyCode>,
+    /// Named sets of layer keys, for layers to `use` instead of repeating them
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub templates: HashMap<String, HashMap<KeyCode, LayerAction>>,
+    /// Settings shared by every keyboard
+    #[serde(default)]
+    pub globals: Globals,
+    /// Sends every keyboard's keys through one virtual device instead of one each
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub merge_output: bool,
+    /// Only keyboard grabbed, from `--device`
+    #[serde(skip)]
+    pub device: Option<String>,
+    /// What loading migrated from an older format, logged once logging is set up
+    #[serde(skip)]
+    pub migrated: Vec<String>,
+}
+
+impl Config {
+    /// Logs what loading migrated from an older format
+    pub fn log_migrated(&self) {
+        for change in &self.migrated {
+            info!("Config migrated: {}", change);
+        }
+        if !self.migrated.is_empty() {
+            info!("Migrated config written back, the old one kept with .bak on the end");
+        }
+    }
+
+    /// Every keycode the config sends or maps, in any keyboard, profile or template
+    pub fn keycodes(&self) -> BTreeSet<KeyCode> {
+        let mut keycodes = BTreeSet::new();
+        if let Ok(value) = serde_yaml::to_value(self) {
+            collect_keycodes(&value, &mut keycodes);
+        }
+        keycodes
+    }
+
+    /// The profile marked as the default, the top level keyboards are used without one
+    pub fn default_profile(&self) -> Option<String> {
+        self.profiles
+            .iter()
+            .find(|(_, profile)| profile.default)
+            .map(|(name, _)| name.clone())
+    }
+
+    /// Whether the keyboard is configured at the top level or in any profile, or every keyboard
+    /// is grabbed
+    pub fn has_keyboard(&self, name: &str) -> bool {
+        self.auto_grab || self.names_keyboard(name)
+    }
+
+    /// Whether the keyboard is configured by name at the top level or in any profile
+    pub fn names_keyboard(&self, name: &str) -> bool {
+        self.keyboards.contains_key(name)
+            || self
+                .profiles
+                .values()
+                .any(|profile| profile.keyboards.contains_key(name))
+    }
+
+    /// The enabled features and config of a keyboard under a profile, a keyboard the profile
+    /// doesn't configure takes the `default_keyboard`, or passes its keys through without one
+    pub fn keyboard(&self, profile: Option<&str>, name: &str) -> (Features, KeyboardConfig) {
+        let profile = profile.and_then(|profile| self.profiles.get(profile));
+        let keyboards = profile
+            .filter(|profile| !profile.keyboards.is_empty())
+            .map_or(&self.keyboards, |profile| &profile.keyboards);
+        let mut keyboard = keyboards
+            .get(name)
+            .or(self.default_keyboard.as_ref())
+            .cloned()
+            .unwrap_or_default();
+        keyboard.passthrough.extend(&self.passthrough);
+        keyboard.term = keyboard.term.or(self.globals.term);
+        // A keyboard's own `hrm_term` wins over the global ones for each hand
+        if keyboard.hrm_term.is_none() {
+            keyboard.hrm_term_left = keyboard.hrm_term_left.or(self.globals.hrm_term_left);
+            keyboard.hrm_term_right = keyboard.hrm_term_right.or(self.globals.hrm_term_right);
+        }
+        keyboard.hrm_term = keyboard.hrm_term.or(self.globals.hrm_term);
+        keyboard.repeat_delay_ms = keyboard.repeat_delay_ms.or(self.globals.repeat_delay_ms);
+        keyboard.repeat_rate_ms = keyboard.repeat_rate_ms.or(self.globals.repeat_rate_ms);
+
+        let mut features = self.features.clone();
+        if let Some(profile) = profile {
+            features.extend(profile.features.clone());
+        }
+        features.extend(keyboard.features.clone());
+        (features, keyboard)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    /// Used on startup
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub default: bool,
+    /// Overrides of the global features
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub features: Features,
+    /// Replaces the top level keyboards, which are kept when empty
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub keyboards: Keyboards,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Globals {
+    /// `term` of the keyboards that don't set their own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub term: Option<u16>,
+    /// `hrm_term` of the keyboards that don't set their own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hrm_term: Option<u16>,
+    /// `hrm_term_left` of the keyboards that don't set their own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hrm_term_left: Option<u16>,
+    /// `hrm_term_right` of the keyboards that don't set their own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hrm_term_right: Option<u16>,
+    /// `repeat_delay_ms` of the keyboards that don't set their own
+    #[serde(
+        default,
+        alias = "repeat_delay",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub repeat_delay_ms: Option<u16>,
+    /// `repeat_rate_ms` of the keyboards that don't set their own
+    #[serde(
+        default,
+        alias = "repeat_rate",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub repeat_rate_ms: Option<u16>,
+    /// Reads keyboards without grabbing them and logs the keys that would be sent instead
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_emit: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogConfig {
+    pub file: PathBuf,
+    #[serde(default = "default_log_level")]
+    pub level: LevelFilter,
+}
+
+fn default_log_level() -> LevelFilter {
+    LevelFilter::Info
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub(crate) struct KeyboardConfig {
+pub struct KeyboardConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub layout: Option<String>,
+    pub layout: Option<LayoutConfig>,
     #[serde(default = "default_mappings")]
     pub mappings: Mappings,
     #[serde(default = "default_layers")]
     pub layers: Layers,
     #[serde(default = "default_double_tap_timeout")]
     pub double_tap_timeout: Option<u16>,
+    #[serde(default = "default_tapdance_timeout")]
+    pub tapdance_timeout: Option<u16>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub combos: Vec<Combo>,
+    #[serde(default = "default_combo_term")]
+    pub combo_term: Option<u16>,
+    #[serde(default = "default_toggle_term")]
+    pub toggle_term: Option<u16>,
+    /// Taps in a row that latch a `tap_toggle` layer
+    #[serde(default = "default_tapping_toggle")]
+    pub tapping_toggle: Option<u8>,
+    /// Keys passed through untouched, along with the global ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub passthrough: Vec<KeyCode>,
+    /// How long a key can stay pressed on the virtual device with no key held before it's
+    /// released as stuck
+    #[serde(default = "default_max_hold")]
+    pub max_hold: Option<u16>,
+    /// Sends modifiers in the same `SYN_REPORT` frame as the keys they're sent with, instead
+    /// of a frame ahead, for apps that only read them together
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub same_frame: bool,
+    /// Milliseconds after which releasing a mapping with a hold no longer taps, unless the
+    /// mapping has a `term` of its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub term: Option<u16>,
+    /// Milliseconds a key is held before it repeats, repeating it here instead of leaving it to
+    /// the kernel
+    #[serde(
+        default,
+        alias = "repeat_delay",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub repeat_delay_ms: Option<u16>,
+    /// Milliseconds between the repeats of a held key, repeating it here instead of leaving it
+    /// to the kernel
+    #[serde(
+        default,
+        alias = "repeat_rate",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub repeat_rate_ms: Option<u16>,
+    #[serde(default = "default_unicode_prefix")]
+    pub unicode_prefix: Option<Vec<KeyCode>>,
+    #[serde(default = "default_unicode_suffix")]
+    pub unicode_suffix: Option<Vec<KeyCode>>,
+    #[serde(default = "default_mouse_interval")]
+    pub mouse_interval: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hrm_term: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hrm_term_left: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hrm_term_right: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub streak_term: Option<u16>,
+    #[serde(default = "default_bilateral_combinations")]
+    pub bilateral_combinations: Option<bool>,
+    #[serde(default = "default_auto_shift_term")]
+    pub auto_shift_term: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_shift_keys: Option<Vec<KeyCode>>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub hands: HashMap<KeyCode, Hand>,
+    /// Precomposed characters of letters typed after a dead key, checked before the built-ins
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub compose: HashMap<char, HashMap<char, char>>,
+    /// Overrides of the global features for this keyboard
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub features: Features,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            version: CONFIG_VERSION,
             features: default_features(),
             keyboards: default_keyboards(),
+            default_keyboard: None,
+            auto_grab: false,
+            grab_timeout: None,
+            socket: None,
+            app_command: None,
+            app_interval: None,
+            log: None,
+            profiles: HashMap::new(),
+            passthrough: Vec::new(),
+            templates: HashMap::new(),
+            globals: Globals::default(),
+            merge_output: false,
+            device: None,
+            migrated: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Layer {
+    /// Always active underneath the other layers, with its keys under `keys`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub default: bool,
+
+    /// Tapping a trigger latches the layer on until tapped again
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub toggle: bool,
+
+    /// Tapping a trigger `tapping_toggle` times in a row latches the layer on until tapped again
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub tap_toggle: bool,
+
+    /// Double tapping a trigger within the `double_tap_timeout` latches the layer on until
+    /// tapped again
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub double_tap_toggle: bool,
+
+    /// Sequence a trigger types when tapped within the toggle term, with no other key pressed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tap: Option<Vec<KeyCode>>,
+
+    /// Releasing a trigger without using the layer leaves it on for the next key press
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub oneshot: bool,
+
+    /// Keyboard LED lit while the layer is active
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub led: Option<Led>,
+
+    /// Modifiers held around every key the layer maps, transparent ones included
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub apply_mods: Vec<KeyCode>,
+
+    /// Keys held together to activate the layer, it turns off once any of them is released
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chord: Vec<KeyCode>,
+
+    /// Actions of the keys on a chord or default layer
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub keys: HashMap<KeyCode, LayerAction>,
+
+    /// Template whose keys the layer takes, the keys it maps itself win
+    #[serde(default, rename = "use", skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+
+    /// Trigger keys and the actions of the keys while the layer is active
+    #[serde(flatten)]
+    pub triggers: IndexMap<KeyCode, HashMap<KeyCode, LayerAction>>,
+}
+
+impl From<IndexMap<KeyCode, HashMap<KeyCode, LayerAction>>> for Layer {
+    fn from(triggers: IndexMap<KeyCode, HashMap<KeyCode, LayerAction>>) -> Self {
+        Self {
+            default: false,
+            toggle: false,
+            tap_toggle: false,
+            double_tap_toggle: false,
+            tap: None,
+            oneshot: false,
+            led: None,
+            apply_mods: Vec::new(),
+            chord: Vec::new(),
+            keys: HashMap::new(),
+            template: None,
+            triggers,
+        }
+    }
+}
+
+impl Layer {
+    /// Action the layer maps a key to, whichever trigger or chord activated it
+    pub fn action(&self, key: &KeyCode) -> Option<&LayerAction> {
+        self.triggers
+            .values()
+            .find_map(|mapping| mapping.get(key))
+            .or_else(|| self.keys.get(key))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LayerAction {
+    /// Sequence emitted while the key is held, empty blocks the key
+    Keys(Vec<KeyCode>),
+
+    /// Mouse action while the key is held
+    Mouse(MouseAction),
+
+    /// Key handled as if no layer was active
+    Marker(LayerMarker),
+
+    /// Mapping the key has while pressed on the layer, tapping and holding like any other
+    Remap(Box<RemapAction>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayerMarker {
+    /// Falls through to the base mappings
+    Transparent,
+}
+
+impl From<Vec<KeyCode>> for LayerAction {
+    fn from(keys: Vec<KeyCode>) -> Self {
+        Self::Keys(keys)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseAction {
+    /// Pointer movement per tick
+    Move { x: i32, y: i32 },
+
+    /// Wheel movement per press, repeated every `interval` (ms) while held if set
+    Scroll {
+        x: i32,
+        y: i32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        interval: Option<u16>,
+    },
+
+    /// Button held while the key is
+    Button(MouseButton),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Side,
+    Extra,
+    Forward,
+    Back,
+}
+
+impl MouseButton {
+    pub fn code(&self) -> KeyCode {
+        match self {
+            MouseButton::Left => KeyCode::BTN_LEFT,
+            MouseButton::Right => KeyCode::BTN_RIGHT,
+            MouseButton::Middle => KeyCode::BTN_MIDDLE,
+            MouseButton::Side => KeyCode::BTN_SIDE,
+            MouseButton::Extra => KeyCode::BTN_EXTRA,
+            MouseButton::Forward => KeyCode::BTN_FORWARD,
+            MouseButton::Back => KeyCode::BTN_BACK,
         }
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Morph {
+    /// Modifiers that all have to be held
+    pub mods: Vec<KeyCode>,
+    /// Sequence tapped instead, with the held modifiers released around it
+    pub tap: Vec<KeyCode>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Combo {
+    /// Keys pressed together
+    pub keys: Vec<KeyCode>,
+
+    /// Sequence held while the combo is
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub output: Vec<KeyCode>,
+
+    /// Text typed once every key of the combo is released, instead of an output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LayoutConfig {
+    /// Built-in layout by name
+    Named(String),
+
+    /// Layout file of physical to logical keys
+    Custom { custom: PathBuf },
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
-pub(crate) struct RemapAction {
+#[serde(deny_unknown_fields)]
+pub struct RemapAction {
     /// Tap sequence
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tap: Option<Vec<KeyCode>>,
@@ -249,4 +1256,499 @@ pub(crate) struct RemapAction {
     /// Hold sequence
     #[serde(skip_serializing_if = "Option::is_none")]
     pub hold: Option<Vec<KeyCode>>,
+
+    /// Tap sequence typed instead of `tap` when released after the term
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub long_tap: Option<Vec<KeyCode>>,
+
+    /// Keys held down together for as long as the key is, with no tap or hold
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chord: Option<Vec<KeyCode>>,
+
+    /// Key tapped over and over while the key is held, with no tap or hold
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub autofire: Option<Autofire>,
+
+    /// Tap sequences replacing `tap` while their modifiers are held
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub morph: Option<Vec<Morph>>,
+
+    /// Text typed when tapped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+
+    /// Steps run in order when tapped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub steps: Option<Vec<MacroStep>>,
+
+    /// Hex code points entered when tapped, separated by whitespace
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unicode: Option<String>,
+
+    /// Shell command run when tapped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exec: Option<String>,
+
+    /// Tap dance sequences, emitted by tap count
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub taps: Option<Vec<Vec<KeyCode>>>,
+
+    /// Whether an overlapping key triggers the hold, defaults to true
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overlap: Option<bool>,
+
+    /// Milliseconds after which releasing no longer taps
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub term: Option<u16>,
+
+    /// Home row mod, using the keyboard's `hrm_term` and bilateral combinations
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hrm: Option<bool>,
+
+    /// Milliseconds held alone before the hold fires, overriding the keyboard's terms
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hrm_term: Option<u16>,
+
+    /// Milliseconds held before these overlapping keys trigger the hold, tapping when sooner
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub term_by_key: Option<HashMap<KeyCode, u16>>,
+
+    /// The same by the hand of the overlapping key, for keys not in `term_by_key`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub term_by_hand: Option<HandTerms>,
+
+    /// Pressing again within the double tap timeout of a tap holds straight away, instead of
+    /// repeating the tap
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quick_hold: Option<bool>,
+
+    /// Pressing again within the double tap timeout of a tap decides between tap and hold as
+    /// usual, instead of repeating the tap
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub force_hold: Option<bool>,
+
+    /// Sends the tap as soon as the key is pressed, a hold decided after still fires but the
+    /// tap has already been typed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eager: Option<bool>,
+
+    /// How an overlapping key decides between tap and hold
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tap_hold_mode: Option<TapHoldMode>,
+
+    /// Layer activated while held, tap still emits `tap`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer: Option<String>,
+
+    /// Toggles caps word when pressed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caps_word: Option<bool>,
+
+    /// Mirrors the keyboard while held, typing `tap` when tapped alone
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swap_hands: Option<bool>,
+
+    /// Locks the next key pressed down until it's tapped again
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_lock: Option<bool>,
+
+    /// Dead key arming its diacritic for the next letter, named by its literal character
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dead: Option<char>,
+
+    /// Presses the last key sent again, with the modifiers held along with it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat: Option<bool>,
+
+    /// Consumes the key with nothing sent, no feature sees it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disable: Option<bool>,
+
+    /// Stops remapping on every keyboard when pressed, so keys pass through until pressed again
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bypass_toggle: Option<bool>,
+
+    /// Profile every keyboard switches to when tapped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    /// Reads the config file again when tapped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reload: Option<bool>,
+
+    /// Layout to switch to when tapped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub switch_layout: Option<LayoutConfig>,
+
+    /// Layout the hold keys are sent in, instead of the keyboard's
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hold_layout: Option<LayoutConfig>,
+
+    /// Window classes of the focused applications the mapping applies in, any when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub when_app: Option<Vec<String>>,
+
+    /// Mapping used instead outside of the `when_app` applications
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub otherwise: Option<Box<RemapAction>>,
+}
+
+impl RemapAction {
+    /// The mapping that applies in the focused application, following `otherwise`
+    pub fn for_app(&self, app: Option<&str>) -> Option<&RemapAction> {
+        let applies = self.when_app.as_ref().is_none_or(|apps| {
+            app.is_some_and(|app| apps.iter().any(|a| a.eq_ignore_ascii_case(app)))
+        });
+        if applies {
+            Some(self)
+        } else {
+            self.otherwise.as_deref()?.for_app(app)
+        }
+    }
+}
+
+/// The mappings that apply in the focused application
+pub fn app_mappings(mappings: &Mappings, app: Option<&str>) -> Mappings {
+    mappings
+        .iter()
+        .filter_map(|(key, remap)| Some((*key, remap.for_app(app)?.clone())))
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Led {
+    NumLock,
+    CapsLock,
+    ScrollLock,
+    Compose,
+    Kana,
+}
+
+impl Led {
+    pub fn code(&self) -> LedCode {
+        match self {
+            Self::NumLock => LedCode::LED_NUML,
+            Self::CapsLock => LedCode::LED_CAPSL,
+            Self::ScrollLock => LedCode::LED_SCROLLL,
+            Self::Compose => LedCode::LED_COMPOSE,
+            Self::Kana => LedCode::LED_KANA,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MacroStep {
+    /// Keys pressed together then released
+    Tap { tap: Vec<KeyCode> },
+    /// Text typed
+    Text { text: String },
+    /// Milliseconds to wait before the next step, at most a second
+    Delay { delay: u16 },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Autofire {
+    pub key: KeyCode,
+    /// Milliseconds between taps, also read as `interval` like the mouse keys' scroll
+    #[serde(alias = "interval")]
+    pub interval_ms: u16,
+}
+
+/// Milliseconds by whether the overlapping key is on the same hand or the opposite one
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct HandTerms {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub same: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opposite: Option<u16>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TapHoldMode {
+    /// Any other key pressed while held triggers the hold
+    #[default]
+    HoldOnOtherKeyPress,
+
+    /// Another key has to be pressed and released while held to trigger the hold
+    PermissiveHold,
+
+    /// Another key pressed while held only triggers the hold once the hold term has passed
+    /// since the press, sooner it taps
+    HoldAfterTerm,
+
+    /// Other keys never trigger the hold, only the hold term passing does, and they're held
+    /// back until it's decided
+    IgnoreInterrupt,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_config_round_trips_through_the_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+
+        let written = config(Some(&path)).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("[keyboards.\"AT Translated Set 2 keyboard\"]"));
+        assert!(content.contains("KEY_SPACE"));
+
+        let read = config(Some(&path)).unwrap();
+        assert_eq!(
+            serde_yaml::to_value(&read).unwrap(),
+            serde_yaml::to_value(&written).unwrap()
+        );
+        assert!(read.migrated.is_empty());
+    }
+
+    #[test]
+    fn toml_and_yaml_read_keycodes_alike() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml_path = dir.path().join("config.toml");
+        let yaml_path = dir.path().join("config.yml");
+        fs::write(
+            &toml_path,
+            "[keyboards.Test]\nlayout = \"qwerty\"\n\n\
+             [keyboards.Test.mappings.KEY_CAPSLOCK]\ntap = [\"KEY_ESC\"]\nhold = [\"KEY_LEFTCTRL\"]\n",
+        )
+        .unwrap();
+        fs::write(
+            &yaml_path,
+            "keyboards:\n  Test:\n    layout: qwerty\n    mappings:\n      \
+             KEY_CAPSLOCK: {tap: [KEY_ESC], hold: [KEY_LEFTCTRL]}\n",
+        )
+        .unwrap();
+
+        let from_toml = config(Some(&toml_path)).unwrap();
+        let from_yaml = config(Some(&yaml_path)).unwrap();
+        assert_eq!(
+            serde_yaml::to_value(&from_toml).unwrap(),
+            serde_yaml::to_value(&from_yaml).unwrap()
+        );
+        let (_, keyboard) = from_toml.keyboard(None, "Test");
+        let mapping = &keyboard.mappings[&KeyCode::KEY_CAPSLOCK];
+        assert_eq!(mapping.tap, Some(vec![KeyCode::KEY_ESC]));
+        assert_eq!(mapping.hold, Some(vec![KeyCode::KEY_LEFTCTRL]));
+    }
+
+    #[test]
+    fn top_level_globals_are_migrated_and_written_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yml");
+        let old = "\
+version: 1
+no_emit: true
+term: 300
+repeat_delay: 200
+keyboards:
+  Test:
+    layout: qwerty
+";
+        fs::write(&path, old).unwrap();
+
+        let migrated = config(Some(&path)).unwrap();
+        assert!(migrated.globals.no_emit);
+        assert_eq!(migrated.globals.term, Some(300));
+        assert_eq!(
+            migrated.migrated,
+            [
+                "no_emit moved under globals",
+                "term moved under globals",
+                "repeat_delay moved under globals as repeat_delay_ms"
+            ]
+        );
+        assert_eq!(migrated.globals.repeat_delay_ms, Some(200));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("config.yml.bak")).unwrap(),
+            old
+        );
+
+        let written: Value = serde_yaml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["version"], Value::from(CONFIG_VERSION));
+        assert_eq!(written["globals"]["term"], Value::from(300));
+        assert!(written.get("no_emit").is_none());
+        let reread = config(Some(&path)).unwrap();
+        assert!(reread.migrated.is_empty());
+        assert_eq!(reread.globals.term, Some(300));
+    }
+
+    #[test]
+    fn unknown_globals_are_rejected_not_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yml");
+        let old = "\
+globals:
+  term: 300
+  tapping_term: 200
+keyboards:
+  Test:
+    layout: qwerty
+";
+        fs::write(&path, old).unwrap();
+
+        let error = config(Some(&path)).unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("tapping_term"),
+            "{:#}",
+            error
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), old);
+        assert!(!dir.path().join("config.yml.bak").exists());
+    }
+
+    #[test]
+    fn globals_term_applies_unless_a_mapping_sets_its_own() {
+        use crate::io::OutputEvent::{Press, Release};
+        use crate::pipeline::tests::run;
+
+        let yaml = "
+version: 2
+globals:
+  term: 300
+  hrm_term: 180
+keyboards:
+  Test:
+    layout: qwerty
+    layers: {}
+    mappings:
+      KEY_F: {tap: [KEY_F], hold: [KEY_LEFTCTRL]}
+";
+        let config = from_yaml(yaml).unwrap();
+        assert_eq!(config.globals.term, Some(300));
+        let (_, keyboard) = config.keyboard(None, "Test");
+        assert_eq!(keyboard.term, Some(300));
+        assert_eq!(keyboard.hrm_term, Some(180));
+
+        let held = [(0, KeyCode::KEY_F, 1), (250, KeyCode::KEY_F, 0)];
+        assert_eq!(
+            run(yaml, &held),
+            vec![Press(KeyCode::KEY_F), Release(KeyCode::KEY_F)]
+        );
+        // Released past its own term without holding, so neither fires
+        let own_term = yaml.replace("hold: [KEY_LEFTCTRL]}", "hold: [KEY_LEFTCTRL], term: 200}");
+        assert_eq!(run(&own_term, &held), vec![]);
+    }
+
+    #[test]
+    fn globals_hrm_term_by_hand_applies_to_each_hand() {
+        use crate::io::OutputEvent::{Press, Release};
+        use crate::pipeline::tests::run;
+
+        let yaml = "
+globals:
+  hrm_term: 200
+  hrm_term_left: 150
+  hrm_term_right: 300
+keyboards:
+  Test:
+    layout: qwerty
+    layers: {}
+    mappings:
+      KEY_F: {tap: [KEY_F], hold: [KEY_LEFTCTRL], hrm: true}
+      KEY_J: {tap: [KEY_J], hold: [KEY_RIGHTCTRL], hrm: true}
+";
+        let config = from_yaml(yaml).unwrap();
+        let (_, keyboard) = config.keyboard(None, "Test");
+        assert_eq!(keyboard.hrm_term_left, Some(150));
+        assert_eq!(keyboard.hrm_term_right, Some(300));
+
+        let held = |key| [(0, key, 1), (250, key, 0)];
+        assert_eq!(
+            run(yaml, &held(KeyCode::KEY_F)),
+            vec![Press(KeyCode::KEY_LEFTCTRL), Release(KeyCode::KEY_LEFTCTRL)]
+        );
+        assert_eq!(
+            run(yaml, &held(KeyCode::KEY_J)),
+            vec![Press(KeyCode::KEY_J), Release(KeyCode::KEY_J)]
+        );
+
+        // A keyboard's own term wins over the global ones
+        let own_term = yaml.replace("layers: {}", "layers: {}\n    hrm_term: 200");
+        let (_, keyboard) = from_yaml(&own_term).unwrap().keyboard(None, "Test");
+        assert_eq!(keyboard.hrm_term_left, None);
+        assert_eq!(
+            run(&own_term, &held(KeyCode::KEY_J)),
+            vec![
+                Press(KeyCode::KEY_RIGHTCTRL),
+                Release(KeyCode::KEY_RIGHTCTRL)
+            ]
+        );
+    }
+
+    #[test]
+    fn remap_reads_overlap_term_and_hrm() {
+        let remap: RemapAction = serde_yaml::from_str(
+            "{tap: [KEY_F], hold: [KEY_LEFTCTRL], overlap: false, term: 300, hrm: true, hrm_term: 180}",
+        )
+        .unwrap();
+        assert_eq!(remap.overlap, Some(false));
+        assert_eq!(remap.term, Some(300));
+        assert_eq!(remap.hrm, Some(true));
+        assert_eq!(remap.hrm_term, Some(180));
+    }
+
+    #[test]
+    fn default_mappings_leave_unset_options_out() {
+        let serialized = serde_yaml::to_string(&default_mappings()).unwrap();
+        for option in ["overlap", "term", "hrm", "null"] {
+            assert!(!serialized.contains(option), "{}", serialized);
+        }
+    }
+
+    #[test]
+    fn misspelt_mapping_options_are_rejected() {
+        let layer = "
+keyboards:
+  Test:
+    layers:
+      Navigation:
+        KEY_RIGHTALT:
+          KEY_J: {tapp: [KEY_X]}
+";
+        assert!(from_yaml(layer).is_err());
+        let mouse = layer.replace("{tapp: [KEY_X]}", "{move: {dx: -5, dy: 0}}");
+        assert!(from_yaml(&mouse).is_err());
+        let mapping = "
+keyboards:
+  Test:
+    mappings:
+      KEY_F: {tap: [KEY_F], hodl: [KEY_LEFTCTRL]}
+";
+        let error = format!("{:#}", from_yaml(mapping).unwrap_err());
+        assert!(error.contains("unknown field `hodl`"), "{}", error);
+    }
+
+    #[test]
+    fn layer_keys_take_key_lists_and_mappings() {
+        let config = from_yaml(
+            "
+keyboards:
+  Test:
+    layers:
+      Navigation:
+        KEY_RIGHTALT:
+          KEY_J: [KEY_DOWN]
+          KEY_K: {tap: [KEY_UP], hold: [KEY_LEFTCTRL]}
+          KEY_L: transparent
+",
+        )
+        .unwrap();
+        let (_, keyboard) = config.keyboard(None, "Test");
+        let keys = &keyboard.layers["Navigation"].triggers[&KeyCode::KEY_RIGHTALT];
+        assert!(matches!(keys[&KeyCode::KEY_J], LayerAction::Keys(_)));
+        assert!(matches!(keys[&KeyCode::KEY_K], LayerAction::Remap(_)));
+        assert!(matches!(keys[&KeyCode::KEY_L], LayerAction::Marker(_)));
+    }
 }
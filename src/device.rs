@@ -0,0 +1,138 @@
+use anyhow::Result;
+use evdev::{AttributeSet, Device, EventType, InputEvent, LedCode};
+use oxidekeys::config::Led;
+use oxidekeys::io::{EventSink, LedSink, ModHolds};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// The uinput device a keyboard's keys are sent through
+pub struct VirtualDevice(pub uinput::Device);
+
+impl EventSink for VirtualDevice {
+    fn write(&mut self, kind: i32, code: i32, value: i32) -> Result<()> {
+        Ok(self.0.write(kind, code, value)?)
+    }
+
+    fn synchronize(&mut self) -> Result<()> {
+        Ok(self.0.synchronize()?)
+    }
+}
+
+/// A virtual device the keyboards share, each write locks it. Modifiers are counted across the
+/// keyboards, so one releasing a modifier doesn't release it under another still holding it
+pub struct SharedDevice<S = VirtualDevice> {
+    device: Arc<Mutex<S>>,
+    mod_holds: ModHolds,
+}
+
+impl<S> SharedDevice<S> {
+    pub fn new(device: S) -> Self {
+        Self {
+            device: Arc::new(Mutex::new(device)),
+            mod_holds: ModHolds::default(),
+        }
+    }
+}
+
+impl<S> Clone for SharedDevice<S> {
+    fn clone(&self) -> Self {
+        Self {
+            device: self.device.clone(),
+            mod_holds: self.mod_holds.clone(),
+        }
+    }
+}
+
+impl<S: EventSink> EventSink for SharedDevice<S> {
+    fn write(&mut self, kind: i32, code: i32, value: i32) -> Result<()> {
+        self.device.lock().unwrap().write(kind, code, value)
+    }
+
+    fn synchronize(&mut self) -> Result<()> {
+        self.device.lock().unwrap().synchronize()
+    }
+
+    fn mod_holds(&self) -> Option<ModHolds> {
+        Some(self.mod_holds.clone())
+    }
+}
+
+/// LEDs of the physical keyboard, opened separately as the grabbed device is busy reading
+pub struct Leds {
+    device: Device,
+    original: AttributeSet<LedCode>,
+}
+
+impl Leds {
+    pub fn open(path: &Path) -> Result<Self> {
+        let device = Device::open(path)?;
+        let original = device.get_led_state()?;
+        Ok(Self { device, original })
+    }
+}
+
+impl LedSink for Leds {
+    fn set(&mut self, led: Led, on: bool) -> Result<()> {
+        self.device
+            .send_events(&[InputEvent::new(EventType::LED.0, led.code().0, on as i32)])?;
+        Ok(())
+    }
+
+    /// Puts the LEDs back to how they were when opened
+    fn restore(&mut self) -> Result<()> {
+        let events: Vec<InputEvent> = self
+            .device
+            .supported_leds()
+            .map(|leds| leds.iter().collect::<Vec<_>>())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|led| InputEvent::new(EventType::LED.0, led.0, self.original.contains(led) as i32))
+            .collect();
+        self.device.send_events(&events)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev::KeyCode;
+    use oxidekeys::features::{PRESS, RELEASE};
+    use oxidekeys::io::OutputEvent::{Press, Release};
+    use oxidekeys::io::{MockDevice, Output};
+    use oxidekeys::layouts;
+
+    fn output(device: impl EventSink + 'static) -> Output {
+        Output::new(Box::new(device), layouts::get(None).unwrap())
+    }
+
+    #[test]
+    fn shared_modifier_is_released_by_the_last_output_holding_it() {
+        let device = MockDevice::default();
+        let shared = SharedDevice::new(device.clone());
+        let mut first = output(shared.clone());
+        let mut second = output(shared);
+        let shift = KeyCode::KEY_LEFTSHIFT;
+
+        first.send_key(&shift, PRESS).unwrap();
+        first.send_key(&shift, PRESS).unwrap();
+        second.send_key(&shift, PRESS).unwrap();
+        first.release_all().unwrap();
+        second.force_release(&KeyCode::KEY_RIGHTSHIFT).unwrap();
+        first.force_release(&shift).unwrap();
+        assert_eq!(
+            device.events(),
+            [Press(shift), Release(KeyCode::KEY_RIGHTSHIFT)]
+        );
+
+        second.send_key(&shift, RELEASE).unwrap();
+        assert_eq!(
+            device.events(),
+            [
+                Press(shift),
+                Release(KeyCode::KEY_RIGHTSHIFT),
+                Release(shift),
+            ]
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use crate::features::{Context, Feature, Handled, KeyEvent, PRESS, RELEASE};
+use anyhow::Result;
+use evdev::KeyCode;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const SHIFT: KeyCode = KeyCode::KEY_LEFTSHIFT;
+
+/// Auto Shift
+/// - Tapping a letter or number types it as normal.
+/// - Holding it past the auto shift term types it shifted instead.
+/// - Keys with mappings, and keys pressed while a layer or shift is active, are left alone.
+#[derive(Default)]
+pub(crate) struct AutoShiftFeature {
+    pending: Option<(KeyCode, Instant)>,
+    /// Keys pressed on the output for each physical key still held
+    held: HashMap<KeyCode, Vec<KeyCode>>,
+}
+
+impl Feature for AutoShiftFeature {
+    fn on_event(&mut self, ctx: &mut Context, event: &KeyEvent) -> Result<Handled> {
+        let key = &event.key;
+
+        if let Some(emitted) = self.held.get(key) {
+            if event.state == RELEASE {
+                ctx.output.send_keys(emitted, RELEASE)?;
+                self.held.remove(key);
+            }
+            return Ok(Handled::Stop);
+        }
+
+        if self.pending.is_some_and(|(pending, _)| pending == *key) {
+            if event.state == RELEASE {
+                self.pending = None;
+                ctx.output.send_key(key, PRESS)?;
+                ctx.output.send_key(key, RELEASE)?;
+            }
+            return Ok(Handled::Stop);
+        }
+
+        if event.state != PRESS {
+            return Ok(Handled::No);
+        }
+
+        // Anything else pressed settles the pending key as unshifted, so rolls keep their order
+        self.resolve(ctx, false)?;
+
+        if !is_auto_shifted(ctx, key) {
+            return Ok(Handled::No);
+        }
+
+        let term = Duration::from_millis(ctx.config.auto_shift_term.unwrap_or(0) as u64);
+        self.pending = Some((*key, event.time + term));
+        Ok(Handled::Stop)
+    }
+
+    fn next_timer(&self) -> Option<Instant> {
+        self.pending.map(|(_, deadline)| deadline)
+    }
+
+    fn on_timer(&mut self, ctx: &mut Context, _now: Instant) -> Result<()> {
+        self.resolve(ctx, true)
+    }
+}
+
+impl AutoShiftFeature {
+    /// Presses the pending key, shifted or not, until it's released
+    fn resolve(&mut self, ctx: &mut Context, shifted: bool) -> Result<()> {
+        let Some((key, _)) = self.pending.take() else {
+            return Ok(());
+        };
+
+        let keys = if shifted { vec![SHIFT, key] } else { vec![key] };
+        ctx.output.send_keys(&keys, PRESS)?;
+        self.held.insert(key, keys);
+        Ok(())
+    }
+}
+
+fn is_auto_shifted(ctx: &Context, key: &KeyCode) -> bool {
+    if ctx.config.mappings.contains_key(key)
+        || !ctx.active_layers.is_empty()
+        || ctx.output.is_pressed(&KeyCode::KEY_LEFTSHIFT)
+        || ctx.output.is_pressed(&KeyCode::KEY_RIGHTSHIFT)
+    {
+        return false;
+    }
+
+    match &ctx.config.auto_shift_keys {
+        Some(keys) => keys.contains(key),
+        None => is_alphanumeric(key),
+    }
+}
+
+fn is_alphanumeric(key: &KeyCode) -> bool {
+    matches!(
+        *key,
+        KeyCode::KEY_A
+            | KeyCode::KEY_B
+            | KeyCode::KEY_C
+            | KeyCode::KEY_D
+            | KeyCode::KEY_E
+            | KeyCode::KEY_F
+            | KeyCode::KEY_G
+            | KeyCode::KEY_H
+            | KeyCode::KEY_I
+            | KeyCode::KEY_J
+            | KeyCode::KEY_K
+            | KeyCode::KEY_L
+            | KeyCode::KEY_M
+            | KeyCode::KEY_N
+            | KeyCode::KEY_O
+            | KeyCode::KEY_P
+            | KeyCode::KEY_Q
+            | KeyCode::KEY_R
+            | KeyCode::KEY_S
+            | KeyCode::KEY_T
+            | KeyCode::KEY_U
+            | KeyCode::KEY_V
+            | KeyCode::KEY_W
+            | KeyCode::KEY_X
+            | KeyCode::KEY_Y
+            | KeyCode::KEY_Z
+            | KeyCode::KEY_1
+            | KeyCode::KEY_2
+            | KeyCode::KEY_3
+            | KeyCode::KEY_4
+            | KeyCode::KEY_5
+            | KeyCode::KEY_6
+            | KeyCode::KEY_7
+            | KeyCode::KEY_8
+            | KeyCode::KEY_9
+            | KeyCode::KEY_0
+    )
+}
@@ -0,0 +1,123 @@
+use crate::features::{Context, Feature, Handled, KeyEvent, PRESS, RELEASE, is_modifier};
+use anyhow::Result;
+use evdev::KeyCode;
+
+const SHIFT: KeyCode = KeyCode::KEY_LEFTSHIFT;
+
+/// Caps Word
+/// - Pressing a `caps_word` key shifts letters until the current word ends.
+/// - Minus is shifted to an underscore, digits and backspace keep the word going.
+/// - Any other key ends the word.
+#[derive(Default)]
+pub(crate) struct CapsWordFeature {
+    active: bool,
+    shift_down: bool,
+}
+
+impl Feature for CapsWordFeature {
+    fn on_event(&mut self, ctx: &mut Context, event: &KeyEvent) -> Result<Handled> {
+        let key = &event.key;
+
+        if ctx
+            .config
+            .mappings
+            .get(key)
+            .is_some_and(|remap| remap.caps_word == Some(true))
+        {
+            if event.state == PRESS {
+                if self.active {
+                    self.deactivate(ctx)?;
+                } else {
+                    self.active = true;
+                }
+            }
+            return Ok(Handled::Stop);
+        }
+
+        if !self.active || event.state != PRESS || is_modifier(key) {
+            return Ok(Handled::No);
+        }
+
+        if is_shifted(key) {
+            if !self.shift_down {
+                ctx.output.send_key(&SHIFT, PRESS)?;
+                self.shift_down = true;
+            }
+        } else if is_word(key) {
+            self.release_shift(ctx)?;
+        } else {
+            self.deactivate(ctx)?;
+        }
+
+        Ok(Handled::No)
+    }
+
+    fn latched(&self) -> bool {
+        self.shift_down
+    }
+}
+
+impl CapsWordFeature {
+    fn deactivate(&mut self, ctx: &mut Context) -> Result<()> {
+        self.active = false;
+        self.release_shift(ctx)
+    }
+
+    fn release_shift(&mut self, ctx: &mut Context) -> Result<()> {
+        if self.shift_down {
+            ctx.output.send_key(&SHIFT, RELEASE)?;
+            self.shift_down = false;
+        }
+        Ok(())
+    }
+}
+
+fn is_shifted(key: &KeyCode) -> bool {
+    matches!(
+        *key,
+        KeyCode::KEY_A
+            | KeyCode::KEY_B
+            | KeyCode::KEY_C
+            | KeyCode::KEY_D
+            | KeyCode::KEY_E
+            | KeyCode::KEY_F
+            | KeyCode::KEY_G
+            | KeyCode::KEY_H
+            | KeyCode::KEY_I
+            | KeyCode::KEY_J
+            | KeyCode::KEY_K
+            | KeyCode::KEY_L
+            | KeyCode::KEY_M
+            | KeyCode::KEY_N
+            | KeyCode::KEY_O
+            | KeyCode::KEY_P
+            | KeyCode::KEY_Q
+            | KeyCode::KEY_R
+            | KeyCode::KEY_S
+            | KeyCode::KEY_T
+            | KeyCode::KEY_U
+            | KeyCode::KEY_V
+            | KeyCode::KEY_W
+            | KeyCode::KEY_X
+            | KeyCode::KEY_Y
+            | KeyCode::KEY_Z
+            | KeyCode::KEY_MINUS
+    )
+}
+
+fn is_word(key: &KeyCode) -> bool {
+    matches!(
+        *key,
+        KeyCode::KEY_1
+            | KeyCode::KEY_2
+            | KeyCode::KEY_3
+            | KeyCode::KEY_4
+            | KeyCode::KEY_5
+            | KeyCode::KEY_6
+            | KeyCode::KEY_7
+            | KeyCode::KEY_8
+            | KeyCode::KEY_9
+            | KeyCode::KEY_0
+            | KeyCode::KEY_BACKSPACE
+    )
+}
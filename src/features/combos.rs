@@ -0,0 +1,402 @@
+use crate::config::Combo;
+use crate::features::{Context, Feature, Handled, KeyEvent, PRESS, RELEASE, REPEAT};
+use anyhow::Result;
+use evdev::KeyCode;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+struct ActiveCombo {
+    /// Keys in the order they were pressed
+    keys: Vec<KeyCode>,
+    keys_down: Vec<KeyCode>,
+    output: Option<Vec<KeyCode>>,
+    text: Option<String>,
+    /// When the first of its keys was released
+    released: Option<Instant>,
+}
+
+/// Combos
+/// - Pressing all keys of a combo within the combo term emits its output until one is released.
+/// - The longest combo matching the pressed keys wins.
+/// - Keys that don't complete a combo are released back as normal presses in the order they
+///   were pressed, once the combo term expires, one is released or another key breaks in.
+/// - When a key can't complete a combo with all the buffered keys, only the earliest ones are
+///   released back, so the later ones can still complete another combo sharing them.
+/// - A combo with `text` types it once all its keys are released, as long as they were released
+///   within the combo term of each other, otherwise its keys are typed one by one instead.
+#[derive(Default)]
+pub(crate) struct CombosFeature {
+    buffer: Vec<KeyEvent>,
+    deadline: Option<Instant>,
+    active: Vec<ActiveCombo>,
+}
+
+impl Feature for CombosFeature {
+    fn on_event(&mut self, ctx: &mut Context, event: &KeyEvent) -> Result<Handled> {
+        let key = &event.key;
+        let state = event.state;
+
+        if let Some(index) = self.active_index(key) {
+            if state == RELEASE {
+                self.release_active(ctx, index, event)?;
+            }
+            return Ok(Handled::Stop);
+        }
+
+        let in_combo = ctx
+            .config
+            .combos
+            .iter()
+            .any(|combo| combo.keys.contains(key));
+
+        match state {
+            PRESS if in_combo => {
+                let keys = self.buffered_keys();
+                if !keys.is_empty() && candidates(&ctx.config.combos, &keys, key) == 0 {
+                    let keep_from = (1..keys.len())
+                        .find(|start| candidates(&ctx.config.combos, &keys[*start..], key) > 0)
+                        .unwrap_or(keys.len());
+                    self.resolve_first(ctx, keep_from)?;
+                }
+
+                if self.buffer.is_empty() {
+                    let combo_term = ctx.config.combo_term.unwrap_or(0) as u64;
+                    self.deadline = Some(event.time + Duration::from_millis(combo_term));
+                }
+                self.buffer.push(*event);
+
+                // Nothing longer can still complete, so there's no need to wait out the term
+                let buffer = &self.buffered_keys();
+                let exact = ctx
+                    .config
+                    .combos
+                    .iter()
+                    .any(|combo| is_match(combo, buffer));
+                let longer =
+                    ctx.config.combos.iter().any(|combo| {
+                        combo.keys.len() > buffer.len() && is_subset(buffer, &combo.keys)
+                    });
+                if exact && !longer {
+                    if ctx.replay.is_empty() {
+                        self.resolve(ctx)?;
+                    } else {
+                        // Keys just released back reach the output after this returns, the
+                        // combo fires on the timer straight after so it follows them
+                        self.deadline = Some(event.time);
+                    }
+                }
+
+                Ok(Handled::Stop)
+            }
+            PRESS => {
+                self.resolve(ctx)?;
+                Ok(Handled::No)
+            }
+            RELEASE if self.buffered_keys().contains(key) => {
+                self.resolve(ctx)?;
+                // Released before a longer combo could complete, the shorter one it fired
+                // ends with it
+                if let Some(index) = self.active_index(key) {
+                    self.release_active(ctx, index, event)?;
+                    return Ok(Handled::Stop);
+                }
+                Ok(Handled::No)
+            }
+            REPEAT if self.buffered_keys().contains(key) => Ok(Handled::Stop),
+            _ => Ok(Handled::No),
+        }
+    }
+
+    fn next_timer(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    fn on_timer(&mut self, ctx: &mut Context, _now: Instant) -> Result<()> {
+        self.resolve(ctx)
+    }
+}
+
+impl CombosFeature {
+    fn active_index(&self, key: &KeyCode) -> Option<usize> {
+        self.active
+            .iter()
+            .position(|combo| combo.keys_down.contains(key))
+    }
+
+    /// Releases one key of an active combo, its output is released with the first of them
+    fn release_active(&mut self, ctx: &mut Context, index: usize, event: &KeyEvent) -> Result<()> {
+        let combo = &mut self.active[index];
+        combo.keys_down.retain(|k| k != &event.key);
+        if let Some(output) = combo.output.take() {
+            ctx.output.send_keys(&output, RELEASE)?;
+        }
+        let released = *combo.released.get_or_insert(event.time);
+        if combo.keys_down.is_empty() {
+            let combo = self.active.remove(index);
+            if let Some(text) = combo.text {
+                let combo_term = ctx.config.combo_term.unwrap_or(0) as u64;
+                if event.time.duration_since(released) <= Duration::from_millis(combo_term) {
+                    ctx.output.send_text(&text)?;
+                } else {
+                    for key in combo.keys {
+                        ctx.output.send_key(&key, PRESS)?;
+                        ctx.output.send_key(&key, RELEASE)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn buffered_keys(&self) -> Vec<KeyCode> {
+        self.buffer.iter().map(|event| event.key).collect()
+    }
+
+    /// Fires the longest combo within the buffered keys and replays the rest as normal presses
+    fn resolve(&mut self, ctx: &mut Context) -> Result<()> {
+        self.resolve_first(ctx, self.buffer.len())
+    }
+
+    /// Resolves the earliest `count` buffered keys, the rest stay buffered with their term
+    /// counting from the earliest of them
+    fn resolve_first(&mut self, ctx: &mut Context, count: usize) -> Result<()> {
+        let buffer: Vec<KeyEvent> = self.buffer.drain(..count).collect();
+        self.deadline = self.buffer.first().map(|first| {
+            first.time + Duration::from_millis(ctx.config.combo_term.unwrap_or(0) as u64)
+        });
+        if buffer.is_empty() {
+            return Ok(());
+        }
+
+        let keys: Vec<KeyCode> = buffer.iter().map(|event| event.key).collect();
+        let combo = ctx
+            .config
+            .combos
+            .iter()
+            .filter(|combo| !combo.keys.is_empty() && is_subset(&combo.keys, &keys))
+            .max_by_key(|combo| combo.keys.len());
+
+        if let Some(combo) = combo {
+            if combo.text.is_none() {
+                ctx.output.send_keys(&combo.output, PRESS)?;
+            }
+            self.active.push(ActiveCombo {
+                keys: keys
+                    .iter()
+                    .filter(|key| combo.keys.contains(key))
+                    .copied()
+                    .collect(),
+                keys_down: combo.keys.clone(),
+                output: combo.text.is_none().then(|| combo.output.clone()),
+                text: combo.text.clone(),
+                released: None,
+            });
+        }
+
+        for event in buffer {
+            if combo.is_none_or(|combo| !combo.keys.contains(&event.key)) {
+                ctx.replay.push(event);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Number of combos that could still complete with the buffered keys plus `key`
+fn candidates(combos: &[Combo], buffer: &[KeyCode], key: &KeyCode) -> usize {
+    combos
+        .iter()
+        .filter(|combo| combo.keys.contains(key) && is_subset(buffer, &combo.keys))
+        .count()
+}
+
+fn is_match(combo: &Combo, keys: &[KeyCode]) -> bool {
+    combo.keys.len() == keys.len() && is_subset(keys, &combo.keys)
+}
+
+fn is_subset(keys: &[KeyCode], of: &[KeyCode]) -> bool {
+    keys.iter().all(|key| of.contains(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::features::{PRESS, RELEASE};
+    use crate::io::OutputEvent::{Press, Release};
+    use crate::pipeline::tests::run;
+    use evdev::KeyCode as K;
+
+    const CONFIG: &str = "
+keyboards:
+  Test:
+    layout: qwerty
+    mappings: {}
+    layers: {}
+    combo_term: 50
+    combos:
+      - keys: [KEY_J, KEY_K]
+        output: [KEY_ESC]
+      - keys: [KEY_J, KEY_K, KEY_L]
+        output: [KEY_ENTER]
+      - keys: [KEY_K, KEY_L]
+        text: ok
+";
+
+    #[test]
+    fn near_simultaneous_presses_emit_the_combo() {
+        let output = run(
+            CONFIG,
+            &[
+                (0, K::KEY_J, PRESS),
+                (20, K::KEY_K, PRESS),
+                (100, K::KEY_J, RELEASE),
+                (110, K::KEY_K, RELEASE),
+            ],
+        );
+        assert_eq!(output, [Press(K::KEY_ESC), Release(K::KEY_ESC)]);
+    }
+
+    #[test]
+    fn too_slow_presses_pass_through_in_order() {
+        let output = run(
+            CONFIG,
+            &[
+                (0, K::KEY_J, PRESS),
+                (80, K::KEY_K, PRESS),
+                (200, K::KEY_J, RELEASE),
+                (210, K::KEY_K, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_J),
+                Press(K::KEY_K),
+                Release(K::KEY_J),
+                Release(K::KEY_K),
+            ]
+        );
+    }
+
+    #[test]
+    fn longest_matching_combo_wins() {
+        let output = run(
+            CONFIG,
+            &[
+                (0, K::KEY_J, PRESS),
+                (10, K::KEY_K, PRESS),
+                (20, K::KEY_L, PRESS),
+                (100, K::KEY_J, RELEASE),
+                (110, K::KEY_K, RELEASE),
+                (120, K::KEY_L, RELEASE),
+            ],
+        );
+        assert_eq!(output, [Press(K::KEY_ENTER), Release(K::KEY_ENTER)]);
+    }
+
+    #[test]
+    fn early_release_while_a_longer_combo_is_possible_ends_the_shorter_one() {
+        let output = run(
+            CONFIG,
+            &[
+                (0, K::KEY_J, PRESS),
+                (10, K::KEY_K, PRESS),
+                (30, K::KEY_J, RELEASE),
+                (40, K::KEY_K, RELEASE),
+                (200, K::KEY_J, PRESS),
+                (210, K::KEY_J, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_ESC),
+                Release(K::KEY_ESC),
+                Press(K::KEY_J),
+                Release(K::KEY_J),
+            ]
+        );
+    }
+
+    #[test]
+    fn text_combo_released_together_types_its_text() {
+        let output = run(
+            CONFIG,
+            &[
+                (0, K::KEY_K, PRESS),
+                (10, K::KEY_L, PRESS),
+                (100, K::KEY_K, RELEASE),
+                (120, K::KEY_L, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_O),
+                Release(K::KEY_O),
+                Press(K::KEY_K),
+                Release(K::KEY_K),
+            ]
+        );
+    }
+
+    #[test]
+    fn text_combo_released_slowly_types_its_keys() {
+        let output = run(
+            CONFIG,
+            &[
+                (0, K::KEY_K, PRESS),
+                (10, K::KEY_L, PRESS),
+                (100, K::KEY_K, RELEASE),
+                (200, K::KEY_L, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_K),
+                Release(K::KEY_K),
+                Press(K::KEY_L),
+                Release(K::KEY_L),
+            ]
+        );
+    }
+
+    #[test]
+    fn longest_combo_wins_over_a_shorter_text_combo() {
+        let output = run(
+            CONFIG,
+            &[
+                (0, K::KEY_K, PRESS),
+                (10, K::KEY_L, PRESS),
+                (20, K::KEY_J, PRESS),
+                (100, K::KEY_K, RELEASE),
+                (110, K::KEY_L, RELEASE),
+                (120, K::KEY_J, RELEASE),
+            ],
+        );
+        assert_eq!(output, [Press(K::KEY_ENTER), Release(K::KEY_ENTER)]);
+    }
+
+    #[test]
+    fn partial_text_combo_flushes_as_plain_keys() {
+        let output = run(
+            CONFIG,
+            &[
+                (0, K::KEY_L, PRESS),
+                (20, K::KEY_A, PRESS),
+                (100, K::KEY_L, RELEASE),
+                (110, K::KEY_A, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_L),
+                Press(K::KEY_A),
+                Release(K::KEY_L),
+                Release(K::KEY_A),
+            ]
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use crate::features::{Context, Feature, Handled, KeyEvent, PRESS, RELEASE, Stats, is_modifier};
+use anyhow::Result;
+use colored::Colorize;
+use evdev::KeyCode;
+use log::debug;
+use std::collections::HashSet;
+
+const ACUTE: &[(char, char)] = &[
+    ('a', 'á'),
+    ('c', 'ć'),
+    ('e', 'é'),
+    ('i', 'í'),
+    ('n', 'ń'),
+    ('o', 'ó'),
+    ('s', 'ś'),
+    ('u', 'ú'),
+    ('y', 'ý'),
+    ('z', 'ź'),
+];
+const GRAVE: &[(char, char)] = &[('a', 'à'), ('e', 'è'), ('i', 'ì'), ('o', 'ò'), ('u', 'ù')];
+const CIRCUMFLEX: &[(char, char)] = &[('a', 'â'), ('e', 'ê'), ('i', 'î'), ('o', 'ô'), ('u', 'û')];
+const DIAERESIS: &[(char, char)] = &[
+    ('a', 'ä'),
+    ('e', 'ë'),
+    ('i', 'ï'),
+    ('o', 'ö'),
+    ('u', 'ü'),
+    ('y', 'ÿ'),
+];
+const TILDE: &[(char, char)] = &[('a', 'ã'), ('n', 'ñ'), ('o', 'õ')];
+const CEDILLA: &[(char, char)] = &[('c', 'ç'), ('s', 'ş')];
+const CARON: &[(char, char)] = &[
+    ('c', 'č'),
+    ('e', 'ě'),
+    ('n', 'ň'),
+    ('r', 'ř'),
+    ('s', 'š'),
+    ('z', 'ž'),
+];
+const RING: &[(char, char)] = &[('a', 'å'), ('u', 'ů')];
+
+/// Built-in compose table of a dead key, by its literal character
+fn builtin(dead: char) -> &'static [(char, char)] {
+    match dead {
+        '´' | '\'' => ACUTE,
+        '`' => GRAVE,
+        '^' => CIRCUMFLEX,
+        '¨' | '"' => DIAERESIS,
+        '~' => TILDE,
+        '¸' => CEDILLA,
+        'ˇ' => CARON,
+        '˚' => RING,
+        _ => &[],
+    }
+}
+
+/// Compose
+/// - Tapping a `dead` key arms its diacritic, the next letter types the precomposed character
+///   through the keyboard's unicode input.
+/// - The keyboard's `compose` table is checked first, then the built-in one, which also
+///   covers the uppercase letters.
+/// - Any other key types the dead key's literal before itself, space types only the literal.
+#[derive(Default)]
+pub(crate) struct ComposeFeature {
+    pending: Option<char>,
+    /// Keys composed on press, their release is swallowed
+    composed: HashSet<KeyCode>,
+    compositions: u64,
+}
+
+impl Feature for ComposeFeature {
+    fn on_event(&mut self, ctx: &mut Context, event: &KeyEvent) -> Result<Handled> {
+        let key = &event.key;
+
+        if let Some(dead) = ctx.config.mappings.get(key).and_then(|remap| remap.dead) {
+            if event.state == PRESS {
+                // Tapping a dead key twice types its literal
+                if let Some(pending) = self.pending.take() {
+                    type_char(ctx, pending)?;
+                } else {
+                    debug!("{}: {}", "DEAD".purple(), dead);
+                    self.pending = Some(dead);
+                }
+            }
+            return Ok(Handled::Stop);
+        }
+
+        if self.composed.contains(key) {
+            if event.state == RELEASE {
+                self.composed.remove(key);
+            }
+            return Ok(Handled::Stop);
+        }
+
+        if event.state != PRESS || is_modifier(key) {
+            return Ok(Handled::No);
+        }
+        let Some(dead) = self.pending.take() else {
+            return Ok(Handled::No);
+        };
+
+        let shift = ctx.output.is_pressed(&KeyCode::KEY_LEFTSHIFT)
+            || ctx.output.is_pressed(&KeyCode::KEY_RIGHTSHIFT);
+        if let Some(composed) = compose(ctx, dead, key, shift) {
+            debug!("{}: {}", "COMPOSED".purple(), composed);
+            type_char(ctx, composed)?;
+            self.compositions += 1;
+            self.composed.insert(*key);
+            return Ok(Handled::Stop);
+        }
+
+        type_char(ctx, dead)?;
+        if *key == KeyCode::KEY_SPACE {
+            self.composed.insert(*key);
+            return Ok(Handled::Stop);
+        }
+        Ok(Handled::No)
+    }
+
+    fn stats(&self) -> Stats {
+        Stats::from([("compositions", self.compositions)])
+    }
+}
+
+/// The precomposed character of a dead key and the key typed after it
+fn compose(ctx: &Context, dead: char, key: &KeyCode, shift: bool) -> Option<char> {
+    let layout = ctx.output.layout();
+    let typed = |c: char| layout.char_key(c) == Some((*key, shift));
+
+    if let Some(composed) = ctx
+        .config
+        .compose
+        .get(&dead)
+        .and_then(|table| table.iter().find(|(base, _)| typed(**base)))
+        .map(|(_, composed)| *composed)
+    {
+        return Some(composed);
+    }
+
+    builtin(dead).iter().find_map(|(base, composed)| {
+        if typed(*base) {
+            Some(*composed)
+        } else if typed(upper(*base)) {
+            Some(upper(*composed))
+        } else {
+            None
+        }
+    })
+}
+
+fn upper(c: char) -> char {
+    c.to_uppercase().next().unwrap_or(c)
+}
+
+/// Types a character with the layout if it can, otherwise through unicode input, with the
+/// modifiers held lifted so they don't change it
+fn type_char(ctx: &mut Context, c: char) -> Result<()> {
+    let held: Vec<KeyCode> = ctx
+        .output
+        .pressed()
+        .into_iter()
+        .filter(is_modifier)
+        .collect();
+    if !held.is_empty() {
+        ctx.output.send_keys(&held, RELEASE)?;
+    }
+    if ctx.output.layout().char_key(c).is_some() {
+        ctx.output.send_text(&c.to_string())?;
+    } else {
+        ctx.output.send_unicode(
+            &format!("{:x}", c as u32),
+            ctx.config.unicode_prefix.as_deref().unwrap_or_default(),
+            ctx.config.unicode_suffix.as_deref().unwrap_or_default(),
+        )?;
+    }
+    if !held.is_empty() {
+        ctx.output.send_keys(&held, PRESS)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,1298 @@
+use crate::config::{Hand, LayerAction, LayoutConfig, Morph, RemapAction, TapHoldMode};
+use crate::features::{
+    Context, Feature, Handled, KeyEvent, PRESS, RELEASE, REPEAT, Stats, is_layer_key, is_modifier,
+    key_hand, layer_action, layer_mods,
+};
+use crate::io::Output;
+use crate::layouts::{self, Layout};
+use anyhow::Result;
+use evdev::KeyCode;
+use log::{debug, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+struct DoubleTapState {
+    last_tap_time: Option<Instant>,
+    tap_count: u32,
+}
+
+/// Fewest milliseconds between autofire taps, so the virtual device isn't flooded
+const MIN_AUTOFIRE_INTERVAL: u16 = 20;
+
+struct Autofiring {
+    key: KeyCode,
+    interval: Duration,
+    next: Instant,
+}
+
+#[derive(Debug, Clone)]
+struct RepeatState {
+    repeat_keys: Vec<KeyCode>,
+}
+
+/// Dual Function with Double-Tap Repeat
+/// - A `chord` is held down with the key from press to release.
+/// - An `autofire` key is tapped on press and every interval after until released, no more
+///   often than every 20 ms.
+/// - A key without a hold or `term` has nothing to decide, its tap is held down while it's held.
+/// - If you press and release a key without overlapping another, Tap fires.
+/// - If you press the key and while it's held another key overlaps, Hold fires.
+/// - With `permissive_hold`, the overlapping key also has to be released while held for Hold to
+///   fire, otherwise it's held back and follows the Tap.
+/// - Tap fires a morph instead when all of its modifiers are held.
+/// - If you hold the key alone past its hold term, Hold fires.
+/// - A home row mod pressed within the streak term of the last key released always taps.
+/// - If you release the key after its `term` without Hold firing, neither fires, or its
+///   `long_tap` does. Without a hold, a key pressed while it's held settles it as Tap.
+/// - With `overlap: false`, overlapping keys don't fire Hold.
+/// - With bilateral combinations, an overlapping key on the same hand resolves to Tap instead.
+/// - With `term_by_key` or `term_by_hand`, an overlapping key only fires Hold once the key has
+///   been held for the term set for it, otherwise it resolves to Tap.
+/// - With `hold_after_term`, the same goes for the key's hold term, counted from its press.
+/// - With `ignore_interrupt`, overlapping keys never fire Hold, only holding the key past its
+///   hold term does. They're held back until then and follow whichever fires.
+/// - If you tap a key and press it again within the double tap timeout, the tap is held so the
+///   OS autorepeats it until released, or with `quick_hold` the hold fires straight away.
+/// - With `force_hold`, pressing again after a tap decides between tap and hold like any press.
+/// - With `hold_layout`, Hold is sent in that layout rather than the keyboard's.
+/// - With `eager`, Tap fires on press and doesn't fire again on release, Hold still fires if
+///   decided after.
+/// - A key a layer remaps acts as any other key, its own mapping is left out, unless the layer
+///   gives it a mapping, which it takes instead.
+#[derive(Default)]
+pub(crate) struct DualFunctionFeature {
+    double_tap_states: HashMap<KeyCode, DoubleTapState>,
+    repeat_states: HashMap<KeyCode, RepeatState>,
+    /// Keys held back while a permissive hold, or one ignoring interrupts, is undecided
+    buffered: Vec<KeyEvent>,
+    /// Keys already tapped by a roll, ignored until released
+    rolled: HashSet<KeyCode>,
+    /// When keys held alone turn into holds, for keys with a hold term
+    deadlines: HashMap<KeyCode, Instant>,
+    /// When the last non-modifier key was released, for typing streaks
+    last_release: Option<Instant>,
+    /// Home row mods pressed mid typing streak, acting as their tap until released
+    streak_taps: HashSet<KeyCode>,
+    /// Keys without a hold, and the tap or chord keys they hold down until released
+    held_taps: HashMap<KeyCode, Vec<KeyCode>>,
+    /// Autofire keys held, with the key they tap and when next
+    autofiring: HashMap<KeyCode, Autofiring>,
+    /// Eager keys whose tap was sent when pressed
+    eager_taps: HashSet<KeyCode>,
+    /// Keys a layer remapped when pressed, their own mapping sits out until they're released
+    layered: HashSet<KeyCode>,
+    /// Layouts loaded for `hold_layout`, so custom ones aren't read on every hold
+    hold_layouts: HashMap<LayoutConfig, Box<dyn Layout>>,
+    exec_enabled: bool,
+    taps: u64,
+    holds: u64,
+    /// Holds fired by another key overlapping
+    overlaps: u64,
+}
+
+impl DualFunctionFeature {
+    pub(crate) fn new(exec_enabled: bool) -> Self {
+        Self {
+            exec_enabled,
+            ..Default::default()
+        }
+    }
+
+    /// Presses the hold keys of the pending mapped keys using the given mode. With bilateral
+    /// combinations a key on the same hand as the trigger taps instead, as does a key held for
+    /// less than its overlap term for the trigger
+    fn trigger_holds(
+        &mut self,
+        ctx: &mut Context,
+        mode: TapHoldMode,
+        trigger: &KeyCode,
+        now: Instant,
+    ) -> Result<()> {
+        let origins: Vec<KeyCode> = pending_holds(ctx, mode).collect();
+        let trigger_hand = key_hand(ctx, trigger);
+
+        for origin in origins {
+            let Some(remap) = ctx.config.mappings.get(&origin) else {
+                continue;
+            };
+
+            let same_hand = remap.hrm == Some(true)
+                && ctx.config.bilateral_combinations.unwrap_or(false)
+                && trigger_hand.is_some()
+                && key_hand(ctx, &origin) == trigger_hand;
+            let too_short = overlap_term(ctx, remap, &origin, trigger).is_some_and(|term| {
+                self.double_tap_states
+                    .get(&origin)
+                    .and_then(|double_tap_state| double_tap_state.last_tap_time)
+                    .is_some_and(|pressed| now.duration_since(pressed).as_millis() < term as u128)
+            });
+            if same_hand || too_short {
+                if !self.eager_taps.remove(&origin)
+                    && let Some(tap_keys) = &remap.tap
+                {
+                    ctx.output.send_keys(tap_keys, PRESS)?;
+                    ctx.output.send_keys(tap_keys, RELEASE)?;
+                }
+                ctx.keys_down.remove(&origin);
+                self.rolled.insert(origin);
+                self.taps += 1;
+                continue;
+            }
+
+            press_hold(ctx, &mut self.hold_layouts, &origin)?;
+
+            ctx.holds_triggered.insert(origin);
+            self.holds += 1;
+            self.overlaps += 1;
+        }
+        Ok(())
+    }
+
+    /// Taps the keys held that only decide between tap and long tap, as another key pressed
+    /// settles them as taps so rolls keep their order
+    fn tap_long_taps(&mut self, ctx: &mut Context) -> Result<()> {
+        let pending: Vec<KeyCode> = ctx
+            .keys_down
+            .iter()
+            .filter(|origin| {
+                !ctx.holds_triggered.contains(origin)
+                    && !self.repeat_states.contains_key(origin)
+                    && ctx
+                        .config
+                        .mappings
+                        .get(origin)
+                        .is_some_and(|remap| remap.hold.is_none() && remap.long_tap.is_some())
+            })
+            .copied()
+            .collect();
+
+        for origin in pending {
+            let Some(remap) = ctx.config.mappings.get(&origin).cloned() else {
+                continue;
+            };
+            self.tap(ctx, &remap)?;
+            ctx.keys_down.remove(&origin);
+            self.deadlines.remove(&origin);
+            self.rolled.insert(origin);
+        }
+        Ok(())
+    }
+
+    /// Taps a mapping, its morph if the modifiers are held, and runs the rest of its tap actions
+    fn tap(&mut self, ctx: &mut Context, remap: &RemapAction) -> Result<()> {
+        self.taps += 1;
+        if let Some(morph) = held_morph(&ctx.output, remap) {
+            send_morph(&mut ctx.output, morph)?;
+        } else if let Some(tap_keys) = &remap.tap {
+            ctx.output.send_keys(tap_keys, PRESS)?;
+            ctx.output.send_keys(tap_keys, RELEASE)?;
+        }
+        self.tap_actions(ctx, remap)
+    }
+
+    /// Types a mapping's text, runs its steps and switches what it switches when tapped
+    fn tap_actions(&self, ctx: &mut Context, remap: &RemapAction) -> Result<()> {
+        if let Some(text) = &remap.text {
+            ctx.output.send_text(text)?;
+        }
+
+        if let Some(steps) = &remap.steps {
+            ctx.output.send_steps(steps)?;
+        }
+
+        if let Some(unicode) = &remap.unicode {
+            ctx.output.send_unicode(
+                unicode,
+                ctx.config.unicode_prefix.as_deref().unwrap_or_default(),
+                ctx.config.unicode_suffix.as_deref().unwrap_or_default(),
+            )?;
+        }
+
+        if let Some(layout) = &remap.switch_layout {
+            switch_layout(&mut ctx.output, layout);
+        }
+
+        if let Some(profile) = &remap.profile {
+            ctx.profile = Some(profile.clone());
+        }
+
+        if remap.reload == Some(true) {
+            ctx.reload = true;
+        }
+
+        if let Some(command) = &remap.exec {
+            if self.exec_enabled {
+                exec(command);
+            } else {
+                debug!("Exec disabled, ignoring: {}", command);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Feature for DualFunctionFeature {
+    fn on_event(&mut self, ctx: &mut Context, event: &KeyEvent) -> Result<Handled> {
+        let key = &event.key;
+        let state = event.state;
+
+        let in_streak = self.last_release.is_some_and(|released| {
+            ctx.config.streak_term.is_some_and(|streak_term| {
+                event.time.duration_since(released).as_millis() <= streak_term as u128
+            })
+        });
+        if state == RELEASE && !is_modifier(key) {
+            self.last_release = Some(event.time);
+        }
+
+        // Held on a layer, the key is whatever the layer made it and autorepeats as such
+        let layered = match state {
+            PRESS => {
+                // A layer's mapping for the key is its mapping here
+                let layered = match layer_action(&ctx.config, &ctx.active_layers, key) {
+                    Some(LayerAction::Remap(_)) => false,
+                    Some(_) => true,
+                    None => !layer_mods(&ctx.config, &ctx.active_layers, key).is_empty(),
+                };
+                if layered {
+                    self.layered.insert(*key);
+                }
+                layered
+            }
+            RELEASE => self.layered.remove(key),
+            _ => self.layered.contains(key),
+        };
+
+        if state == PRESS {
+            self.tap_long_taps(ctx)?;
+        }
+
+        if let Some(remap) = ctx
+            .config
+            .mappings
+            .get(key)
+            .filter(|remap| remap.taps.is_none() && !layered)
+        {
+            if let Some(tap_keys) = self.held_taps.get(key) {
+                match state {
+                    RELEASE => {
+                        if let Some(tap_keys) = self.held_taps.remove(key) {
+                            ctx.output.send_keys(&tap_keys, RELEASE)?;
+                        }
+                    }
+                    _ => {
+                        let tap_keys: Vec<KeyCode> = tap_keys
+                            .iter()
+                            .filter(|k| !is_modifier(k))
+                            .copied()
+                            .collect();
+                        ctx.output.send_keys(&tap_keys, state)?;
+                    }
+                }
+                return Ok(Handled::Yes);
+            }
+
+            if let Some(autofire) = remap.autofire {
+                match state {
+                    PRESS => {
+                        ctx.output.send_key(&autofire.key, PRESS)?;
+                        ctx.output.send_key(&autofire.key, RELEASE)?;
+                        let interval = Duration::from_millis(
+                            autofire.interval_ms.max(MIN_AUTOFIRE_INTERVAL) as u64,
+                        );
+                        self.autofiring.insert(
+                            *key,
+                            Autofiring {
+                                key: autofire.key,
+                                interval,
+                                next: event.time + interval,
+                            },
+                        );
+                    }
+                    RELEASE => {
+                        self.autofiring.remove(key);
+                    }
+                    _ => {}
+                }
+                return Ok(Handled::Yes);
+            }
+
+            if state == PRESS
+                && let Some(chord) = &remap.chord
+            {
+                ctx.output.send_keys(chord, PRESS)?;
+                self.held_taps.insert(*key, chord.clone());
+                return Ok(Handled::Yes);
+            }
+
+            // Without a hold there's nothing to wait for, unless a pending hold is deciding whether
+            // the key is held back
+            if state == PRESS
+                && remap.hold.is_none()
+                && remap.term.is_none()
+                && remap.long_tap.is_none()
+                && !holding_back(ctx)
+            {
+                let remap = remap.clone();
+                if !ctx.keys_down.is_empty() {
+                    self.trigger_holds(ctx, TapHoldMode::HoldOnOtherKeyPress, key, event.time)?;
+                }
+                let tap_keys = if let Some(morph) = held_morph(&ctx.output, &remap) {
+                    send_morph(&mut ctx.output, morph)?;
+                    Vec::new()
+                } else {
+                    let tap_keys = remap.tap.clone().unwrap_or_default();
+                    ctx.output.send_keys(&tap_keys, PRESS)?;
+                    tap_keys
+                };
+                self.held_taps.insert(*key, tap_keys);
+                self.taps += 1;
+                self.tap_actions(ctx, &remap)?;
+                return Ok(Handled::Yes);
+            }
+
+            if state == RELEASE && self.streak_taps.remove(key) {
+                if let Some(tap_keys) = &remap.tap {
+                    ctx.output.send_keys(tap_keys, RELEASE)?;
+                }
+                return Ok(Handled::Yes);
+            }
+
+            // Mid typing streak a home row mod is a plain key, pressed straight away so rolls
+            // keep their order
+            if state == PRESS && in_streak && remap.hrm == Some(true) {
+                if let Some(tap_keys) = &remap.tap {
+                    ctx.output.send_keys(tap_keys, PRESS)?;
+                }
+                self.streak_taps.insert(*key);
+                self.taps += 1;
+                return Ok(Handled::Yes);
+            }
+
+            match state {
+                PRESS => {
+                    ctx.keys_down.insert(*key);
+
+                    let non_layer_modifier_keys_down = ctx
+                        .keys_down
+                        .iter()
+                        .filter(|k| !is_layer_key(k, &ctx.config) && !is_modifier(k))
+                        .count();
+                    let overlap_now =
+                        remap.overlap.unwrap_or(true) && non_layer_modifier_keys_down > 1;
+                    if overlap_now {
+                        ctx.holds_triggered.insert(*key);
+                        self.holds += 1;
+                        self.overlaps += 1;
+
+                        press_hold(ctx, &mut self.hold_layouts, key)?;
+                    } else {
+                        let now = event.time;
+                        let double_tap_state =
+                            self.double_tap_states
+                                .entry(*key)
+                                .or_insert(DoubleTapState {
+                                    last_tap_time: None,
+                                    tap_count: 0,
+                                });
+
+                        if let Some(last_tap) = double_tap_state.last_tap_time
+                            && let Some(double_tap_timeout) = ctx.config.double_tap_timeout
+                            && remap.force_hold != Some(true)
+                        {
+                            if now.duration_since(last_tap).as_millis()
+                                <= double_tap_timeout as u128
+                            {
+                                double_tap_state.tap_count += 1;
+
+                                if remap.quick_hold == Some(true) {
+                                    press_hold(ctx, &mut self.hold_layouts, key)?;
+                                    ctx.holds_triggered.insert(*key);
+                                    self.holds += 1;
+                                } else if let Some(tap_keys) = &remap.tap {
+                                    self.repeat_states.remove(key);
+
+                                    let repeat_state = RepeatState {
+                                        repeat_keys: tap_keys.clone(),
+                                    };
+                                    self.repeat_states.insert(*key, repeat_state);
+
+                                    ctx.output.send_keys(tap_keys, PRESS)?;
+                                }
+                            } else {
+                                double_tap_state.tap_count = 1;
+                                self.repeat_states.remove(key);
+                            }
+                        } else {
+                            double_tap_state.tap_count = 1;
+                        }
+
+                        double_tap_state.last_tap_time = Some(now);
+
+                        if !self.repeat_states.contains_key(key)
+                            && !ctx.holds_triggered.contains(key)
+                        {
+                            if let Some(remap) = ctx
+                                .config
+                                .mappings
+                                .get(key)
+                                .filter(|remap| remap.eager == Some(true))
+                                .cloned()
+                            {
+                                self.tap(ctx, &remap)?;
+                                self.eager_taps.insert(*key);
+                            }
+                            if let Some(term) = hold_term(ctx, key) {
+                                self.deadlines
+                                    .insert(*key, now + Duration::from_millis(term as u64));
+                            }
+                        }
+                    }
+
+                    return Ok(Handled::Yes);
+                }
+                RELEASE => {
+                    self.deadlines.remove(key);
+                    if self.rolled.remove(key) {
+                        return Ok(Handled::Yes);
+                    }
+
+                    let was_hold = ctx.holds_triggered.remove(key);
+                    let eager_tapped = self.eager_taps.remove(key);
+                    ctx.keys_down.remove(key);
+
+                    let had_repeat_state = self.repeat_states.contains_key(key);
+
+                    // Held longer than its term without turning into a hold, so it's not a tap
+                    let term = remap.term.or(ctx
+                        .config
+                        .term
+                        .filter(|_| remap.hold.is_some() || remap.long_tap.is_some()));
+                    let tap_expired = term.is_some_and(|term| {
+                        self.double_tap_states
+                            .get(key)
+                            .and_then(|double_tap_state| double_tap_state.last_tap_time)
+                            .is_some_and(|pressed| {
+                                event.time.duration_since(pressed).as_millis() > term as u128
+                            })
+                    });
+
+                    if let Some(repeat_state) = self.repeat_states.remove(key) {
+                        ctx.output.send_keys(&repeat_state.repeat_keys, RELEASE)?;
+                    }
+
+                    if let Some(double_tap_state) = self.double_tap_states.get(key)
+                        && let Some(last_tap) = double_tap_state.last_tap_time
+                        && let Some(double_tap_timeout) = ctx.config.double_tap_timeout
+                    {
+                        let now = event.time;
+                        if now.duration_since(last_tap).as_millis()
+                            > (double_tap_timeout as u128 * 2)
+                        {
+                            self.double_tap_states.remove(key);
+                        }
+                    }
+
+                    if was_hold {
+                        if let Some(hold_keys) = &remap.hold {
+                            ctx.output.send_keys(hold_keys, RELEASE)?;
+                        }
+
+                        // Only a tap followed by another press starts repeating, not a hold
+                        self.double_tap_states.remove(key);
+                    } else if !had_repeat_state && !tap_expired && !eager_tapped {
+                        let remap = remap.clone();
+                        self.tap(ctx, &remap)?;
+                    } else if !had_repeat_state
+                        && tap_expired
+                        && let Some(long_tap) = &remap.long_tap
+                    {
+                        ctx.output.send_keys(long_tap, PRESS)?;
+                        ctx.output.send_keys(long_tap, RELEASE)?;
+                    }
+
+                    // Keys held back follow the tap
+                    if !holding_back(ctx) {
+                        ctx.replay.append(&mut self.buffered);
+                    }
+
+                    return Ok(Handled::Yes);
+                }
+                _ => {}
+            }
+
+            return Ok(Handled::Yes);
+        }
+
+        if state == PRESS && !ctx.keys_down.is_empty() && !ctx.keys_down.contains(key) {
+            self.trigger_holds(ctx, TapHoldMode::HoldOnOtherKeyPress, key, event.time)?;
+
+            // A layer has already acted on the key, so it can't be held back
+            if layered {
+                self.trigger_holds(ctx, TapHoldMode::PermissiveHold, key, event.time)?;
+                return Ok(Handled::No);
+            }
+
+            if holding_back(ctx) {
+                self.buffered.push(*event);
+                return Ok(Handled::Stop);
+            }
+
+            return Ok(Handled::No);
+        }
+
+        if state == REPEAT && self.buffered.iter().any(|buffered| buffered.key == *key) {
+            return Ok(Handled::Stop);
+        }
+
+        // Pressed and released within a permissive hold, so it was a hold after all
+        if state == RELEASE && self.buffered.iter().any(|buffered| buffered.key == *key) {
+            self.trigger_holds(ctx, TapHoldMode::PermissiveHold, key, event.time)?;
+            // Holds ignoring interrupts are still undecided, so the release waits with the press
+            if holding_back(ctx) {
+                self.buffered.push(*event);
+                return Ok(Handled::Stop);
+            }
+            ctx.replay.append(&mut self.buffered);
+            ctx.replay.push(*event);
+            return Ok(Handled::Stop);
+        }
+
+        Ok(Handled::No)
+    }
+
+    fn next_timer(&self) -> Option<Instant> {
+        self.deadlines
+            .values()
+            .chain(self.autofiring.values().map(|autofiring| &autofiring.next))
+            .min()
+            .copied()
+    }
+
+    fn on_timer(&mut self, ctx: &mut Context, now: Instant) -> Result<()> {
+        for autofiring in self
+            .autofiring
+            .values_mut()
+            .filter(|autofiring| autofiring.next <= now)
+        {
+            ctx.output.send_key(&autofiring.key, PRESS)?;
+            ctx.output.send_key(&autofiring.key, RELEASE)?;
+            // Ticks missed while busy are skipped rather than sent in a burst
+            autofiring.next += autofiring.interval;
+            if autofiring.next <= now {
+                autofiring.next = now + autofiring.interval;
+            }
+        }
+
+        let expired: Vec<KeyCode> = self
+            .deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(key, _)| *key)
+            .collect();
+
+        for key in expired {
+            self.deadlines.remove(&key);
+            if !ctx.keys_down.contains(&key) || ctx.holds_triggered.contains(&key) {
+                continue;
+            }
+
+            press_hold(ctx, &mut self.hold_layouts, &key)?;
+            ctx.holds_triggered.insert(key);
+            self.holds += 1;
+
+            // The hold is decided, so keys held back follow it
+            if !holding_back(ctx) {
+                ctx.replay.append(&mut self.buffered);
+            }
+        }
+        Ok(())
+    }
+
+    fn stats(&self) -> Stats {
+        Stats::from([
+            ("taps", self.taps),
+            ("holds", self.holds),
+            ("overlaps", self.overlaps),
+        ])
+    }
+}
+
+/// How long a key is held alone before it holds, the mapping's own term wins over the hand's,
+/// which wins over the keyboard's. The keyboard's terms only apply to home row mods
+fn hold_term(ctx: &Context, key: &KeyCode) -> Option<u16> {
+    let remap = ctx.config.mappings.get(key)?;
+    if remap.hrm_term.is_some() || remap.hrm != Some(true) {
+        return remap.hrm_term;
+    }
+
+    let hand_term = match key_hand(ctx, key) {
+        Some(Hand::Left) => ctx.config.hrm_term_left,
+        Some(Hand::Right) => ctx.config.hrm_term_right,
+        None => None,
+    };
+    hand_term.or(ctx.config.hrm_term)
+}
+
+/// How long a key has to be held before an overlapping key triggers its hold, from its
+/// `term_by_key` for that key, then its `term_by_hand`, then its hold term when it holds after
+/// the term
+fn overlap_term(
+    ctx: &Context,
+    remap: &RemapAction,
+    origin: &KeyCode,
+    trigger: &KeyCode,
+) -> Option<u16> {
+    if let Some(term) = remap
+        .term_by_key
+        .as_ref()
+        .and_then(|terms| terms.get(trigger))
+    {
+        return Some(*term);
+    }
+    let Some(terms) = &remap.term_by_hand else {
+        return match remap.tap_hold_mode {
+            Some(TapHoldMode::HoldAfterTerm) => hold_term(ctx, origin),
+            _ => None,
+        };
+    };
+    if key_hand(ctx, origin)? == key_hand(ctx, trigger)? {
+        terms.same
+    } else {
+        terms.opposite
+    }
+}
+
+/// Mapped keys held down that haven't decided on tap or hold yet. Permissive holds fall back
+/// to holding on other key press while a layer is active, as the layer has already acted on
+/// the other key
+fn pending_holds(ctx: &Context, mode: TapHoldMode) -> impl Iterator<Item = KeyCode> + '_ {
+    ctx.keys_down.iter().copied().filter(move |origin| {
+        let remap_mode = if ctx.active_layers.is_empty() {
+            ctx.config
+                .mappings
+                .get(origin)
+                .and_then(|remap| remap.tap_hold_mode)
+                .unwrap_or_default()
+        } else {
+            TapHoldMode::HoldOnOtherKeyPress
+        };
+        // Holding after the term is decided on the other key's press too, by how long it's held
+        let remap_mode = match remap_mode {
+            TapHoldMode::HoldAfterTerm => TapHoldMode::HoldOnOtherKeyPress,
+            remap_mode => remap_mode,
+        };
+        remap_mode == mode
+            && !ctx.holds_triggered.contains(origin)
+            && !is_layer_key(origin, &ctx.config)
+            && !is_modifier(origin)
+            && ctx.config.mappings.get(origin).is_some_and(|remap| {
+                // A long tap without a hold is only decided by how long it's held
+                remap.overlap.unwrap_or(true) && (remap.hold.is_some() || remap.long_tap.is_none())
+            })
+    })
+}
+
+/// Whether a pending hold holds back the other keys pressed until it decides, as permissive
+/// holds and holds ignoring interrupts do
+fn holding_back(ctx: &Context) -> bool {
+    pending_holds(ctx, TapHoldMode::PermissiveHold)
+        .chain(pending_holds(ctx, TapHoldMode::IgnoreInterrupt))
+        .next()
+        .is_some()
+}
+
+/// The morph with the most modifiers that are all held
+fn held_morph<'a>(output: &Output, remap: &'a RemapAction) -> Option<&'a Morph> {
+    remap
+        .morph
+        .as_ref()?
+        .iter()
+        .filter(|morph| !morph.mods.is_empty() && morph.mods.iter().all(|m| output.is_pressed(m)))
+        .max_by_key(|morph| morph.mods.len())
+}
+
+/// Taps the morph without its modifiers, pressing them again after as they're still held
+fn send_morph(output: &mut Output, morph: &Morph) -> Result<()> {
+    output.send_keys(&morph.mods, RELEASE)?;
+    output.send_keys(&morph.tap, PRESS)?;
+    output.send_keys(&morph.tap, RELEASE)?;
+    output.send_keys(&morph.mods, PRESS)
+}
+
+/// Presses a mapping's hold keys, in its `hold_layout` if it has one
+fn press_hold(
+    ctx: &mut Context,
+    hold_layouts: &mut HashMap<LayoutConfig, Box<dyn Layout>>,
+    key: &KeyCode,
+) -> Result<()> {
+    let Some(remap) = ctx.config.mappings.get(key) else {
+        return Ok(());
+    };
+    let Some(hold_keys) = &remap.hold else {
+        return Ok(());
+    };
+    if let Some(layout_config) = &remap.hold_layout {
+        if !hold_layouts.contains_key(layout_config) {
+            match layouts::get(Some(layout_config)) {
+                Ok(layout) => {
+                    hold_layouts.insert(layout_config.clone(), layout);
+                }
+                Err(e) => warn!("Failed to load hold layout: {}", e),
+            }
+        }
+        if let Some(layout) = hold_layouts.get_mut(layout_config) {
+            return ctx.output.send_keys_in(hold_keys, PRESS, layout);
+        }
+    }
+    ctx.output.send_keys(hold_keys, PRESS)
+}
+
+/// Swaps the layout used for output, keys already held still release under the old layout
+fn switch_layout(output: &mut Output, layout: &LayoutConfig) {
+    match layouts::get(Some(layout)) {
+        Ok(new_layout) => {
+            output.set_layout(new_layout);
+            info!("Layout switched: {:?}", layout);
+        }
+        Err(e) => warn!("Failed to switch layout: {}", e),
+    }
+}
+
+/// Runs a shell command in the background, logging its stderr once it exits
+fn exec(command: &str) {
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn();
+
+    let child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("Failed to exec {}: {}", command, e);
+            return;
+        }
+    };
+    info!("Exec: {}", command);
+
+    let command = command.to_owned();
+    std::thread::spawn(move || match child.wait_with_output() {
+        Ok(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if !stderr.trim().is_empty() {
+                debug!("Exec stderr {}: {}", command, stderr.trim());
+            }
+            if !output.status.success() {
+                warn!("Exec {} exited with {}", command, output.status);
+            }
+        }
+        Err(e) => warn!("Failed to wait on exec {}: {}", command, e),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::features::{PRESS, RELEASE};
+    use crate::io::OutputEvent::{self, Press, Release};
+    use crate::pipeline::tests::run;
+    use evdev::KeyCode as K;
+
+    /// `KEY_F` taps itself and holds control past 200 ms, in the given `tap_hold_mode`
+    fn run_mode(mode: &str, events: &[(u64, K, i32)]) -> Vec<OutputEvent> {
+        let config = format!(
+            "
+keyboards:
+  Test:
+    layout: qwerty
+    layers: {{}}
+    mappings:
+      KEY_F:
+        tap: [KEY_F]
+        hold: [KEY_LEFTCTRL]
+        hrm_term: 200
+        tap_hold_mode: {}
+",
+            mode
+        );
+        run(&config, events)
+    }
+
+    const NESTED: &[(u64, K, i32)] = &[
+        (0, K::KEY_F, PRESS),
+        (20, K::KEY_J, PRESS),
+        (40, K::KEY_J, RELEASE),
+        (60, K::KEY_F, RELEASE),
+    ];
+
+    const ROLLED: &[(u64, K, i32)] = &[
+        (0, K::KEY_F, PRESS),
+        (20, K::KEY_J, PRESS),
+        (40, K::KEY_F, RELEASE),
+        (60, K::KEY_J, RELEASE),
+    ];
+
+    #[test]
+    fn nested_press_holds_in_both_modes() {
+        let held = [
+            Press(K::KEY_LEFTCTRL),
+            Press(K::KEY_J),
+            Release(K::KEY_J),
+            Release(K::KEY_LEFTCTRL),
+        ];
+        assert_eq!(run_mode("hold_on_other_key_press", NESTED), held);
+        assert_eq!(run_mode("permissive_hold", NESTED), held);
+    }
+
+    #[test]
+    fn rolled_press_only_holds_on_other_key_press() {
+        assert_eq!(
+            run_mode("hold_on_other_key_press", ROLLED),
+            [
+                Press(K::KEY_LEFTCTRL),
+                Press(K::KEY_J),
+                Release(K::KEY_LEFTCTRL),
+                Release(K::KEY_J),
+            ]
+        );
+        assert_eq!(
+            run_mode("permissive_hold", ROLLED),
+            [
+                Press(K::KEY_F),
+                Release(K::KEY_F),
+                Press(K::KEY_J),
+                Release(K::KEY_J),
+            ]
+        );
+    }
+
+    const STREAK: &str = "
+keyboards:
+  Test:
+    layout: qwerty
+    layers: {}
+    streak_term: 150
+    mappings:
+      KEY_F: {tap: [KEY_F], hold: [KEY_LEFTCTRL], hrm: true, hrm_term: 200}
+";
+
+    #[test]
+    fn home_row_mod_rolled_mid_streak_taps() {
+        let output = run(
+            STREAK,
+            &[
+                (0, K::KEY_A, PRESS),
+                (30, K::KEY_A, RELEASE),
+                (80, K::KEY_F, PRESS),
+                (100, K::KEY_J, PRESS),
+                (120, K::KEY_J, RELEASE),
+                (140, K::KEY_F, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_A),
+                Release(K::KEY_A),
+                Press(K::KEY_F),
+                Press(K::KEY_J),
+                Release(K::KEY_J),
+                Release(K::KEY_F),
+            ]
+        );
+    }
+
+    #[test]
+    fn home_row_mod_held_after_a_pause_holds() {
+        let output = run(
+            STREAK,
+            &[
+                (0, K::KEY_A, PRESS),
+                (30, K::KEY_A, RELEASE),
+                (500, K::KEY_F, PRESS),
+                (520, K::KEY_J, PRESS),
+                (540, K::KEY_J, RELEASE),
+                (560, K::KEY_F, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_A),
+                Release(K::KEY_A),
+                Press(K::KEY_LEFTCTRL),
+                Press(K::KEY_J),
+                Release(K::KEY_J),
+                Release(K::KEY_LEFTCTRL),
+            ]
+        );
+    }
+
+    const BILATERAL: &str = "
+keyboards:
+  Test:
+    layout: qwerty
+    layers: {}
+    bilateral_combinations: true
+    mappings:
+      KEY_F: {tap: [KEY_F], hold: [KEY_LEFTCTRL], hrm: true, hrm_term: 200}
+";
+
+    #[test]
+    fn bilateral_same_hand_roll_taps() {
+        let output = run(
+            BILATERAL,
+            &[
+                (0, K::KEY_F, PRESS),
+                (20, K::KEY_D, PRESS),
+                (40, K::KEY_D, RELEASE),
+                (60, K::KEY_F, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_F),
+                Release(K::KEY_F),
+                Press(K::KEY_D),
+                Release(K::KEY_D),
+            ]
+        );
+    }
+
+    #[test]
+    fn bilateral_opposite_hand_key_holds() {
+        let output = run(
+            BILATERAL,
+            &[
+                (0, K::KEY_F, PRESS),
+                (20, K::KEY_J, PRESS),
+                (40, K::KEY_J, RELEASE),
+                (60, K::KEY_F, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_LEFTCTRL),
+                Press(K::KEY_J),
+                Release(K::KEY_J),
+                Release(K::KEY_LEFTCTRL),
+            ]
+        );
+    }
+
+    /// `KEY_F` tapped, then pressed again straight away and held past its hold term
+    fn tap_then_hold(force_hold: bool) -> Vec<OutputEvent> {
+        let config = format!(
+            "
+keyboards:
+  Test:
+    layout: qwerty
+    layers: {{}}
+    mappings:
+      KEY_F: {{tap: [KEY_F], hold: [KEY_LEFTCTRL], hrm_term: 200, force_hold: {}}}
+",
+            force_hold
+        );
+        run(
+            &config,
+            &[
+                (0, K::KEY_F, PRESS),
+                (30, K::KEY_F, RELEASE),
+                (80, K::KEY_F, PRESS),
+                (500, K::KEY_F, RELEASE),
+            ],
+        )
+    }
+
+    #[test]
+    fn hold_right_after_a_tap_repeats_the_tap() {
+        assert_eq!(
+            tap_then_hold(false),
+            [
+                Press(K::KEY_F),
+                Release(K::KEY_F),
+                Press(K::KEY_F),
+                Release(K::KEY_F),
+            ]
+        );
+    }
+
+    #[test]
+    fn force_hold_holds_right_after_a_tap() {
+        assert_eq!(
+            tap_then_hold(true),
+            [
+                Press(K::KEY_F),
+                Release(K::KEY_F),
+                Press(K::KEY_LEFTCTRL),
+                Release(K::KEY_LEFTCTRL),
+            ]
+        );
+    }
+
+    const QUICK_HOLD: &str = "
+keyboards:
+  Test:
+    layout: qwerty
+    layers: {}
+    mappings:
+      KEY_F: {tap: [KEY_F], hold: [KEY_LEFTCTRL], hrm_term: 200, quick_hold: true}
+";
+
+    #[test]
+    fn quick_hold_single_press_waits_for_the_term() {
+        let tapped = run(
+            QUICK_HOLD,
+            &[(0, K::KEY_F, PRESS), (150, K::KEY_F, RELEASE)],
+        );
+        assert_eq!(tapped, [Press(K::KEY_F), Release(K::KEY_F)]);
+        let held = run(
+            QUICK_HOLD,
+            &[(0, K::KEY_F, PRESS), (300, K::KEY_F, RELEASE)],
+        );
+        assert_eq!(held, [Press(K::KEY_LEFTCTRL), Release(K::KEY_LEFTCTRL)]);
+    }
+
+    #[test]
+    fn quick_hold_holds_straight_away_after_a_tap() {
+        // Released again before the term, so only the quick hold can have held it
+        let output = run(
+            QUICK_HOLD,
+            &[
+                (0, K::KEY_F, PRESS),
+                (30, K::KEY_F, RELEASE),
+                (80, K::KEY_F, PRESS),
+                (150, K::KEY_F, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_F),
+                Release(K::KEY_F),
+                Press(K::KEY_LEFTCTRL),
+                Release(K::KEY_LEFTCTRL),
+            ]
+        );
+    }
+
+    const HOLD_LAYOUT: &str = "
+keyboards:
+  Test:
+    layout: dvorak
+    layers: {}
+    mappings:
+      KEY_A: {tap: [KEY_O], hold: [KEY_O], hrm_term: 200, hold_layout: qwerty}
+";
+
+    #[test]
+    fn tap_is_sent_in_the_keyboard_layout() {
+        let output = run(
+            HOLD_LAYOUT,
+            &[(0, K::KEY_A, PRESS), (50, K::KEY_A, RELEASE)],
+        );
+        // Dvorak's O is on Qwerty's S
+        assert_eq!(output, [Press(K::KEY_S), Release(K::KEY_S)]);
+    }
+
+    #[test]
+    fn hold_is_sent_in_its_hold_layout() {
+        let output = run(
+            HOLD_LAYOUT,
+            &[(0, K::KEY_A, PRESS), (300, K::KEY_A, RELEASE)],
+        );
+        assert_eq!(output, [Press(K::KEY_O), Release(K::KEY_O)]);
+    }
+
+    #[test]
+    fn hold_after_term_taps_an_early_roll() {
+        assert_eq!(
+            run_mode("hold_after_term", NESTED),
+            [
+                Press(K::KEY_F),
+                Release(K::KEY_F),
+                Press(K::KEY_J),
+                Release(K::KEY_J),
+            ]
+        );
+    }
+
+    #[test]
+    fn hold_after_term_holds_an_overlap_past_the_term() {
+        let output = run_mode(
+            "hold_after_term",
+            &[
+                (0, K::KEY_F, PRESS),
+                (250, K::KEY_J, PRESS),
+                (270, K::KEY_J, RELEASE),
+                (290, K::KEY_F, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_LEFTCTRL),
+                Press(K::KEY_J),
+                Release(K::KEY_J),
+                Release(K::KEY_LEFTCTRL),
+            ]
+        );
+    }
+
+    /// A held past the term, with B pressed during it and released after
+    const HELD_PAST_TERM: &[(u64, K, i32)] = &[
+        (0, K::KEY_F, PRESS),
+        (20, K::KEY_J, PRESS),
+        (250, K::KEY_J, RELEASE),
+        (270, K::KEY_F, RELEASE),
+    ];
+
+    /// B pressed once A has been held past the term
+    const PRESSED_AFTER_TERM: &[(u64, K, i32)] = &[
+        (0, K::KEY_F, PRESS),
+        (250, K::KEY_J, PRESS),
+        (270, K::KEY_J, RELEASE),
+        (290, K::KEY_F, RELEASE),
+    ];
+
+    #[test]
+    fn ignore_interrupt_only_holds_once_the_term_passes() {
+        let tapped = [
+            Press(K::KEY_F),
+            Release(K::KEY_F),
+            Press(K::KEY_J),
+            Release(K::KEY_J),
+        ];
+        let held = [
+            Press(K::KEY_LEFTCTRL),
+            Press(K::KEY_J),
+            Release(K::KEY_J),
+            Release(K::KEY_LEFTCTRL),
+        ];
+        assert_eq!(run_mode("ignore_interrupt", NESTED), tapped);
+        assert_eq!(run_mode("ignore_interrupt", ROLLED), tapped);
+        assert_eq!(run_mode("ignore_interrupt", HELD_PAST_TERM), held);
+        assert_eq!(run_mode("ignore_interrupt", PRESSED_AFTER_TERM), held);
+    }
+
+    #[test]
+    fn tap_hold_modes_follow_the_readme_table() {
+        let modes = [
+            "hold_on_other_key_press",
+            "permissive_hold",
+            "hold_after_term",
+            "ignore_interrupt",
+        ];
+        let rows = [
+            (NESTED, [true, true, false, false]),
+            (ROLLED, [true, false, false, false]),
+            (HELD_PAST_TERM, [true, true, false, true]),
+            (PRESSED_AFTER_TERM, [true, true, true, true]),
+        ];
+        for (events, holds) in rows {
+            for (mode, hold) in modes.iter().zip(holds) {
+                let output = run_mode(mode, events);
+                let first = if hold { K::KEY_LEFTCTRL } else { K::KEY_F };
+                assert_eq!(output.first(), Some(&Press(first)), "{} {:?}", mode, events);
+                assert_eq!(output.len(), 4, "{} {:?}", mode, events);
+                assert!(output.contains(&Press(K::KEY_J)), "{} {:?}", mode, events);
+                assert!(output.contains(&Release(K::KEY_J)), "{} {:?}", mode, events);
+            }
+        }
+    }
+
+    const LONG_TAP: &str = "
+keyboards:
+  Test:
+    layout: qwerty
+    layers: {}
+    mappings:
+      KEY_A: {tap: [KEY_A], long_tap: [KEY_RIGHTALT, KEY_A], term: 200}
+";
+
+    #[test]
+    fn long_tap_quick_tap_types_the_tap() {
+        let output = run(LONG_TAP, &[(0, K::KEY_A, PRESS), (100, K::KEY_A, RELEASE)]);
+        assert_eq!(output, [Press(K::KEY_A), Release(K::KEY_A)]);
+    }
+
+    #[test]
+    fn long_tap_released_after_the_term_types_the_long_tap() {
+        let output = run(LONG_TAP, &[(0, K::KEY_A, PRESS), (300, K::KEY_A, RELEASE)]);
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_RIGHTALT),
+                Press(K::KEY_A),
+                Release(K::KEY_A),
+                Release(K::KEY_RIGHTALT),
+            ]
+        );
+    }
+
+    #[test]
+    fn long_tap_rolled_into_another_key_taps_first() {
+        let output = run(
+            LONG_TAP,
+            &[
+                (0, K::KEY_A, PRESS),
+                (20, K::KEY_J, PRESS),
+                (40, K::KEY_A, RELEASE),
+                (60, K::KEY_J, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_A),
+                Release(K::KEY_A),
+                Press(K::KEY_J),
+                Release(K::KEY_J),
+            ]
+        );
+    }
+
+    #[test]
+    fn autofire_taps_every_interval_until_released() {
+        let output = run(
+            "
+keyboards:
+  Test:
+    layout: qwerty
+    layers: {}
+    mappings:
+      KEY_F: {autofire: {key: KEY_SPACE, interval_ms: 50}}
+",
+            &[(0, K::KEY_F, PRESS), (120, K::KEY_F, RELEASE)],
+        );
+        assert_eq!(
+            output,
+            vec![vec![Press(K::KEY_SPACE), Release(K::KEY_SPACE)]; 3].concat()
+        );
+    }
+
+    #[test]
+    fn autofire_interval_is_capped() {
+        let output = run(
+            "
+keyboards:
+  Test:
+    layout: qwerty
+    layers: {}
+    mappings:
+      KEY_F: {autofire: {key: KEY_SPACE, interval: 1}}
+",
+            &[(0, K::KEY_F, PRESS), (50, K::KEY_F, RELEASE)],
+        );
+        assert_eq!(
+            output,
+            vec![vec![Press(K::KEY_SPACE), Release(K::KEY_SPACE)]; 3].concat()
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use crate::features::{Context, Feature, Handled, KeyEvent, PRESS, RELEASE};
+use anyhow::Result;
+use colored::Colorize;
+use evdev::KeyCode;
+use log::debug;
+use std::collections::HashSet;
+
+/// Key Lock
+/// - Tapping a `key_lock` key locks the next key pressed down until it's tapped again.
+/// - Several keys can be locked at once.
+/// - Tapping the `key_lock` key twice releases every locked key.
+#[derive(Default)]
+pub(crate) struct KeyLockFeature {
+    armed: bool,
+    locked: HashSet<KeyCode>,
+    /// Locked keys pressed again, released along with the physical key
+    unlocking: HashSet<KeyCode>,
+}
+
+impl Feature for KeyLockFeature {
+    fn on_event(&mut self, ctx: &mut Context, event: &KeyEvent) -> Result<Handled> {
+        let key = &event.key;
+
+        if ctx
+            .config
+            .mappings
+            .get(key)
+            .is_some_and(|remap| remap.key_lock == Some(true))
+        {
+            if event.state == PRESS {
+                if self.armed {
+                    self.armed = false;
+                    // Released through the features after this one, so layers turn off too
+                    for locked in self.locked.drain() {
+                        ctx.replay.push(KeyEvent {
+                            key: locked,
+                            state: RELEASE,
+                            time: event.time,
+                        });
+                    }
+                    debug!("{}", "UNLOCKED ALL".purple());
+                } else {
+                    self.armed = true;
+                }
+            }
+            return Ok(Handled::Stop);
+        }
+
+        match event.state {
+            PRESS if self.locked.contains(key) => {
+                self.locked.remove(key);
+                self.unlocking.insert(*key);
+                Ok(Handled::Stop)
+            }
+            PRESS if self.armed => {
+                self.armed = false;
+                self.locked.insert(*key);
+                debug!("{}: {:?}", "LOCKED".purple(), key);
+                Ok(Handled::No)
+            }
+            RELEASE if self.unlocking.remove(key) => Ok(Handled::No),
+            _ if self.locked.contains(key) => Ok(Handled::Stop),
+            _ => Ok(Handled::No),
+        }
+    }
+
+    fn latched(&self) -> bool {
+        !self.locked.is_empty()
+    }
+}
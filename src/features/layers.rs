@@ -0,0 +1,736 @@
+use crate::config::{LayerAction, RemapAction};
+use crate::features::{
+    Context, Feature, Handled, KeyEvent, PRESS, RELEASE, layer_action, layer_mods, update_leds,
+};
+use crate::io::state_arrow;
+use anyhow::Result;
+use colored::Colorize;
+use evdev::KeyCode;
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Layers
+/// - Holding a layer key activates its layer until released.
+/// - Tapping the layer key of a toggle layer latches it on, tapping again turns it off.
+/// - Holding a toggle layer key past the toggle term, or using the layer, is momentary.
+/// - Tapping the layer key of a layer with a `tap` types it, unless another key was pressed.
+/// - Tapping the layer key of a tap toggle layer `tapping_toggle` times in a row latches it on,
+///   each tap within the toggle term of the last, tapping again turns it off.
+/// - Double tapping the layer key of a double tap toggle layer latches it on, both taps and the
+///   gap between them within the double tap timeout, tapping again turns it off. A hold after a
+///   tap stays momentary.
+/// - A mapping with a `layer` taps its keys, or activates the layer if another key overlaps.
+/// - Keys pressed while a layer is active emit the layer's remapped keys.
+/// - With several layers active, the one declared last that maps the key wins.
+/// - Releasing the trigger of a one-shot layer without using it leaves the layer on for the next
+///   key press.
+/// - A layer's `apply_mods` are held around the keys it maps, and released once no key held
+///   on the layer needs them.
+/// - A key a layer gives a mapping takes it in place of its own until released, for dual
+///   function to tap or hold.
+/// - A `chord` layer is on while all its keys are held, they're held back until the chord is
+///   complete, and pass through as usual if it isn't within the combo term.
+#[derive(Default)]
+pub(crate) struct LayersFeature {
+    latched: HashSet<String>,
+    trigger: Option<TriggerState>,
+    /// Taps in a row of a tap toggle or double tap toggle layer key
+    taps: Option<TapCount>,
+    /// Layer-tap keys held down, and whether another key has engaged their layer
+    layer_taps: HashMap<KeyCode, bool>,
+    /// Keys pressed on a layer, and the keys and modifiers they're holding
+    pressed: HashMap<KeyCode, (Vec<KeyCode>, Vec<KeyCode>)>,
+    /// Keys pressed with a layer's mapping in place of their own, and their own to put back
+    remapped: HashMap<KeyCode, Option<RemapAction>>,
+    /// Keys released with a layer's mapping, put back once dual function has seen the release
+    released: Vec<KeyCode>,
+    /// One-shot layers left on after their trigger was released, until the next key press
+    oneshot: HashSet<String>,
+    /// Chord keys pressed so far, held back until a chord completes or can't
+    chord_pending: Vec<KeyEvent>,
+    chord_deadline: Option<Instant>,
+    /// Completed chords, until all their keys are released
+    chords: Vec<ActiveChord>,
+}
+
+struct ActiveChord {
+    layer: String,
+    /// Keys still held, their releases are swallowed
+    keys: Vec<KeyCode>,
+    /// Cleared once any of the keys is released
+    active: bool,
+}
+
+struct TapCount {
+    key: KeyCode,
+    count: u8,
+    released: Instant,
+}
+
+struct TriggerState {
+    key: KeyCode,
+    deadline: Instant,
+    used: bool,
+    /// Typed when tapped, any other key pressed uses the trigger as with a layer-tap key
+    tap: Option<Vec<KeyCode>>,
+}
+
+impl Feature for LayersFeature {
+    fn on_event(&mut self, ctx: &mut Context, event: &KeyEvent) -> Result<Handled> {
+        self.restore_mappings(ctx);
+        if let Some(handled) = self.chord_event(ctx, event)? {
+            return Ok(handled);
+        }
+        self.handle(ctx, event)
+    }
+
+    fn next_timer(&self) -> Option<Instant> {
+        self.chord_deadline
+    }
+
+    fn on_timer(&mut self, ctx: &mut Context, now: Instant) -> Result<()> {
+        if self.chord_deadline.is_some_and(|deadline| now >= deadline) {
+            self.flush_chord(ctx)?;
+        }
+        Ok(())
+    }
+}
+
+impl LayersFeature {
+    /// Tracks chord layers, `None` when the event isn't part of a chord
+    fn chord_event(&mut self, ctx: &mut Context, event: &KeyEvent) -> Result<Option<Handled>> {
+        let key = &event.key;
+
+        if let Some(index) = self
+            .chords
+            .iter()
+            .position(|chord| chord.keys.contains(key))
+        {
+            if event.state == RELEASE {
+                let chord = &mut self.chords[index];
+                chord.keys.retain(|k| k != key);
+                if std::mem::take(&mut chord.active) {
+                    let layer_name = chord.layer.to_owned();
+                    self.deactivate(ctx, &layer_name)?;
+                    log_layer(&layer_name, RELEASE);
+                }
+                if self.chords[index].keys.is_empty() {
+                    self.chords.remove(index);
+                }
+            }
+            return Ok(Some(Handled::Stop));
+        }
+
+        let chord_key = ctx
+            .config
+            .layers
+            .values()
+            .any(|layer| layer.chord.contains(key));
+        let pending = self.chord_pending.iter().any(|pending| pending.key == *key);
+
+        match event.state {
+            PRESS if chord_key => {
+                let mut keys: Vec<KeyCode> = self.chord_pending.iter().map(|e| e.key).collect();
+                keys.push(*key);
+                let possible = |keys: &[KeyCode]| {
+                    ctx.config
+                        .layers
+                        .values()
+                        .any(|layer| keys.iter().all(|k| layer.chord.contains(k)))
+                };
+                // A key no chord goes with lets the ones pending through first
+                if !possible(&keys) {
+                    self.flush_chord(ctx)?;
+                    keys = vec![*key];
+                }
+
+                if self.chord_pending.is_empty() {
+                    self.chord_deadline = Some(
+                        event.time
+                            + Duration::from_millis(ctx.config.combo_term.unwrap_or(0) as u64),
+                    );
+                }
+                self.chord_pending.push(*event);
+
+                let complete = ctx.config.layers.iter().find(|(_, layer)| {
+                    layer.chord.len() == keys.len() && keys.iter().all(|k| layer.chord.contains(k))
+                });
+                if let Some((layer_name, _)) = complete {
+                    let layer_name = layer_name.to_owned();
+                    self.chord_pending.clear();
+                    self.chord_deadline = None;
+                    self.engage_layer_taps(ctx);
+                    ctx.active_layers.insert(layer_name.to_owned());
+                    update_leds(ctx);
+                    log_layer(&layer_name, PRESS);
+                    self.chords.push(ActiveChord {
+                        layer: layer_name,
+                        keys,
+                        active: true,
+                    });
+                }
+                Ok(Some(Handled::Stop))
+            }
+            // Another key pressed, or a pending one released, breaks the chord
+            PRESS => {
+                self.flush_chord(ctx)?;
+                Ok(None)
+            }
+            RELEASE if pending => {
+                self.flush_chord(ctx)?;
+                Ok(None)
+            }
+            // Autorepeat of a pending key waits with it
+            _ if pending => Ok(Some(Handled::Stop)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Lets the pending chord keys through as ordinary presses
+    fn flush_chord(&mut self, ctx: &mut Context) -> Result<()> {
+        self.chord_deadline = None;
+        for event in std::mem::take(&mut self.chord_pending) {
+            if self.handle(ctx, &event)? == Handled::No {
+                ctx.replay.push(event);
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, ctx: &mut Context, event: &KeyEvent) -> Result<Handled> {
+        let key = &event.key;
+        let state = event.state;
+
+        if let Some(remap) = ctx.config.mappings.get(key).cloned()
+            && let Some(layer_name) = &remap.layer
+        {
+            match state {
+                PRESS => {
+                    self.engage_layer_taps(ctx);
+                    ctx.keys_down.insert(*key);
+                    self.layer_taps.insert(*key, false);
+                }
+                RELEASE => {
+                    ctx.keys_down.remove(key);
+                    match self.layer_taps.remove(key) {
+                        Some(true) => {
+                            self.deactivate(ctx, layer_name)?;
+                            log_layer(layer_name, state);
+                        }
+                        _ => {
+                            if let Some(tap_keys) = &remap.tap {
+                                ctx.output.send_keys(tap_keys, PRESS)?;
+                                ctx.output.send_keys(tap_keys, RELEASE)?;
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+
+            return Ok(Handled::Stop);
+        }
+
+        if state == PRESS {
+            self.engage_layer_taps(ctx);
+            if let Some(trigger) = &mut self.trigger
+                && trigger.tap.is_some()
+                && trigger.key != *key
+            {
+                trigger.used = true;
+            }
+            // Any other key breaks a run of taps
+            if self.taps.as_ref().is_some_and(|taps| taps.key != *key) {
+                self.taps = None;
+            }
+        }
+
+        let trigger_layer = ctx
+            .config
+            .layers
+            .iter()
+            .find(|(_, layer)| layer.triggers.contains_key(key))
+            .map(|(layer_name, layer)| (layer_name.to_owned(), layer.clone()));
+
+        if let Some((layer_name, layer)) = trigger_layer {
+            let toggle = layer.toggle;
+            let tap_toggle = layer.tap_toggle;
+            let double_tap_toggle = layer.double_tap_toggle;
+            let oneshot = layer.oneshot;
+            // Double tap toggle layers time both their taps and the gap between with the double
+            // tap timeout
+            let toggle_term = if double_tap_toggle {
+                ctx.config.double_tap_timeout
+            } else {
+                ctx.config.toggle_term
+            };
+            let toggle_term = Duration::from_millis(toggle_term.unwrap_or(0) as u64);
+            match state {
+                PRESS => {
+                    ctx.keys_down.insert(*key);
+                    ctx.active_layers.insert(layer_name.to_owned());
+                    update_leds(ctx);
+                    self.trigger = (toggle
+                        || tap_toggle
+                        || double_tap_toggle
+                        || oneshot
+                        || layer.tap.is_some())
+                    .then(|| TriggerState {
+                        key: *key,
+                        deadline: event.time + toggle_term,
+                        used: false,
+                        tap: layer.tap,
+                    });
+                    if self
+                        .taps
+                        .as_ref()
+                        .is_some_and(|taps| event.time > taps.released + toggle_term)
+                    {
+                        self.taps = None;
+                    }
+                }
+                RELEASE => {
+                    ctx.keys_down.remove(key);
+
+                    let unused = self
+                        .trigger
+                        .take()
+                        .filter(|trigger| trigger.key == *key && !trigger.used);
+                    let tapped = unused
+                        .as_ref()
+                        .is_some_and(|trigger| event.time < trigger.deadline);
+                    let taps = self.count_tap(
+                        *key,
+                        (tap_toggle || double_tap_toggle) && tapped,
+                        event.time,
+                    );
+                    let latch = toggle
+                        || (tap_toggle
+                            && ctx
+                                .config
+                                .tapping_toggle
+                                .is_some_and(|tapping_toggle| taps >= tapping_toggle))
+                        || (double_tap_toggle
+                            && ctx.config.double_tap_timeout.is_some()
+                            && taps >= 2)
+                        || self.latched.contains(&layer_name);
+                    // Tapping a latched layer's trigger unlatches it
+                    if (toggle || tap_toggle || double_tap_toggle) && tapped && latch {
+                        self.taps = None;
+                        if !self.latched.remove(&layer_name) {
+                            self.latched.insert(layer_name.to_owned());
+                            debug!("{} {}", "LATCHED".purple(), layer_name.bright_blue());
+                        }
+                    } else if oneshot && unused.is_some() && !self.latched.contains(&layer_name) {
+                        self.oneshot.insert(layer_name.to_owned());
+                        debug!("{} {}", "ONESHOT".purple(), layer_name.bright_blue());
+                        return Ok(Handled::Yes);
+                    } else if tapped && let Some(tap_keys) = unused.and_then(|trigger| trigger.tap)
+                    {
+                        ctx.output.send_keys(&tap_keys, PRESS)?;
+                        ctx.output.send_keys(&tap_keys, RELEASE)?;
+                    }
+
+                    self.deactivate(ctx, &layer_name)?;
+                }
+                // Autorepeat of a held trigger doesn't change the layer
+                _ => return Ok(Handled::Yes),
+            }
+
+            log_layer(&layer_name, state);
+
+            return Ok(Handled::Yes);
+        }
+
+        // Keeps the layer's mapping until released, whatever the layer does meanwhile
+        if self.remapped.contains_key(key) {
+            if state == RELEASE {
+                self.released.push(*key);
+            }
+            return Ok(Handled::No);
+        }
+
+        // Released as pressed even if the layer turned off while held, as a one-shot layer does
+        if let Some((remapped, mods)) = self.pressed.get(key).cloned() {
+            if state == RELEASE {
+                self.pressed.remove(key);
+                ctx.output.send_keys(&remapped, RELEASE)?;
+                self.release_mods(ctx, &mods)?;
+            } else {
+                ctx.output.send_keys(&remapped, state)?;
+            }
+            return Ok(Handled::Yes);
+        }
+
+        let action = layer_action(&ctx.config, &ctx.active_layers, key).cloned();
+        let mods = layer_mods(&ctx.config, &ctx.active_layers, key);
+        if action.is_some()
+            && let Some(trigger) = &mut self.trigger
+        {
+            trigger.used = true;
+        }
+
+        let handled = match action {
+            // Blocked keys are consumed so no later feature acts on them
+            Some(LayerAction::Keys(remapped)) if remapped.is_empty() => Handled::Stop,
+            Some(LayerAction::Keys(remapped)) if state == PRESS => {
+                self.press(ctx, *key, remapped, mods)?;
+                Handled::Yes
+            }
+            // Transparent all the way down, so the key itself gets the modifiers
+            Some(LayerAction::Marker(_)) | None if state == PRESS && !mods.is_empty() => {
+                if let Some(trigger) = &mut self.trigger {
+                    trigger.used = true;
+                }
+                self.press(ctx, *key, vec![*key], mods)?;
+                Handled::Yes
+            }
+            Some(LayerAction::Remap(remap)) if state == PRESS => {
+                let own = ctx.config.mappings.insert(*key, *remap);
+                self.remapped.insert(*key, own);
+                Handled::No
+            }
+            Some(LayerAction::Mouse(_)) => Handled::Yes,
+            Some(LayerAction::Keys(_) | LayerAction::Marker(_) | LayerAction::Remap(_)) | None => {
+                Handled::No
+            }
+        };
+
+        // Any key pressed uses up the one-shot layers, whether they map it or not
+        if state == PRESS && !self.oneshot.is_empty() {
+            for layer_name in self.oneshot.drain() {
+                ctx.active_layers.remove(&layer_name);
+                log_layer(&layer_name, RELEASE);
+            }
+            update_leds(ctx);
+        }
+
+        Ok(handled)
+    }
+
+    /// Counts a tap of a tap toggle or double tap toggle layer key, anything else ends the run.
+    /// Returns the taps in a row so far
+    fn count_tap(&mut self, key: KeyCode, tapped: bool, now: Instant) -> u8 {
+        if !tapped {
+            self.taps = None;
+            return 0;
+        }
+        let taps = self.taps.get_or_insert(TapCount {
+            key,
+            count: 0,
+            released: now,
+        });
+        taps.count += 1;
+        taps.released = now;
+        taps.count
+    }
+
+    /// Puts back the mappings of keys released with a layer's mapping
+    fn restore_mappings(&mut self, ctx: &mut Context) {
+        for key in self.released.drain(..) {
+            match self.remapped.remove(&key) {
+                Some(Some(own)) => {
+                    ctx.config.mappings.insert(key, own);
+                }
+                Some(None) => {
+                    ctx.config.mappings.remove(&key);
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Overlapping keys turn pending layer-tap keys into their layers
+    fn engage_layer_taps(&mut self, ctx: &mut Context) {
+        for (layer_key, engaged) in self.layer_taps.iter_mut().filter(|(_, engaged)| !**engaged) {
+            *engaged = true;
+            if let Some(layer_name) = ctx
+                .config
+                .mappings
+                .get(layer_key)
+                .and_then(|remap| remap.layer.as_ref())
+            {
+                ctx.active_layers.insert(layer_name.to_owned());
+                log_layer(layer_name, PRESS);
+            }
+        }
+        update_leds(ctx);
+    }
+
+    /// Presses a layer's keys with its modifiers, pressing only the modifiers not already held.
+    /// They're sent after the features that come later, so a home row mod the key triggers
+    /// holds first
+    fn press(
+        &mut self,
+        ctx: &mut Context,
+        key: KeyCode,
+        remapped: Vec<KeyCode>,
+        mods: Vec<KeyCode>,
+    ) -> Result<()> {
+        let unheld: Vec<KeyCode> = mods
+            .iter()
+            .filter(|m| !self.pressed.values().any(|(_, held)| held.contains(m)))
+            .copied()
+            .collect();
+        ctx.deferred.push(unheld);
+        ctx.deferred.push(remapped.clone());
+        self.pressed.insert(key, (remapped, mods));
+        Ok(())
+    }
+
+    /// Releases the layer modifiers no other held key still needs
+    fn release_mods(&self, ctx: &mut Context, mods: &[KeyCode]) -> Result<()> {
+        let unused: Vec<KeyCode> = mods
+            .iter()
+            .filter(|m| !self.pressed.values().any(|(_, held)| held.contains(m)))
+            .copied()
+            .collect();
+        ctx.output.send_keys(&unused, RELEASE)
+    }
+
+    /// Releases holds triggered on the layer and turns it off, unless it's latched
+    fn deactivate(&self, ctx: &mut Context, layer_name: &str) -> Result<()> {
+        for held_key in ctx.holds_triggered.iter() {
+            if let Some(remap) = ctx.config.mappings.get(held_key)
+                && let Some(hold_keys) = &remap.hold
+            {
+                ctx.output.send_keys(hold_keys, RELEASE)?;
+            }
+        }
+        ctx.holds_triggered.clear();
+
+        if !self.latched.contains(layer_name) {
+            ctx.active_layers.remove(layer_name);
+        }
+        update_leds(ctx);
+        Ok(())
+    }
+}
+
+fn log_layer(layer: &str, state: i32) {
+    debug!(
+        "{} {}: {}",
+        state_arrow(state),
+        "LAYER".purple(),
+        layer.bright_blue(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::features::{PRESS, RELEASE};
+    use crate::io::OutputEvent::{self, Press, Release};
+    use crate::pipeline::tests::run;
+    use evdev::KeyCode as K;
+
+    /// Layer on `KEY_RIGHTALT` mapping `KEY_J` to `KEY_DOWN`, latched the way `option` says
+    fn run_layer(option: &str, events: &[(u64, K, i32)]) -> Vec<OutputEvent> {
+        let config = format!(
+            "
+keyboards:
+  Test:
+    layout: qwerty
+    mappings: {{}}
+    layers:
+      Navigation:
+        {}: true
+        KEY_RIGHTALT:
+          KEY_J: [KEY_DOWN]
+",
+            option
+        );
+        run(&config, events)
+    }
+
+    /// `count` taps of the trigger from `start`, 60 ms apart, then `KEY_J` tapped
+    fn taps_then_j(start: u64, count: u64) -> Vec<(u64, K, i32)> {
+        let mut events = Vec::new();
+        for tap in 0..count {
+            let at = start + tap * 60;
+            events.push((at, K::KEY_RIGHTALT, PRESS));
+            events.push((at + 30, K::KEY_RIGHTALT, RELEASE));
+        }
+        let at = start + count * 60;
+        events.push((at, K::KEY_J, PRESS));
+        events.push((at + 30, K::KEY_J, RELEASE));
+        events
+    }
+
+    #[test]
+    fn tap_toggle_layer_is_momentary_while_held() {
+        let output = run_layer(
+            "tap_toggle",
+            &[
+                (0, K::KEY_RIGHTALT, PRESS),
+                (20, K::KEY_J, PRESS),
+                (40, K::KEY_J, RELEASE),
+                (60, K::KEY_RIGHTALT, RELEASE),
+                (100, K::KEY_J, PRESS),
+                (120, K::KEY_J, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_DOWN),
+                Release(K::KEY_DOWN),
+                Press(K::KEY_J),
+                Release(K::KEY_J),
+            ]
+        );
+    }
+
+    #[test]
+    fn tap_toggle_layer_latches_after_enough_taps() {
+        let mut events = taps_then_j(0, 4);
+        assert_eq!(
+            run_layer("tap_toggle", &events),
+            [Press(K::KEY_J), Release(K::KEY_J)]
+        );
+
+        events = taps_then_j(0, 5);
+        events.extend(taps_then_j(1000, 1));
+        assert_eq!(
+            run_layer("tap_toggle", &events),
+            [
+                Press(K::KEY_DOWN),
+                Release(K::KEY_DOWN),
+                Press(K::KEY_J),
+                Release(K::KEY_J),
+            ]
+        );
+    }
+
+    const TAP_TRIGGER: &str = "
+keyboards:
+  Test:
+    layout: qwerty
+    mappings: {}
+    layers:
+      Navigation:
+        tap: [KEY_SPACE]
+        KEY_RIGHTALT:
+          KEY_J: [KEY_DOWN]
+";
+
+    #[test]
+    fn trigger_tapped_quickly_types_its_tap() {
+        let output = run(
+            TAP_TRIGGER,
+            &[(0, K::KEY_RIGHTALT, PRESS), (30, K::KEY_RIGHTALT, RELEASE)],
+        );
+        assert_eq!(output, [Press(K::KEY_SPACE), Release(K::KEY_SPACE)]);
+    }
+
+    #[test]
+    fn trigger_held_activates_the_layer_without_its_tap() {
+        let alone = run(
+            TAP_TRIGGER,
+            &[
+                (0, K::KEY_RIGHTALT, PRESS),
+                (1000, K::KEY_RIGHTALT, RELEASE),
+            ],
+        );
+        assert_eq!(alone, []);
+
+        let used = run(
+            TAP_TRIGGER,
+            &[
+                (0, K::KEY_RIGHTALT, PRESS),
+                (20, K::KEY_J, PRESS),
+                (40, K::KEY_J, RELEASE),
+                (60, K::KEY_RIGHTALT, RELEASE),
+            ],
+        );
+        assert_eq!(used, [Press(K::KEY_DOWN), Release(K::KEY_DOWN)]);
+    }
+
+    #[test]
+    fn double_tap_toggle_layer_is_momentary_while_held() {
+        let output = run_layer(
+            "double_tap_toggle",
+            &[
+                (0, K::KEY_RIGHTALT, PRESS),
+                (300, K::KEY_J, PRESS),
+                (320, K::KEY_J, RELEASE),
+                (340, K::KEY_RIGHTALT, RELEASE),
+                (400, K::KEY_J, PRESS),
+                (420, K::KEY_J, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_DOWN),
+                Release(K::KEY_DOWN),
+                Press(K::KEY_J),
+                Release(K::KEY_J),
+            ]
+        );
+    }
+
+    #[test]
+    fn double_tap_toggle_layer_latches_until_tapped_again() {
+        let single = taps_then_j(0, 1);
+        assert_eq!(
+            run_layer("double_tap_toggle", &single),
+            [Press(K::KEY_J), Release(K::KEY_J)]
+        );
+
+        let mut events = taps_then_j(0, 2);
+        events.extend(taps_then_j(1000, 0));
+        events.extend(taps_then_j(2000, 1));
+        assert_eq!(
+            run_layer("double_tap_toggle", &events),
+            [
+                Press(K::KEY_DOWN),
+                Release(K::KEY_DOWN),
+                Press(K::KEY_DOWN),
+                Release(K::KEY_DOWN),
+                Press(K::KEY_J),
+                Release(K::KEY_J),
+            ]
+        );
+    }
+
+    #[test]
+    fn double_tap_toggle_layer_needs_both_taps_in_time() {
+        // A tap then a hold stays momentary
+        let output = run_layer(
+            "double_tap_toggle",
+            &[
+                (0, K::KEY_RIGHTALT, PRESS),
+                (30, K::KEY_RIGHTALT, RELEASE),
+                (60, K::KEY_RIGHTALT, PRESS),
+                (300, K::KEY_J, PRESS),
+                (320, K::KEY_J, RELEASE),
+                (400, K::KEY_RIGHTALT, RELEASE),
+                (500, K::KEY_J, PRESS),
+                (520, K::KEY_J, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_DOWN),
+                Release(K::KEY_DOWN),
+                Press(K::KEY_J),
+                Release(K::KEY_J),
+            ]
+        );
+
+        // Taps further apart than the double tap timeout don't latch
+        let mut events = taps_then_j(0, 1);
+        events.extend(taps_then_j(500, 1));
+        assert_eq!(
+            run_layer("double_tap_toggle", &events),
+            [
+                Press(K::KEY_J),
+                Release(K::KEY_J),
+                Press(K::KEY_J),
+                Release(K::KEY_J)
+            ]
+        );
+    }
+}
@@ -0,0 +1,310 @@
+mod auto_shift;
+mod caps_word;
+mod combos;
+mod compose;
+mod dual_function;
+mod keylock;
+mod layers;
+mod mouse_keys;
+mod repeat_key;
+mod swap_hands;
+mod tapdance;
+
+pub(crate) use auto_shift::AutoShiftFeature;
+pub(crate) use caps_word::CapsWordFeature;
+pub(crate) use combos::CombosFeature;
+pub(crate) use compose::ComposeFeature;
+pub(crate) use dual_function::DualFunctionFeature;
+pub(crate) use keylock::KeyLockFeature;
+pub(crate) use layers::LayersFeature;
+pub(crate) use mouse_keys::MouseKeysFeature;
+pub(crate) use repeat_key::RepeatKeyFeature;
+pub(crate) use swap_hands::SwapHandsFeature;
+pub(crate) use tapdance::TapDanceFeature;
+
+use crate::config::{Hand, KeyboardConfig, Layer, LayerAction, LayerMarker, Led};
+use crate::io::{LedSink, Output};
+use crate::layouts;
+use anyhow::Result;
+use evdev::KeyCode;
+use log::warn;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::Instant;
+
+pub const RELEASE: i32 = 0;
+pub const PRESS: i32 = 1;
+pub const REPEAT: i32 = 2;
+
+/// Key event after layout translation
+#[derive(Debug, Clone, Copy)]
+pub struct KeyEvent {
+    pub key: KeyCode,
+    /// `PRESS`, `RELEASE` or the kernel's autorepeat `REPEAT`
+    pub state: i32,
+    /// When the kernel saw the event, timing decisions use this rather than when it's processed
+    pub time: Instant,
+}
+
+/// How a feature handled a key event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handled {
+    /// The key passes through unless another feature handles it
+    No,
+    /// The key doesn't pass through, later features still see it
+    Yes,
+    /// The key doesn't pass through and later features don't see it
+    Stop,
+}
+
+/// Per keyboard state shared by all features
+pub struct Context {
+    pub output: Output,
+    pub config: KeyboardConfig,
+    pub keys_down: HashSet<KeyCode>,
+    pub holds_triggered: HashSet<KeyCode>,
+    pub active_layers: HashSet<String>,
+    /// Events a feature held back and releases again, processed by the features after it
+    pub replay: Vec<KeyEvent>,
+    /// Keys a layer presses for the current event, sent once every feature has seen it so the
+    /// holds it triggers go first
+    pub deferred: Vec<Vec<KeyCode>>,
+    /// The physical keyboard's LEDs, when a layer shows itself on one
+    pub leds: Option<Box<dyn LedSink>>,
+    /// Window class of the focused application, when an `app_command` is configured
+    pub app: Option<String>,
+    /// Profile a mapping switched to, every keyboard switches once the event is processed
+    pub profile: Option<String>,
+    /// Set by a mapping to reload the config once the event is processed
+    pub reload: bool,
+    /// Keys pass through untouched while set, shared with the other keyboards
+    pub bypass: Arc<AtomicBool>,
+}
+
+/// Used for processing key events
+pub trait Feature: Send {
+    /// Handle a key event
+    fn on_event(&mut self, ctx: &mut Context, event: &KeyEvent) -> Result<Handled>;
+
+    /// When the feature next needs `on_timer` to be called
+    fn next_timer(&self) -> Option<Instant> {
+        None
+    }
+
+    /// Called once the instant from `next_timer` has passed
+    fn on_timer(&mut self, _ctx: &mut Context, _now: Instant) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether the feature keeps keys pressed on purpose with no key held, the stuck key
+    /// watchdog leaves everything alone meanwhile
+    fn latched(&self) -> bool {
+        false
+    }
+
+    /// Counters kept for the life of the process, reported by `status`
+    fn stats(&self) -> Stats {
+        Stats::new()
+    }
+}
+
+pub type Stats = BTreeMap<&'static str, u64>;
+
+/// Hand that types a key, the keyboard's `hands` override the built-in physical positions
+pub(crate) fn key_hand(ctx: &Context, key: &KeyCode) -> Option<Hand> {
+    ctx.config
+        .hands
+        .get(key)
+        .copied()
+        .or_else(|| layouts::hand(&ctx.output.layout().from(key)))
+}
+
+/// Lights the LEDs of the active layers and turns off the rest
+pub fn update_leds(ctx: &mut Context) {
+    let Some(leds) = ctx.leds.as_mut() else {
+        return;
+    };
+    // Layers can share an LED, it's lit while any of them is active
+    let mut states: HashMap<Led, bool> = HashMap::new();
+    for (layer_name, layer) in &ctx.config.layers {
+        if let Some(led) = layer.led {
+            *states.entry(led).or_default() |=
+                layer.default || ctx.active_layers.contains(layer_name);
+        }
+    }
+    for (led, on) in states {
+        if let Err(e) = leds.set(led, on) {
+            warn!("Failed to set {:?} LED: {}", led, e);
+        }
+    }
+}
+
+pub fn is_modifier(key: &KeyCode) -> bool {
+    matches!(
+        *key,
+        KeyCode::KEY_LEFTSHIFT
+            | KeyCode::KEY_RIGHTSHIFT
+            | KeyCode::KEY_LEFTCTRL
+            | KeyCode::KEY_RIGHTCTRL
+            | KeyCode::KEY_LEFTALT
+            | KeyCode::KEY_RIGHTALT
+            | KeyCode::KEY_LEFTMETA
+            | KeyCode::KEY_RIGHTMETA
+    )
+}
+
+pub(crate) fn is_layer_key(key: &KeyCode, kb_config: &KeyboardConfig) -> bool {
+    kb_config
+        .layers
+        .iter()
+        .any(|(_, layer)| layer.triggers.contains_key(key))
+        || kb_config
+            .mappings
+            .get(key)
+            .is_some_and(|remap| remap.layer.is_some())
+}
+
+/// Active layers in order of precedence, later declared first and the default layers last
+fn layers_by_precedence<'a>(
+    kb_config: &'a KeyboardConfig,
+    active_layers: &HashSet<String>,
+) -> impl Iterator<Item = &'a Layer> {
+    let layers = kb_config.layers.iter().rev();
+    layers
+        .clone()
+        .filter(|(layer_name, layer)| !layer.default && active_layers.contains(*layer_name))
+        .chain(layers.filter(|(_, layer)| layer.default))
+        .map(|(_, layer)| layer)
+}
+
+/// Action the active layers map a key to, later declared layers take precedence and
+/// transparent keys fall through to the layers below
+pub(crate) fn layer_action<'a>(
+    kb_config: &'a KeyboardConfig,
+    active_layers: &HashSet<String>,
+    key: &KeyCode,
+) -> Option<&'a LayerAction> {
+    layers_by_precedence(kb_config, active_layers)
+        .filter_map(|layer| layer.action(key))
+        .find(|action| !matches!(action, LayerAction::Marker(LayerMarker::Transparent)))
+}
+
+/// Modifiers the active layers apply to a key, from each layer mapping it down to the one
+/// whose action it takes
+pub(crate) fn layer_mods(
+    kb_config: &KeyboardConfig,
+    active_layers: &HashSet<String>,
+    key: &KeyCode,
+) -> Vec<KeyCode> {
+    let mut mods = Vec::new();
+    for layer in layers_by_precedence(kb_config, active_layers) {
+        let Some(action) = layer.action(key) else {
+            continue;
+        };
+        for m in &layer.apply_mods {
+            if !mods.contains(m) {
+                mods.push(*m);
+            }
+        }
+        if !matches!(action, LayerAction::Marker(LayerMarker::Transparent)) {
+            break;
+        }
+    }
+    mods
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::from_yaml;
+    use crate::io::OutputEvent::{Press, Release};
+    use crate::pipeline::{simulate, tests::run};
+    use evdev::KeyCode as K;
+    use std::time::Duration;
+
+    const OVERLAPPING: &str = "
+keyboards:
+  Test:
+    layout: qwerty
+    mappings: {}
+    layers:
+      Navigation:
+        KEY_RIGHTALT:
+          KEY_J: [KEY_DOWN]
+          KEY_K: [KEY_UP]
+      Numbers:
+        KEY_LEFTALT:
+          KEY_J: [KEY_4]
+          KEY_K: transparent
+";
+
+    #[test]
+    fn later_declared_layer_wins_whichever_is_held_first() {
+        for (first, second) in [
+            (K::KEY_RIGHTALT, K::KEY_LEFTALT),
+            (K::KEY_LEFTALT, K::KEY_RIGHTALT),
+        ] {
+            let output = run(
+                OVERLAPPING,
+                &[
+                    (0, first, PRESS),
+                    (10, second, PRESS),
+                    (20, K::KEY_J, PRESS),
+                    (30, K::KEY_J, RELEASE),
+                    (40, second, RELEASE),
+                    (50, first, RELEASE),
+                ],
+            );
+            assert_eq!(output, [Press(K::KEY_4), Release(K::KEY_4)]);
+        }
+    }
+
+    #[test]
+    fn transparent_keys_fall_through_to_the_layer_below() {
+        let output = run(
+            OVERLAPPING,
+            &[
+                (0, K::KEY_RIGHTALT, PRESS),
+                (10, K::KEY_LEFTALT, PRESS),
+                (20, K::KEY_K, PRESS),
+                (30, K::KEY_K, RELEASE),
+                (40, K::KEY_LEFTALT, RELEASE),
+                (50, K::KEY_RIGHTALT, RELEASE),
+            ],
+        );
+        assert_eq!(output, [Press(K::KEY_UP), Release(K::KEY_UP)]);
+    }
+
+    #[test]
+    fn stalled_events_are_timed_from_when_they_happened() {
+        let config = from_yaml(
+            "
+keyboards:
+  Test:
+    layout: qwerty
+    layers: {}
+    mappings:
+      KEY_F: {tap: [KEY_F], hold: [KEY_LEFTCTRL], hrm_term: 200}
+",
+        )
+        .unwrap();
+        let (features, keyboard) = config.keyboard(None, "Test");
+        // Both queued for a second before they're processed, a tap all the same
+        let pressed = Instant::now() - Duration::from_secs(1);
+        let events = [
+            KeyEvent {
+                key: K::KEY_F,
+                state: PRESS,
+                time: pressed,
+            },
+            KeyEvent {
+                key: K::KEY_F,
+                state: RELEASE,
+                time: pressed + Duration::from_millis(50),
+            },
+        ];
+        let output = simulate(keyboard, &features, &events).unwrap();
+        assert_eq!(output, [Press(K::KEY_F), Release(K::KEY_F)]);
+    }
+}
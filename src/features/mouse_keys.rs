@@ -0,0 +1,157 @@
+use crate::config::{LayerAction, MouseAction};
+use crate::features::{Context, Feature, Handled, KeyEvent, PRESS, RELEASE, layer_action};
+use crate::io::OutputEvent;
+use anyhow::Result;
+use evdev::KeyCode;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Mouse Keys
+/// - Layer keys mapped to a mouse action control the pointer instead of emitting keys.
+/// - Movement repeats every mouse interval while the key is held.
+/// - Scrolling repeats every `interval` of its own while the key is held, if it has one.
+/// - Buttons are held while the key is held.
+#[derive(Default)]
+pub(crate) struct MouseKeysFeature {
+    held: HashMap<KeyCode, MouseAction>,
+    next_tick: Option<Instant>,
+    /// Held scroll keys that repeat, and when they next scroll
+    scrolling: HashMap<KeyCode, Instant>,
+}
+
+impl Feature for MouseKeysFeature {
+    fn on_event(&mut self, ctx: &mut Context, event: &KeyEvent) -> Result<Handled> {
+        let key = &event.key;
+
+        match event.state {
+            PRESS => {
+                let Some(LayerAction::Mouse(action)) =
+                    layer_action(&ctx.config, &ctx.active_layers, key)
+                else {
+                    return Ok(Handled::No);
+                };
+
+                match *action {
+                    MouseAction::Move { x, y } => {
+                        ctx.output
+                            .emit(&[OutputEvent::MouseMove { dx: x, dy: y }])?;
+                        if self.next_tick.is_none() {
+                            self.next_tick = Some(event.time + interval(ctx));
+                        }
+                    }
+                    MouseAction::Scroll { x, y, interval } => {
+                        ctx.output.emit(&[OutputEvent::Scroll { dx: x, dy: y }])?;
+                        if let Some(interval) = interval {
+                            self.scrolling
+                                .insert(*key, event.time + scroll_interval(interval));
+                        }
+                    }
+                    MouseAction::Button(button) => {
+                        ctx.output
+                            .emit(&[OutputEvent::MouseButton(button, PRESS)])?;
+                    }
+                }
+                self.held.insert(*key, *action);
+
+                Ok(Handled::Stop)
+            }
+            RELEASE => {
+                let Some(action) = self.held.remove(key) else {
+                    return Ok(Handled::No);
+                };
+
+                if let MouseAction::Button(button) = action {
+                    ctx.output
+                        .emit(&[OutputEvent::MouseButton(button, RELEASE)])?;
+                }
+                if !self.moving() {
+                    self.next_tick = None;
+                }
+                self.scrolling.remove(key);
+
+                Ok(Handled::Stop)
+            }
+            // Held mouse keys move on their own timer rather than with autorepeat
+            _ if self.held.contains_key(key) => Ok(Handled::Stop),
+            _ => Ok(Handled::No),
+        }
+    }
+
+    fn next_timer(&self) -> Option<Instant> {
+        self.next_tick
+            .into_iter()
+            .chain(self.scrolling.values().copied())
+            .min()
+    }
+
+    fn on_timer(&mut self, ctx: &mut Context, now: Instant) -> Result<()> {
+        if self.next_tick.is_some_and(|tick| tick <= now) {
+            let (dx, dy) = self
+                .held
+                .values()
+                .fold((0, 0), |(dx, dy), action| match action {
+                    MouseAction::Move { x, y } => (dx + x, dy + y),
+                    _ => (dx, dy),
+                });
+            ctx.output.emit(&[OutputEvent::MouseMove { dx, dy }])?;
+            self.next_tick = Some(now + interval(ctx));
+        }
+
+        for (key, next) in self.scrolling.iter_mut().filter(|(_, next)| **next <= now) {
+            if let Some(MouseAction::Scroll { x, y, interval }) = self.held.get(key) {
+                ctx.output.emit(&[OutputEvent::Scroll { dx: *x, dy: *y }])?;
+                *next = now + scroll_interval(interval.unwrap_or_default());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MouseKeysFeature {
+    fn moving(&self) -> bool {
+        self.held
+            .values()
+            .any(|action| matches!(action, MouseAction::Move { .. }))
+    }
+}
+
+fn interval(ctx: &Context) -> Duration {
+    Duration::from_millis(ctx.config.mouse_interval.unwrap_or(16).max(1) as u64)
+}
+
+fn scroll_interval(interval: u16) -> Duration {
+    Duration::from_millis(interval.max(1) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::features::{PRESS, RELEASE};
+    use crate::io::OutputEvent::MouseMove;
+    use crate::pipeline::tests::run;
+    use evdev::KeyCode as K;
+
+    #[test]
+    fn held_move_key_repeats_until_released() {
+        let output = run(
+            "
+keyboards:
+  Test:
+    layout: qwerty
+    mappings: {}
+    mouse_interval: 50
+    layers:
+      Mouse:
+        KEY_RIGHTALT:
+          KEY_J: {move: {x: 0, y: 10}}
+",
+            &[
+                (0, K::KEY_RIGHTALT, PRESS),
+                (100, K::KEY_J, PRESS),
+                (220, K::KEY_J, RELEASE),
+                (240, K::KEY_RIGHTALT, RELEASE),
+            ],
+        );
+        // Moved on press, then at 150 and 200 ms, and not again once released
+        assert_eq!(output, vec![MouseMove { dx: 0, dy: 10 }; 3]);
+    }
+}
@@ -0,0 +1,67 @@
+use crate::features::{Context, Feature, Handled, KeyEvent, PRESS, RELEASE, Stats, is_modifier};
+use anyhow::Result;
+use colored::Colorize;
+use evdev::KeyCode;
+use log::debug;
+use std::collections::HashMap;
+
+/// Repeat Key
+/// - Pressing a `repeat` key presses the last key sent again, along with the modifiers that
+///   were held with it, whichever feature sent it.
+/// - Modifiers already held aren't pressed again, so they aren't released early either.
+/// - The repeat key never becomes the last key itself, so it can be tapped over and over.
+#[derive(Default)]
+pub(crate) struct RepeatKeyFeature {
+    /// Keys each repeat key pressed, released along with it
+    pressed: HashMap<KeyCode, Vec<KeyCode>>,
+    repeats: u64,
+}
+
+impl Feature for RepeatKeyFeature {
+    fn on_event(&mut self, ctx: &mut Context, event: &KeyEvent) -> Result<Handled> {
+        let key = &event.key;
+
+        if ctx.config.mappings.get(key).and_then(|remap| remap.repeat) != Some(true) {
+            return Ok(Handled::No);
+        }
+
+        match event.state {
+            PRESS => {
+                let last = ctx.output.last().to_vec();
+                let keys: Vec<KeyCode> = last
+                    .iter()
+                    .filter(|last_key| !ctx.output.is_pressed(last_key))
+                    .copied()
+                    .collect();
+                if !keys.is_empty() {
+                    debug!("{}: {:?}", "REPEAT".purple(), last);
+                    ctx.output.send_keys(&keys, PRESS)?;
+                    self.repeats += 1;
+                }
+                // Modifiers held now would otherwise become part of what's repeated next
+                ctx.output.set_last(last);
+                self.pressed.insert(*key, keys);
+            }
+            RELEASE => {
+                if let Some(keys) = self.pressed.remove(key).filter(|keys| !keys.is_empty()) {
+                    ctx.output.send_keys(&keys, RELEASE)?;
+                }
+            }
+            state => {
+                if let Some(keys) = self.pressed.get(key) {
+                    let keys: Vec<KeyCode> =
+                        keys.iter().filter(|k| !is_modifier(k)).copied().collect();
+                    if !keys.is_empty() {
+                        ctx.output.send_keys(&keys, state)?;
+                    }
+                }
+            }
+        }
+
+        Ok(Handled::Stop)
+    }
+
+    fn stats(&self) -> Stats {
+        Stats::from([("repeats", self.repeats)])
+    }
+}
@@ -0,0 +1,188 @@
+use crate::features::{Context, Feature, Handled, KeyEvent, PRESS, RELEASE, Stats};
+use crate::layouts;
+use anyhow::Result;
+use colored::Colorize;
+use evdev::KeyCode;
+use log::debug;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Swap Hands
+/// - Holding a `swap_hands` key mirrors the number and letter rows, each key acting as the one
+///   in the mirror image position on the other hand, for typing with one hand.
+/// - Keys are mirrored by their physical position, whatever the layout, and every later
+///   feature sees the mirror image key.
+/// - A key pressed while mirrored releases as its mirror image, even if the swap ended first.
+/// - Tapping the `swap_hands` key within the toggle term types its `tap`, unless another key
+///   was pressed.
+#[derive(Default)]
+pub(crate) struct SwapHandsFeature {
+    trigger: Option<Trigger>,
+    /// Keys pressed while mirrored, and the mirror image key they pressed
+    mirrored: HashMap<KeyCode, KeyCode>,
+    swaps: u64,
+}
+
+struct Trigger {
+    key: KeyCode,
+    deadline: Instant,
+    used: bool,
+}
+
+impl Feature for SwapHandsFeature {
+    fn on_event(&mut self, ctx: &mut Context, event: &KeyEvent) -> Result<Handled> {
+        let key = &event.key;
+
+        if let Some(remap) = ctx
+            .config
+            .mappings
+            .get(key)
+            .filter(|remap| remap.swap_hands == Some(true))
+        {
+            match event.state {
+                PRESS => {
+                    debug!("{}", "SWAP HANDS".purple());
+                    ctx.keys_down.insert(*key);
+                    let toggle_term = ctx.config.toggle_term.unwrap_or(0);
+                    self.trigger = Some(Trigger {
+                        key: *key,
+                        deadline: event.time + Duration::from_millis(toggle_term as u64),
+                        used: false,
+                    });
+                    self.swaps += 1;
+                }
+                RELEASE => {
+                    ctx.keys_down.remove(key);
+                    let tapped = self
+                        .trigger
+                        .take_if(|trigger| trigger.key == *key)
+                        .is_some_and(|trigger| !trigger.used && event.time < trigger.deadline);
+                    if tapped && let Some(tap_keys) = &remap.tap {
+                        ctx.output.send_keys(tap_keys, PRESS)?;
+                        ctx.output.send_keys(tap_keys, RELEASE)?;
+                    }
+                }
+                _ => {}
+            }
+            return Ok(Handled::Stop);
+        }
+
+        let mirror = match event.state {
+            PRESS => {
+                let mirror = self.trigger.as_mut().and_then(|trigger| {
+                    trigger.used = true;
+                    mirror_key(ctx, key)
+                });
+                if let Some(mirror) = mirror {
+                    self.mirrored.insert(*key, mirror);
+                }
+                mirror
+            }
+            RELEASE => self.mirrored.remove(key),
+            _ => self.mirrored.get(key).copied(),
+        };
+        let Some(mirror) = mirror else {
+            return Ok(Handled::No);
+        };
+
+        ctx.replay.push(KeyEvent {
+            key: mirror,
+            ..*event
+        });
+        Ok(Handled::Stop)
+    }
+
+    fn stats(&self) -> Stats {
+        Stats::from([("swaps", self.swaps)])
+    }
+}
+
+/// The key in the mirror image position of a key, in the keyboard's layout
+fn mirror_key(ctx: &Context, key: &KeyCode) -> Option<KeyCode> {
+    let layout = ctx.output.layout();
+    layouts::mirror(&layout.from(key)).map(|mirror| layout.to(&mirror))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::features::{PRESS, RELEASE};
+    use crate::io::OutputEvent::{self, Press, Release};
+    use crate::pipeline::tests::run;
+    use evdev::KeyCode as K;
+
+    /// Swap hands on `KEY_SPACE`, tapping it types a space, in the given layout
+    fn run_swap(layout: &str, events: &[(u64, K, i32)]) -> Vec<OutputEvent> {
+        let config = format!(
+            "
+keyboards:
+  Test:
+    layout: {}
+    layers: {{}}
+    mappings:
+      KEY_SPACE: {{tap: [KEY_SPACE], swap_hands: true}}
+",
+            layout
+        );
+        run(&config, events)
+    }
+
+    /// `key` tapped while `KEY_SPACE` is held
+    fn swapped(key: K) -> [(u64, K, i32); 4] {
+        [
+            (0, K::KEY_SPACE, PRESS),
+            (20, key, PRESS),
+            (40, key, RELEASE),
+            (60, K::KEY_SPACE, RELEASE),
+        ]
+    }
+
+    #[test]
+    fn left_hand_key_types_its_right_hand_mirror() {
+        assert_eq!(
+            run_swap("qwerty", &swapped(K::KEY_F)),
+            [Press(K::KEY_J), Release(K::KEY_J)]
+        );
+        assert_eq!(
+            run_swap("qwerty", &swapped(K::KEY_Q)),
+            [Press(K::KEY_P), Release(K::KEY_P)]
+        );
+    }
+
+    #[test]
+    fn keys_mirror_by_position_in_dvorak() {
+        // Dvorak's U sits where Qwerty's F does, so it mirrors to the H under Qwerty's J
+        assert_eq!(
+            run_swap("dvorak", &swapped(K::KEY_U)),
+            run_swap("dvorak", &[(0, K::KEY_H, PRESS), (20, K::KEY_H, RELEASE)])
+        );
+        assert_eq!(
+            run_swap("dvorak", &swapped(K::KEY_U)),
+            [Press(K::KEY_J), Release(K::KEY_J)]
+        );
+    }
+
+    #[test]
+    fn tapped_alone_types_its_tap() {
+        assert_eq!(
+            run_swap(
+                "qwerty",
+                &[(0, K::KEY_SPACE, PRESS), (50, K::KEY_SPACE, RELEASE)]
+            ),
+            [Press(K::KEY_SPACE), Release(K::KEY_SPACE)]
+        );
+    }
+
+    #[test]
+    fn key_pressed_while_swapped_releases_as_its_mirror() {
+        let events = [
+            (0, K::KEY_SPACE, PRESS),
+            (20, K::KEY_F, PRESS),
+            (40, K::KEY_SPACE, RELEASE),
+            (60, K::KEY_F, RELEASE),
+        ];
+        assert_eq!(
+            run_swap("qwerty", &events),
+            [Press(K::KEY_J), Release(K::KEY_J)]
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use crate::features::{Context, Feature, Handled, KeyEvent, PRESS, RELEASE, REPEAT, is_modifier};
+use anyhow::Result;
+use evdev::KeyCode;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+struct TapDanceState {
+    key: KeyCode,
+    tap_count: usize,
+    key_down: bool,
+    held_keys: Option<Vec<KeyCode>>,
+    deadline: Option<Instant>,
+}
+
+/// Tap Dance
+/// - Tapping a key repeatedly emits the sequence for that tap count once the timeout passes.
+/// - Reaching the last sequence emits it on release without waiting.
+/// - Holding the key when the dance resolves holds the sequence down until released, repeating
+///   its keys other than modifiers.
+/// - Pressing any other key resolves the dance immediately.
+#[derive(Default)]
+pub(crate) struct TapDanceFeature {
+    dance: Option<TapDanceState>,
+}
+
+impl Feature for TapDanceFeature {
+    fn on_event(&mut self, ctx: &mut Context, event: &KeyEvent) -> Result<Handled> {
+        let key = &event.key;
+        let state = event.state;
+
+        let Some(taps) = ctx
+            .config
+            .mappings
+            .get(key)
+            .and_then(|remap| remap.taps.as_ref())
+        else {
+            if state == PRESS {
+                self.resolve(ctx)?;
+            }
+            return Ok(Handled::No);
+        };
+        let tap_count_max = taps.len();
+        let timeout = Duration::from_millis(ctx.config.tapdance_timeout.unwrap_or(0) as u64);
+
+        match state {
+            PRESS => {
+                if self.dance.as_ref().is_some_and(|dance| dance.key != *key) {
+                    self.resolve(ctx)?;
+                }
+
+                let dance = self.dance.get_or_insert(TapDanceState {
+                    key: *key,
+                    tap_count: 0,
+                    key_down: false,
+                    held_keys: None,
+                    deadline: None,
+                });
+                dance.tap_count += 1;
+                dance.key_down = true;
+                dance.deadline = Some(event.time + timeout);
+            }
+            RELEASE => {
+                if let Some(dance) = self.dance.as_mut()
+                    && dance.key == *key
+                {
+                    dance.key_down = false;
+
+                    if let Some(held_keys) = dance.held_keys.take() {
+                        ctx.output.send_keys(&held_keys, RELEASE)?;
+                        self.dance = None;
+                    } else if dance.tap_count >= tap_count_max {
+                        self.resolve(ctx)?;
+                    } else {
+                        dance.deadline = Some(event.time + timeout);
+                    }
+                }
+            }
+            // Repeats before the dance resolves are dropped, the timeout decides it
+            REPEAT => {
+                if let Some(held_keys) = self
+                    .dance
+                    .as_ref()
+                    .filter(|dance| dance.key == *key)
+                    .and_then(|dance| dance.held_keys.as_ref())
+                {
+                    let repeated: Vec<KeyCode> = held_keys
+                        .iter()
+                        .filter(|k| !is_modifier(k))
+                        .copied()
+                        .collect();
+                    ctx.output.send_keys(&repeated, REPEAT)?;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(Handled::Yes)
+    }
+
+    fn next_timer(&self) -> Option<Instant> {
+        self.dance.as_ref().and_then(|dance| dance.deadline)
+    }
+
+    fn on_timer(&mut self, ctx: &mut Context, _now: Instant) -> Result<()> {
+        self.resolve(ctx)
+    }
+}
+
+impl TapDanceFeature {
+    /// Emits the sequence for the current tap count, holding it if the key is still down
+    fn resolve(&mut self, ctx: &mut Context) -> Result<()> {
+        let Some(dance) = self.dance.as_mut() else {
+            return Ok(());
+        };
+        if dance.held_keys.is_some() {
+            return Ok(());
+        }
+
+        let keys = ctx
+            .config
+            .mappings
+            .get(&dance.key)
+            .and_then(|remap| remap.taps.as_ref())
+            .and_then(|taps| taps.get(dance.tap_count.min(taps.len()).saturating_sub(1)))
+            .cloned()
+            .unwrap_or_default();
+
+        if dance.key_down {
+            ctx.output.send_keys(&keys, PRESS)?;
+            dance.held_keys = Some(keys);
+            dance.deadline = None;
+        } else {
+            ctx.output.send_keys(&keys, PRESS)?;
+            ctx.output.send_keys(&keys, RELEASE)?;
+            self.dance = None;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::features::{PRESS, RELEASE, REPEAT};
+    use crate::io::OutputEvent::{Press, Release, Repeat};
+    use crate::pipeline::tests::run;
+    use evdev::KeyCode as K;
+
+    const CONFIG: &str = "
+keyboards:
+  Test:
+    layout: qwerty
+    layers: {}
+    tapdance_timeout: 200
+    mappings:
+      KEY_Q:
+        taps:
+          - [KEY_ESC]
+          - [KEY_LEFTCTRL, KEY_C]
+";
+
+    #[test]
+    fn single_tap_emits_the_first_sequence_after_the_timeout() {
+        let output = run(
+            CONFIG,
+            &[
+                (0, K::KEY_Q, PRESS),
+                (50, K::KEY_Q, RELEASE),
+                (400, K::KEY_A, PRESS),
+            ],
+        );
+        assert_eq!(
+            output,
+            [Press(K::KEY_ESC), Release(K::KEY_ESC), Press(K::KEY_A)]
+        );
+    }
+
+    #[test]
+    fn double_tap_emits_the_last_sequence_on_release() {
+        let output = run(
+            CONFIG,
+            &[
+                (0, K::KEY_Q, PRESS),
+                (50, K::KEY_Q, RELEASE),
+                (100, K::KEY_Q, PRESS),
+                (150, K::KEY_Q, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_LEFTCTRL),
+                Press(K::KEY_C),
+                Release(K::KEY_C),
+                Release(K::KEY_LEFTCTRL),
+            ]
+        );
+    }
+
+    #[test]
+    fn last_tap_held_repeats_until_released() {
+        let output = run(
+            CONFIG,
+            &[
+                (0, K::KEY_Q, PRESS),
+                (50, K::KEY_Q, RELEASE),
+                (100, K::KEY_Q, PRESS),
+                (250, K::KEY_Q, REPEAT),
+                (400, K::KEY_Q, REPEAT),
+                (450, K::KEY_Q, REPEAT),
+                (500, K::KEY_Q, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_LEFTCTRL),
+                Press(K::KEY_C),
+                Repeat(K::KEY_C),
+                Repeat(K::KEY_C),
+                Release(K::KEY_C),
+                Release(K::KEY_LEFTCTRL),
+            ]
+        );
+    }
+}
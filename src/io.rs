@@ -0,0 +1,852 @@
+use crate::config::{Led, MacroStep, MouseButton};
+use crate::features::{PRESS, RELEASE, REPEAT, is_modifier};
+use crate::layouts::Layout;
+use anyhow::Result;
+use colored::{ColoredString, Colorize};
+use evdev::{EventType, KeyCode, RelativeAxisCode};
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub(crate) const EV_KEY: i32 = 1;
+
+/// Event written to the virtual device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputEvent {
+    Press(KeyCode),
+    Release(KeyCode),
+    /// Autorepeat of a held key
+    Repeat(KeyCode),
+    PressMany(Vec<KeyCode>),
+    ReleaseMany(Vec<KeyCode>),
+    MouseMove {
+        dx: i32,
+        dy: i32,
+    },
+    MouseButton(MouseButton, i32),
+    Scroll {
+        dx: i32,
+        dy: i32,
+    },
+    /// Pause before the next event, capped at `MAX_DELAY` as it holds up the keyboard's thread
+    Delay(Duration),
+    /// `SYN_REPORT` ending a frame, as the mock device records them. The other events end their
+    /// own frames, so emitting one sends it on its own
+    Sync,
+}
+
+/// Longest a single delay can pause a keyboard, its keys wait until it's over
+pub(crate) const MAX_DELAY: Duration = Duration::from_secs(1);
+
+/// How many times each modifier is held on a virtual device, by every output writing to it
+pub type ModHolds = Arc<Mutex<HashMap<KeyCode, u32>>>;
+
+/// Where raw events are written, the virtual device or a mock that records them
+pub trait EventSink: Send {
+    fn write(&mut self, kind: i32, code: i32, value: i32) -> Result<()>;
+    fn synchronize(&mut self) -> Result<()>;
+
+    /// Modifier holds of the other outputs writing to the same device, none when the output
+    /// has the device to itself
+    fn mod_holds(&self) -> Option<ModHolds> {
+        None
+    }
+}
+
+/// Where the layers show themselves, the physical keyboard's LEDs in the binary
+pub trait LedSink: Send {
+    fn set(&mut self, led: Led, on: bool) -> Result<()>;
+
+    /// Puts the LEDs back to how they were before any was set
+    fn restore(&mut self) -> Result<()>;
+}
+
+/// Logs the events that would have been written, for trying a config out on a live machine
+pub struct DryRunDevice;
+
+impl EventSink for DryRunDevice {
+    fn write(&mut self, kind: i32, code: i32, value: i32) -> Result<()> {
+        if kind == EV_KEY {
+            info!(
+                "{} {}: {:?}",
+                state_arrow(value),
+                "DRY RUN".yellow(),
+                KeyCode(code as u16)
+            );
+        } else {
+            info!(
+                "{} {:?}: {}",
+                "DRY RUN".yellow(),
+                RelativeAxisCode(code as u16),
+                value
+            );
+        }
+        Ok(())
+    }
+
+    fn synchronize(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Records the events written instead of sending them, shared with whoever reads them back
+#[derive(Debug, Clone, Default)]
+pub struct MockDevice {
+    events: Arc<Mutex<Vec<OutputEvent>>>,
+    /// Relative axes written since the last sync, as they're reported together
+    relative: HashMap<RelativeAxisCode, i32>,
+}
+
+impl MockDevice {
+    /// The events written so far, with each sync's relative axes as one movement
+    pub fn events(&self) -> Vec<OutputEvent> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| **event != OutputEvent::Sync)
+            .cloned()
+            .collect()
+    }
+
+    /// The events written so far with a `Sync` where each frame ended
+    pub fn frames(&self) -> Vec<OutputEvent> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+impl EventSink for MockDevice {
+    fn write(&mut self, kind: i32, code: i32, value: i32) -> Result<()> {
+        if kind == EventType::RELATIVE.0 as i32 {
+            *self
+                .relative
+                .entry(RelativeAxisCode(code as u16))
+                .or_default() += value;
+        } else if kind == EV_KEY {
+            let key = KeyCode(code as u16);
+            let event = match value {
+                PRESS => OutputEvent::Press(key),
+                RELEASE => OutputEvent::Release(key),
+                _ => OutputEvent::Repeat(key),
+            };
+            self.events.lock().unwrap().push(event);
+        }
+        Ok(())
+    }
+
+    fn synchronize(&mut self) -> Result<()> {
+        let mut axis = |code| self.relative.remove(&code).unwrap_or_default();
+        let (x, y) = (axis(RelativeAxisCode::REL_X), axis(RelativeAxisCode::REL_Y));
+        let (hwheel, wheel) = (
+            axis(RelativeAxisCode::REL_HWHEEL),
+            axis(RelativeAxisCode::REL_WHEEL),
+        );
+        let mut events = self.events.lock().unwrap();
+        if x != 0 || y != 0 {
+            events.push(OutputEvent::MouseMove { dx: x, dy: y });
+        }
+        if hwheel != 0 || wheel != 0 {
+            events.push(OutputEvent::Scroll {
+                dx: hwheel,
+                dy: wheel,
+            });
+        }
+        events.push(OutputEvent::Sync);
+        Ok(())
+    }
+}
+
+/// Virtual keyboard output, resolving logical keys through the active layout
+pub struct Output {
+    device: Box<dyn EventSink>,
+    layout: Box<dyn Layout>,
+    /// Keys pressed on the virtual device and the key they resolved to, so they release under
+    /// the layout they were pressed with
+    pressed: HashMap<KeyCode, KeyCode>,
+    /// How many times this output holds each modifier, features can hold the same one
+    mod_holds: HashMap<KeyCode, u32>,
+    /// How many times each modifier is held by every output writing to the device, it's only
+    /// pressed by the first hold and released by the last
+    shared_holds: ModHolds,
+    /// The last key pressed along with the modifiers held at the time, for the repeat key
+    last: Vec<KeyCode>,
+    /// Whether the keys of a send share one frame, modifiers included
+    same_frame: bool,
+    /// Keys pressed by the keys or events being sent and not released yet, released again if
+    /// writing fails partway through
+    emitting: Option<Vec<KeyCode>>,
+}
+
+impl Output {
+    pub fn new(device: Box<dyn EventSink>, layout: Box<dyn Layout>) -> Self {
+        let shared_holds = device.mod_holds().unwrap_or_default();
+        Self {
+            device,
+            layout,
+            pressed: HashMap::new(),
+            mod_holds: HashMap::new(),
+            shared_holds,
+            last: Vec::new(),
+            same_frame: false,
+            emitting: None,
+        }
+    }
+
+    pub fn layout(&self) -> &dyn Layout {
+        self.layout.as_ref()
+    }
+
+    pub fn set_layout(&mut self, layout: Box<dyn Layout>) {
+        self.layout = layout;
+    }
+
+    pub fn set_same_frame(&mut self, same_frame: bool) {
+        self.same_frame = same_frame;
+    }
+
+    /// Whether a key is currently pressed on the virtual device
+    pub fn is_pressed(&self, key: &KeyCode) -> bool {
+        self.pressed.contains_key(key)
+    }
+
+    /// Keys currently pressed on the virtual device
+    pub fn pressed(&self) -> Vec<KeyCode> {
+        self.pressed.keys().copied().collect()
+    }
+
+    /// The last key pressed with the modifiers that were held, empty if none has been yet
+    pub fn last(&self) -> &[KeyCode] {
+        &self.last
+    }
+
+    pub fn set_last(&mut self, keys: Vec<KeyCode>) {
+        self.last = keys;
+    }
+
+    pub fn send_key(&mut self, key: &KeyCode, state: i32) -> Result<()> {
+        self.write_key(key, state)?;
+        self.device.synchronize()?;
+        log_key(key, state);
+        Ok(())
+    }
+
+    /// Sends the keys resolved through another layout, they still release under it
+    pub fn send_keys_in(
+        &mut self,
+        keys: &[KeyCode],
+        state: i32,
+        layout: &mut Box<dyn Layout>,
+    ) -> Result<()> {
+        std::mem::swap(&mut self.layout, layout);
+        let result = self.send_keys(keys, state);
+        std::mem::swap(&mut self.layout, layout);
+        result
+    }
+
+    /// Sends the keys as one action, with the modifiers synchronized on their own so apps see
+    /// them pressed before the keys they modify and released after. With `same_frame` they're
+    /// all synchronized together instead, still written in that order
+    pub fn send_keys(&mut self, keys: &[KeyCode], state: i32) -> Result<()> {
+        self.releasing_on_error(|output| output.write_keys(keys, state))
+    }
+
+    fn write_keys(&mut self, keys: &[KeyCode], state: i32) -> Result<()> {
+        let (mods, others): (Vec<KeyCode>, Vec<KeyCode>) =
+            keys.iter().partition(|key| is_modifier(key));
+        let reports = match state {
+            PRESS if self.same_frame => [[mods, others].concat(), Vec::new()],
+            RELEASE if self.same_frame => [[others, mods].concat(), Vec::new()],
+            PRESS => [mods, others],
+            RELEASE => [others, mods],
+            _ => [keys.to_vec(), Vec::new()],
+        };
+        for report in reports.iter().filter(|report| !report.is_empty()) {
+            for key in report {
+                self.write_key(key, state)?;
+            }
+            self.device.synchronize()?;
+        }
+        log_keys(keys, state);
+        Ok(())
+    }
+
+    /// Sends the events in order. If writing one fails, the keys the events already pressed
+    /// are released before the error is returned, so none are left stuck
+    pub fn emit(&mut self, events: &[OutputEvent]) -> Result<()> {
+        self.releasing_on_error(|output| output.emit_events(events))
+    }
+
+    /// Runs the writes, releasing the keys they pressed if one fails before returning its
+    /// error. Writes nested in others leave it to the outermost
+    fn releasing_on_error(&mut self, writes: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        if self.emitting.is_some() {
+            return writes(self);
+        }
+        self.emitting = Some(Vec::new());
+        let result = writes(self);
+        let pressed = self.emitting.take().unwrap_or_default();
+        if result.is_err() && !pressed.is_empty() {
+            warn!(
+                "Writing failed, releasing the keys it pressed: {:?}",
+                pressed
+            );
+            for key in pressed.iter().rev() {
+                // Best effort, the error that stopped the writes is the one returned
+                let _ = self
+                    .write_key(key, RELEASE)
+                    .and_then(|_| self.device.synchronize());
+            }
+        }
+        result
+    }
+
+    fn emit_events(&mut self, events: &[OutputEvent]) -> Result<()> {
+        for event in events {
+            match event {
+                OutputEvent::Press(key) => self.send_key(key, PRESS)?,
+                OutputEvent::Release(key) => self.send_key(key, RELEASE)?,
+                OutputEvent::Repeat(key) => self.send_key(key, REPEAT)?,
+                OutputEvent::PressMany(keys) => self.send_keys(keys, PRESS)?,
+                OutputEvent::ReleaseMany(keys) => self.send_keys(keys, RELEASE)?,
+                OutputEvent::MouseMove { dx, dy } => self.send_relative(&[
+                    (RelativeAxisCode::REL_X, *dx),
+                    (RelativeAxisCode::REL_Y, *dy),
+                ])?,
+                OutputEvent::MouseButton(button, state) => {
+                    self.device.write(EV_KEY, button.code().0 as i32, *state)?;
+                    self.device.synchronize()?;
+                    log_key(&button.code(), *state);
+                }
+                OutputEvent::Scroll { dx, dy } => self.send_relative(&[
+                    (RelativeAxisCode::REL_HWHEEL, *dx),
+                    (RelativeAxisCode::REL_WHEEL, *dy),
+                ])?,
+                OutputEvent::Delay(delay) => {
+                    // Every event before has been synchronized, so apps see them before the pause
+                    if *delay > MAX_DELAY {
+                        warn!("Delay of {:?} capped to {:?}", delay, MAX_DELAY);
+                    }
+                    thread::sleep((*delay).min(MAX_DELAY));
+                }
+                OutputEvent::Sync => self.device.synchronize()?,
+            }
+        }
+        Ok(())
+    }
+
+    fn send_relative(&mut self, axes: &[(RelativeAxisCode, i32)]) -> Result<()> {
+        for (axis, value) in axes {
+            if *value != 0 {
+                self.device
+                    .write(EventType::RELATIVE.0 as i32, axis.0 as i32, *value)?;
+            }
+        }
+        self.device.synchronize()?;
+        debug!("{} {:?}", "MOUSE".yellow(), axes);
+        Ok(())
+    }
+
+    /// Types text one character at a time, skipping characters the layout can't type
+    pub fn send_text(&mut self, text: &str) -> Result<()> {
+        let events = self.text_events(text);
+        self.emit(&events)
+    }
+
+    fn text_events(&self, text: &str) -> Vec<OutputEvent> {
+        let mut events = Vec::new();
+        for c in text.chars() {
+            let Some((key, shift)) = self.layout.char_key(c) else {
+                warn!("No key for character {:?}", c);
+                continue;
+            };
+            let keys = if shift {
+                vec![KeyCode::KEY_LEFTSHIFT, key]
+            } else {
+                vec![key]
+            };
+            events.push(OutputEvent::PressMany(keys.clone()));
+            events.push(OutputEvent::ReleaseMany(keys));
+        }
+        events
+    }
+
+    /// Runs the steps of a macro in order
+    pub fn send_steps(&mut self, steps: &[MacroStep]) -> Result<()> {
+        let mut events = Vec::new();
+        for step in steps {
+            match step {
+                MacroStep::Tap { tap: keys } => {
+                    events.push(OutputEvent::PressMany(keys.clone()));
+                    events.push(OutputEvent::ReleaseMany(keys.clone()));
+                }
+                MacroStep::Text { text } => events.extend(self.text_events(text)),
+                MacroStep::Delay { delay } => {
+                    events.push(OutputEvent::Delay(Duration::from_millis(*delay as u64)))
+                }
+            }
+        }
+        self.emit(&events)
+    }
+
+    /// Enters whitespace separated hex code points, each typed as the prefix, the hex digits,
+    /// then the suffix
+    pub fn send_unicode(
+        &mut self,
+        code_points: &str,
+        prefix: &[KeyCode],
+        suffix: &[KeyCode],
+    ) -> Result<()> {
+        let mut events = Vec::new();
+
+        for code_point in code_points.split_whitespace() {
+            let hex = code_point.trim_start_matches("U+").trim_start_matches("u+");
+            if u32::from_str_radix(hex, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .is_none()
+            {
+                warn!("Invalid unicode code point: {}", code_point);
+                continue;
+            }
+
+            events.push(OutputEvent::PressMany(prefix.to_vec()));
+            events.push(OutputEvent::ReleaseMany(prefix.to_vec()));
+            for digit in hex.to_lowercase().chars() {
+                match self.layout.char_key(digit) {
+                    Some((key, true)) => {
+                        events.push(OutputEvent::PressMany(vec![KeyCode::KEY_LEFTSHIFT, key]));
+                        events.push(OutputEvent::ReleaseMany(vec![KeyCode::KEY_LEFTSHIFT, key]));
+                    }
+                    Some((key, false)) => {
+                        events.push(OutputEvent::Press(key));
+                        events.push(OutputEvent::Release(key));
+                    }
+                    None => {}
+                }
+            }
+            events.push(OutputEvent::PressMany(suffix.to_vec()));
+            events.push(OutputEvent::ReleaseMany(suffix.to_vec()));
+        }
+
+        self.emit(&events)
+    }
+
+    /// Releases every key this output still has pressed on the virtual device, modifiers
+    /// another output holds stay pressed
+    pub fn release_all(&mut self) -> Result<()> {
+        let keys: Vec<KeyCode> = self.pressed.keys().copied().collect();
+        for key in &keys {
+            self.drop_extra_holds(key);
+        }
+        if !keys.is_empty() {
+            self.send_keys(&keys, RELEASE)?;
+        }
+        Ok(())
+    }
+
+    /// Releases a key however many times this output has pressed it
+    pub fn force_release(&mut self, key: &KeyCode) -> Result<()> {
+        self.drop_extra_holds(key);
+        self.send_key(key, RELEASE)
+    }
+
+    /// Leaves this output holding a modifier once at most, so one release lets go of it
+    fn drop_extra_holds(&mut self, key: &KeyCode) {
+        let Some(holds) = self.mod_holds.get_mut(key).filter(|holds| **holds > 1) else {
+            return;
+        };
+        let extra = *holds - 1;
+        *holds = 1;
+        if let Some(shared) = self.shared_holds.lock().unwrap().get_mut(key) {
+            *shared = shared.saturating_sub(extra);
+        }
+    }
+
+    /// Whether a modifier press or release is written to the device. A press only is by the
+    /// first hold, a release by the last, and a release this output didn't press only when no
+    /// other output holds it
+    fn writes_hold(&self, shared: &HashMap<KeyCode, u32>, key: &KeyCode, state: i32) -> bool {
+        let held_elsewhere = |most| shared.get(key).is_some_and(|holds| *holds > most);
+        match state {
+            PRESS => !held_elsewhere(0),
+            RELEASE if !self.mod_holds.contains_key(key) => !held_elsewhere(0),
+            RELEASE => !held_elsewhere(1),
+            _ => true,
+        }
+    }
+
+    /// Counts a modifier press or release written, or left out as another hold covers it
+    fn count_hold(&mut self, shared: &mut HashMap<KeyCode, u32>, key: &KeyCode, state: i32) {
+        match state {
+            PRESS => {
+                *self.mod_holds.entry(*key).or_default() += 1;
+                *shared.entry(*key).or_default() += 1;
+            }
+            RELEASE => {
+                let Some(own) = self.mod_holds.get_mut(key) else {
+                    return;
+                };
+                *own -= 1;
+                if *own == 0 {
+                    self.mod_holds.remove(key);
+                }
+                match shared.get_mut(key) {
+                    Some(holds) if *holds > 1 => *holds -= 1,
+                    _ => {
+                        shared.remove(key);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Writes a key, only recording it as pressed or released once the device has taken it, so
+    /// a failed write leaves the output as it was
+    fn write_key(&mut self, key: &KeyCode, state: i32) -> Result<()> {
+        let resolved_key = match state {
+            PRESS if !is_modifier(key) => self.layout.from(key),
+            _ => self
+                .pressed
+                .get(key)
+                .copied()
+                .unwrap_or_else(|| self.layout.from(key)),
+        };
+
+        // Modifier holds stay locked until they're counted, so another output sharing the
+        // device can't decide on the same modifier in between
+        let shared_holds = self.shared_holds.clone();
+        let mut shared = is_modifier(key).then(|| shared_holds.lock().unwrap());
+        if shared
+            .as_ref()
+            .is_none_or(|shared| self.writes_hold(shared, key, state))
+        {
+            self.device.write(EV_KEY, resolved_key.0 as i32, state)?;
+        }
+        if let Some(shared) = shared.as_mut() {
+            self.count_hold(shared, key, state);
+        }
+        drop(shared);
+
+        match state {
+            PRESS => {
+                if !is_modifier(key) {
+                    self.last = self
+                        .pressed
+                        .keys()
+                        .filter(|pressed| is_modifier(pressed))
+                        .copied()
+                        .chain([*key])
+                        .collect();
+                }
+                self.pressed.insert(*key, resolved_key);
+            }
+            // A modifier stays pressed for this output until it's released as often as pressed
+            RELEASE if !self.mod_holds.contains_key(key) => {
+                self.pressed.remove(key);
+            }
+            _ => {}
+        }
+
+        if let Some(emitting) = self.emitting.as_mut() {
+            match state {
+                PRESS => emitting.push(*key),
+                RELEASE => {
+                    if let Some(index) = emitting.iter().rposition(|emitted| emitted == key) {
+                        emitting.remove(index);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn log_keys(keys: &[KeyCode], state: i32) {
+    let key_str = keys
+        .iter()
+        .map(|k| format!("{:?}", k).chars().skip(4).collect::<String>())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    debug!(
+        "{} {}: {}",
+        state_arrow(state),
+        "KEYS".yellow(),
+        key_str.bright_blue(),
+    );
+}
+
+fn log_key(key: &KeyCode, state: i32) {
+    debug!(
+        "{} {}: {}",
+        state_arrow(state),
+        "KEY".yellow(),
+        &format!("{:?}", key)[4..].bright_blue(),
+    );
+}
+
+pub(crate) fn state_arrow(state: i32) -> ColoredString {
+    match state {
+        PRESS => "↓".green().bold(),
+        REPEAT => "↻".yellow().bold(),
+        _ => "↑".red().bold(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::OutputEvent::{Press, Release};
+    use crate::layouts::tests::named;
+    use crate::pipeline::tests::run;
+
+    fn output(device: impl EventSink + 'static) -> Output {
+        Output::new(Box::new(device), named("qwerty"))
+    }
+
+    #[test]
+    fn modifier_held_twice_is_pressed_and_released_once() {
+        let device = MockDevice::default();
+        let mut output = output(device.clone());
+        let shift = KeyCode::KEY_LEFTSHIFT;
+        output.send_key(&shift, PRESS).unwrap();
+        output.send_keys(&[shift, KeyCode::KEY_A], PRESS).unwrap();
+        output.send_keys(&[shift, KeyCode::KEY_A], RELEASE).unwrap();
+        assert!(output.is_pressed(&shift));
+        output.send_key(&shift, RELEASE).unwrap();
+        assert!(!output.is_pressed(&shift));
+        assert_eq!(
+            device.events(),
+            [
+                Press(shift),
+                Press(KeyCode::KEY_A),
+                Release(KeyCode::KEY_A),
+                Release(shift),
+            ]
+        );
+    }
+
+    #[test]
+    fn layer_and_hold_sharing_a_modifier_press_it_once() {
+        let output = run(
+            "
+keyboards:
+  Test:
+    layout: qwerty
+    mappings:
+      KEY_F: {tap: [KEY_F], hold: [KEY_LEFTSHIFT], hrm_term: 200}
+    layers:
+      Shifted:
+        apply_mods: [KEY_LEFTSHIFT]
+        KEY_RIGHTALT:
+          KEY_J: [KEY_1]
+",
+            &[
+                (0, KeyCode::KEY_RIGHTALT, PRESS),
+                (20, KeyCode::KEY_J, PRESS),
+                (40, KeyCode::KEY_F, PRESS),
+                (300, KeyCode::KEY_J, RELEASE),
+                (320, KeyCode::KEY_F, RELEASE),
+                (340, KeyCode::KEY_RIGHTALT, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(KeyCode::KEY_LEFTSHIFT),
+                Press(KeyCode::KEY_1),
+                Release(KeyCode::KEY_1),
+                Release(KeyCode::KEY_LEFTSHIFT),
+            ]
+        );
+    }
+
+    #[test]
+    fn same_frame_shares_one_sync_with_the_modifiers() {
+        let device = MockDevice::default();
+        let mut output = output(device.clone());
+        output.set_same_frame(true);
+        let keys = [KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_A];
+        output.send_keys(&keys, PRESS).unwrap();
+        output.send_keys(&keys, RELEASE).unwrap();
+        assert_eq!(
+            device.frames(),
+            [
+                Press(KeyCode::KEY_LEFTSHIFT),
+                Press(KeyCode::KEY_A),
+                OutputEvent::Sync,
+                Release(KeyCode::KEY_A),
+                Release(KeyCode::KEY_LEFTSHIFT),
+                OutputEvent::Sync,
+            ]
+        );
+    }
+
+    #[test]
+    fn mock_records_repeats_and_mouse_frames() {
+        let device = MockDevice::default();
+        let mut output = output(device.clone());
+        output
+            .emit(&[
+                Press(KeyCode::KEY_A),
+                OutputEvent::Repeat(KeyCode::KEY_A),
+                Release(KeyCode::KEY_A),
+                OutputEvent::MouseMove { dx: 3, dy: -2 },
+            ])
+            .unwrap();
+        assert_eq!(
+            device.frames(),
+            [
+                Press(KeyCode::KEY_A),
+                OutputEvent::Sync,
+                OutputEvent::Repeat(KeyCode::KEY_A),
+                OutputEvent::Sync,
+                Release(KeyCode::KEY_A),
+                OutputEvent::Sync,
+                OutputEvent::MouseMove { dx: 3, dy: -2 },
+                OutputEvent::Sync,
+            ]
+        );
+    }
+
+    #[test]
+    fn modifiers_get_a_frame_ahead_of_their_keys() {
+        let device = MockDevice::default();
+        let mut output = output(device.clone());
+        let keys = [KeyCode::KEY_A, KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_B];
+        output.send_keys(&keys, PRESS).unwrap();
+        output.send_keys(&keys, RELEASE).unwrap();
+        output.send_key(&KeyCode::KEY_C, PRESS).unwrap();
+        assert_eq!(
+            device.frames(),
+            [
+                Press(KeyCode::KEY_LEFTSHIFT),
+                OutputEvent::Sync,
+                Press(KeyCode::KEY_A),
+                Press(KeyCode::KEY_B),
+                OutputEvent::Sync,
+                Release(KeyCode::KEY_A),
+                Release(KeyCode::KEY_B),
+                OutputEvent::Sync,
+                Release(KeyCode::KEY_LEFTSHIFT),
+                OutputEvent::Sync,
+                Press(KeyCode::KEY_C),
+                OutputEvent::Sync,
+            ]
+        );
+    }
+
+    #[test]
+    fn emitted_keys_each_end_their_frame() {
+        let device = MockDevice::default();
+        let mut output = output(device.clone());
+        output.send_text("A").unwrap();
+        assert_eq!(
+            device.frames(),
+            [
+                Press(KeyCode::KEY_LEFTSHIFT),
+                OutputEvent::Sync,
+                Press(KeyCode::KEY_A),
+                OutputEvent::Sync,
+                Release(KeyCode::KEY_A),
+                OutputEvent::Sync,
+                Release(KeyCode::KEY_LEFTSHIFT),
+                OutputEvent::Sync,
+            ]
+        );
+    }
+
+    /// Records like the mock device, failing the key write at the given index once
+    struct FailingSink {
+        device: MockDevice,
+        fail_at: usize,
+        writes: usize,
+    }
+
+    impl EventSink for FailingSink {
+        fn write(&mut self, kind: i32, code: i32, value: i32) -> Result<()> {
+            self.writes += 1;
+            if self.writes == self.fail_at + 1 {
+                anyhow::bail!("write {} failed", self.fail_at);
+            }
+            self.device.write(kind, code, value)
+        }
+
+        fn synchronize(&mut self) -> Result<()> {
+            self.device.synchronize()
+        }
+    }
+
+    fn failing(fail_at: usize) -> (MockDevice, Output) {
+        let device = MockDevice::default();
+        let sink = FailingSink {
+            device: device.clone(),
+            fail_at,
+            writes: 0,
+        };
+        (device, output(sink))
+    }
+
+    #[test]
+    fn failed_send_releases_the_keys_it_pressed() {
+        let (device, mut output) = failing(2);
+        let keys = [
+            KeyCode::KEY_LEFTCTRL,
+            KeyCode::KEY_LEFTSHIFT,
+            KeyCode::KEY_C,
+        ];
+        assert!(output.send_keys(&keys, PRESS).is_err());
+        assert_eq!(
+            device.events(),
+            [
+                Press(KeyCode::KEY_LEFTCTRL),
+                Press(KeyCode::KEY_LEFTSHIFT),
+                Release(KeyCode::KEY_LEFTSHIFT),
+                Release(KeyCode::KEY_LEFTCTRL),
+            ]
+        );
+        assert!(output.pressed().is_empty());
+    }
+
+    #[test]
+    fn failed_emit_releases_the_keys_earlier_events_pressed() {
+        let (device, mut output) = failing(3);
+        let events = [
+            Press(KeyCode::KEY_A),
+            OutputEvent::PressMany(vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_B]),
+            Release(KeyCode::KEY_A),
+            Press(KeyCode::KEY_C),
+        ];
+        assert!(output.emit(&events).is_err());
+        assert_eq!(
+            device.events(),
+            [
+                Press(KeyCode::KEY_A),
+                Press(KeyCode::KEY_LEFTSHIFT),
+                Press(KeyCode::KEY_B),
+                Release(KeyCode::KEY_B),
+                Release(KeyCode::KEY_LEFTSHIFT),
+                Release(KeyCode::KEY_A),
+            ]
+        );
+        assert!(output.pressed().is_empty());
+    }
+
+    #[test]
+    fn failed_write_leaves_the_key_unpressed() {
+        let (device, mut output) = failing(0);
+        let shift = KeyCode::KEY_LEFTSHIFT;
+        assert!(output.send_key(&shift, PRESS).is_err());
+        assert!(!output.is_pressed(&shift));
+
+        // Not counted as held, so the next press is still written and one release lets go
+        output.send_key(&shift, PRESS).unwrap();
+        output.send_key(&shift, RELEASE).unwrap();
+        assert!(!output.is_pressed(&shift));
+        assert_eq!(device.events(), [Press(shift), Release(shift)]);
+    }
+}
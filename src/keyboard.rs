@@ -1,465 +1,451 @@
-use crate::config::{Config, KeyboardConfig};
-use crate::layouts::Layout;
+use crate::device::VirtualDevice;
+use crate::processor::Processor;
+use crate::record::Recorder;
+use crate::socket::{Command, Controllers, Shared};
 use anyhow::{Result, anyhow, bail};
-use colored::{ColoredString, Colorize};
-use crossbeam_channel::{select, unbounded};
+use crossbeam_channel::{Receiver, select};
 use evdev::Device as EvDevDevice;
 use evdev::{EventType, InputEvent, KeyCode};
 use log::{debug, info, warn};
-use std::collections::HashMap;
-use std::collections::HashSet;
+use oxidekeys::config::Config;
+use oxidekeys::features::RELEASE;
+use oxidekeys::io::{DryRunDevice, EventSink};
+use std::ffi::OsStr;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use udev::Enumerator;
-use uinput::Device;
-use uinput::device::Device as UInputDevice;
-
-pub(crate) const RELEASE: i32 = 0;
-pub(crate) const PRESS: i32 = 1;
-pub(crate) const EV_KEY: i32 = 1;
-
-#[derive(Debug, Clone)]
-struct DoubleTapState {
-    last_tap_time: Option<Instant>,
-    tap_count: u32,
-}
-
-#[derive(Debug, Clone)]
-struct RepeatState {
-    repeat_keys: Vec<KeyCode>,
-}
+use udev::{
+    Device as UdevDevice, Enumerator, EventType as UdevEventType, MonitorBuilder, MonitorSocket,
+};
+use uinput::Event;
+use uinput::event::controller::Mouse;
+use uinput::event::relative::{Position, Wheel};
+use uinput::event::{Code, controller, keyboard};
+
+/// Appended to the name of the virtual devices created, so they're never grabbed themselves
+const VIRTUAL_SUFFIX: &str = " OxideKeys";
+/// Milliseconds waited for keys to be released before a keyboard is grabbed anyway
+const DEFAULT_GRAB_TIMEOUT: u16 = 5000;
+/// Name of the one virtual device created with `merge_output`
+pub(crate) const MERGED_NAME: &str = "Merged";
 
 pub(crate) struct Keyboard {
     pub device: EvDevDevice,
-    pub config: KeyboardConfig,
+    pub path: PathBuf,
 }
 
 pub(crate) fn open_keyboard_devices(config: &Config) -> Result<Vec<Keyboard>> {
     debug!("Detecting keyboards");
 
     let mut enumerator = Enumerator::new()?;
+    // Devices that aren't keyboards, such as macro pads, are grabbed when named in the config
     enumerator.match_subsystem("input")?;
-    enumerator.match_property("ID_INPUT_KEYBOARD", "1")?;
 
     let mut keyboards = Vec::new();
+    let mut names = Vec::new();
 
-    for device in enumerator.scan_devices()? {
-        if let Some(devnode) = device.devnode()
-            && let Ok(mut keyboard) = EvDevDevice::open(devnode)
-        {
-            let name_matches = match keyboard.name() {
-                Some(name_value) => config
-                    .keyboards
-                    .iter()
-                    .any(|keyboard| name_value == keyboard.0),
-                None => false,
-            };
-
-            if name_matches {
-                // Wait for all keys to be unpressed before grabbing the input device, otherwise
-                // those keys get into a weird state
-                let mut first = true;
-                loop {
-                    let key_states = keyboard.get_key_state()?;
-                    if key_states.iter().len() == 0 {
-                        break;
-                    }
-                    if first {
-                        first = false;
-                        warn!("Waiting for keys to be released");
-                    }
-                    std::thread::sleep(Duration::from_millis(20));
-                }
+    let mut failed = 0;
 
-                keyboard.grab()?;
+    for device in enumerator.scan_devices()? {
+        let name = device_name(&device);
+        // One keyboard held by another tool shouldn't stop the rest
+        match open_keyboard(&device, config) {
+            Ok(Some(keyboard)) => keyboards.push(keyboard),
+            Ok(None) => {}
+            Err(e) => {
+                failed += 1;
+                warn!(
+                    "Skipping keyboard {}: {}",
+                    name.as_deref().unwrap_or_default(),
+                    e
+                );
+            }
+        }
+        names.extend(name);
+    }
 
-                if let Some(name) = keyboard.name() {
-                    info!("Keyboard monitored: {}", name);
-                } else {
-                    info!("Keyboard monitored");
-                }
+    if keyboards.is_empty() && failed > 0 {
+        bail!("None of the keyboards could be grabbed");
+    }
 
-                let keyboard_config = keyboard
-                    .name()
-                    .and_then(|name_value| {
-                        config.keyboards.iter().find_map(|(k, v)| {
-                            if name_value == k {
-                                Some(v.clone())
-                            } else {
-                                None
-                            }
-                        })
-                    })
-                    .unwrap_or_default();
-
-                keyboards.push(Keyboard {
-                    device: keyboard,
-                    config: keyboard_config,
-                });
-            } else {
-                debug!("Keyboard Ignored: {:?}", keyboard.name());
-            }
+    // Waiting for a keyboard that was named on purpose would look like a hang
+    if let Some(device) = &config.device
+        && keyboards.is_empty()
+    {
+        if names.contains(device) {
+            bail!("Keyboard '{}' isn't in the config", device);
         }
+        if names.is_empty() {
+            bail!(
+                "Keyboard '{}' not found, no keyboards are plugged in",
+                device
+            );
+        }
+        names.sort();
+        names.dedup();
+        bail!(
+            "Keyboard '{}' not found, these are plugged in:\n  {}",
+            device,
+            names.join("\n  ")
+        );
     }
 
     if keyboards.is_empty() {
-        bail!("No keyboards found");
-    } else {
-        Ok(keyboards)
+        warn!("No keyboards found, waiting for one to be plugged in");
     }
-}
 
-pub(crate) fn create_virtual_keyboard(name: &str) -> Result<UInputDevice> {
-    let device = uinput::default()
-        .map_err(|e| anyhow!("Failed to open /dev/uinput (sudo modprobe uinput): {e}"))?
-        .name(format!("{} OxideKeys", name))?
-        .event(uinput::event::Keyboard::All)?
-        .create()?;
-    Ok(device)
+    Ok(keyboards)
 }
 
-pub(crate) fn keyboard_processor(keyboard: Keyboard, config: &Config) -> Result<()> {
-    let mut virt = create_virtual_keyboard(keyboard.device.name().unwrap())?;
-    let mut device = keyboard.device;
-    let kb_config = keyboard.config;
-    let mut keys_down: HashSet<KeyCode> = HashSet::new();
-    let mut holds_triggered: HashSet<KeyCode> = HashSet::new();
-    let mut active_layer: Option<String> = None;
-    let mut double_tap_states: HashMap<KeyCode, DoubleTapState> = HashMap::new();
-    let mut repeat_states: HashMap<KeyCode, RepeatState> = HashMap::new();
-    let (tx, rx) = unbounded::<InputEvent>();
-
-    let layout = crate::layouts::get(&kb_config.layout);
+/// Prints every input device as a `keyboards` entry, with its path and whether it's a keyboard
+pub(crate) fn list_devices() -> Result<()> {
+    let mut enumerator = Enumerator::new()?;
+    enumerator.match_subsystem("input")?;
 
-    let feature_layers_enabled = *config.features.get("layers").unwrap_or(&false);
-    let feature_dual_function_enabled = *config.features.get("dual_function").unwrap_or(&false);
+    let mut devices: Vec<(PathBuf, String, bool)> = Vec::new();
+    for device in enumerator.scan_devices()? {
+        let (Some(devnode), Some(name)) = (device.devnode(), device_name(&device)) else {
+            continue;
+        };
+        let keyboard = device.property_value("ID_INPUT_KEYBOARD") == Some(OsStr::new("1"));
+        devices.push((devnode.to_path_buf(), name, keyboard));
+    }
+    devices.sort();
+
+    println!("keyboards:");
+    for (path, name, keyboard) in devices {
+        let kind = if keyboard {
+            "keyboard"
+        } else {
+            "not a keyboard"
+        };
+        println!("  # {}, {}", path.display(), kind);
+        // A JSON string is a valid double quoted YAML one
+        println!("  {}: {{}}", serde_json::to_string(&name)?);
+    }
+    Ok(())
+}
 
-    std::thread::spawn(move || {
-        loop {
-            match device.fetch_events() {
-                Err(_) => {
-                    break;
-                }
-                Ok(events) => {
-                    for event in events {
-                        if tx.send(event).is_err() {
-                            return;
-                        }
-                    }
-                }
-            }
-        }
-    });
+/// Name of an input event node, from its parent input device, `None` for the other nodes and
+/// the virtual devices
+fn device_name(device: &UdevDevice) -> Option<String> {
+    device
+        .devnode()?
+        .file_name()
+        .filter(|name| name.to_string_lossy().starts_with("event"))?;
+    let name = device
+        .parent()?
+        .attribute_value("name")?
+        .to_string_lossy()
+        .into_owned();
+    (!name.ends_with(VIRTUAL_SUFFIX)).then_some(name)
+}
 
-    loop {
-        select! {
-            recv(rx) -> ev => {
-                let event = match ev { Ok(e) => e, Err(_) => break };
-                if event.event_type() != EventType::KEY { continue; }
-                let state = event.value();
-                if state > PRESS { continue; }
-                let key_raw = KeyCode(event.code());
-                let key_layout = layout.to(&key_raw);
-
-                let mut key_handled = false;
-
-                if feature_layers_enabled {
-                    let mutated = feature_layers(&mut virt, &kb_config, &layout, &key_layout, state, &mut keys_down, &mut active_layer, &mut holds_triggered)?;
-                    if !key_handled {
-                        key_handled = mutated
-                    }
-                }
+/// Watches udev for keyboards being plugged in
+pub(crate) fn monitor_keyboards() -> Result<MonitorSocket> {
+    Ok(MonitorBuilder::new()?.match_subsystem("input")?.listen()?)
+}
 
-                if feature_dual_function_enabled {
-                    let mutated = feature_dual_function(
-                        &mut virt,
-                        &kb_config,
-                        &layout,
-                        &key_layout,
-                        state,
-                        &mut keys_down,
-                        &mut holds_triggered,
-                        &mut double_tap_states,
-                        &mut repeat_states,
-                    )?;
-                    if !key_handled {
-                        key_handled = mutated
-                    }
-                }
+/// Opens the keyboards plugged in since the monitor was last checked
+pub(crate) fn added_keyboards(monitor: &MonitorSocket, config: &Config) -> Vec<Keyboard> {
+    let mut keyboards = Vec::new();
 
-                if !key_handled {
-                    send_key(&mut virt, &layout, &key_layout, state)?;
-                }
-            }
+    for event in monitor.iter() {
+        if event.event_type() != UdevEventType::Add {
+            continue;
+        }
+        match open_keyboard(&event.device(), config) {
+            Ok(Some(keyboard)) => keyboards.push(keyboard),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to open keyboard: {}", e),
         }
     }
 
-    Ok(())
+    keyboards
 }
 
-/// Dual Function with Double-Tap Repeat
-/// - If you press and release a key without overlapping another, Tap fires.
-/// - If you press the key and while it's held another key overlaps, Hold fires.
-/// - If you double-tap a key within the timeout, it starts repeating until released.
-fn feature_dual_function(
-    virt: &mut Device,
-    kb_config: &KeyboardConfig,
-    layout: &Box<dyn Layout>,
-    key: &KeyCode,
-    state: i32,
-    keys_down: &mut HashSet<KeyCode>,
-    holds_triggered: &mut HashSet<KeyCode>,
-    double_tap_states: &mut HashMap<KeyCode, DoubleTapState>,
-    repeat_states: &mut HashMap<KeyCode, RepeatState>,
-) -> Result<bool> {
-    if let Some(remap) = kb_config.mappings.get(key) {
-        match state {
-            PRESS => {
-                keys_down.insert(*key);
-
-                let non_layer_modifier_keys_down = keys_down
-                    .iter()
-                    .filter(|k| !is_layer_key(k, kb_config) && !is_modifier(k))
-                    .count();
-                let overlap_now = non_layer_modifier_keys_down > 1;
-                if overlap_now {
-                    holds_triggered.insert(*key);
-
-                    if let Some(hold_keys) = &remap.hold {
-                        send_keys(virt, layout, hold_keys, PRESS)?;
-                    }
-                } else {
-                    let now = Instant::now();
-                    let double_tap_state =
-                        double_tap_states.entry(*key).or_insert(DoubleTapState {
-                            last_tap_time: None,
-                            tap_count: 0,
-                        });
-
-                    if let Some(last_tap) = double_tap_state.last_tap_time
-                        && let Some(double_tap_timeout) = kb_config.double_tap_timeout
-                    {
-                        if now.duration_since(last_tap).as_millis() <= double_tap_timeout as u128 {
-                            double_tap_state.tap_count += 1;
-
-                            if let Some(tap_keys) = &remap.tap {
-                                repeat_states.remove(key);
-
-                                let repeat_state = RepeatState {
-                                    repeat_keys: tap_keys.clone(),
-                                };
-                                repeat_states.insert(*key, repeat_state);
-
-                                send_keys(virt, layout, tap_keys, PRESS)?;
-                            }
-                        } else {
-                            double_tap_state.tap_count = 1;
-                            repeat_states.remove(key);
-                        }
-                    } else {
-                        double_tap_state.tap_count = 1;
-                    }
-
-                    double_tap_state.last_tap_time = Some(now);
-                }
-
-                return Ok(true);
-            }
-            RELEASE => {
-                let was_hold = holds_triggered.remove(key);
-                keys_down.remove(key);
+/// Grabs the device if it's a configured keyboard, or a device with keys that isn't a
+/// keyboard but is configured by name
+fn open_keyboard(device: &UdevDevice, config: &Config) -> Result<Option<Keyboard>> {
+    let Some(devnode) = device.devnode() else {
+        return Ok(None);
+    };
+    let is_keyboard = device.property_value("ID_INPUT_KEYBOARD") == Some(OsStr::new("1"));
+    if !is_keyboard && !device_name(device).is_some_and(|name| config.names_keyboard(&name)) {
+        return Ok(None);
+    }
+    let Ok(mut keyboard) = EvDevDevice::open(devnode) else {
+        return Ok(None);
+    };
 
-                let had_repeat_state = repeat_states.contains_key(key);
+    if !is_keyboard && !keyboard.supported_events().contains(EventType::KEY) {
+        debug!("Not grabbing device without keys: {:?}", keyboard.name());
+        return Ok(None);
+    }
 
-                if let Some(repeat_state) = repeat_states.remove(key) {
-                    send_keys(virt, layout, &repeat_state.repeat_keys, RELEASE)?;
-                }
+    // Grabbing our own output would feed every key back in
+    if keyboard
+        .name()
+        .is_some_and(|name| name.ends_with(VIRTUAL_SUFFIX))
+    {
+        debug!("Not grabbing virtual device: {:?}", keyboard.name());
+        return Ok(None);
+    }
 
-                if let Some(double_tap_state) = double_tap_states.get(key)
-                    && let Some(last_tap) = double_tap_state.last_tap_time
-                    && let Some(double_tap_timeout) = kb_config.double_tap_timeout
-                {
-                    let now = Instant::now();
-                    if now.duration_since(last_tap).as_millis() > (double_tap_timeout as u128 * 2) {
-                        double_tap_states.remove(key);
-                    }
-                }
+    // Keyboards of every profile are grabbed, so switching to one never needs a regrab
+    let name_matches = keyboard.name().is_some_and(|name| {
+        config.has_keyboard(name) && config.device.as_ref().is_none_or(|device| device == name)
+    });
 
-                if was_hold {
-                    if let Some(hold_keys) = &remap.hold {
-                        send_keys(virt, layout, hold_keys, RELEASE)?;
-                    }
-                } else if let Some(tap_keys) = &remap.tap
-                    && !had_repeat_state
-                {
-                    send_keys(virt, layout, tap_keys, PRESS)?;
-                    send_keys(virt, layout, tap_keys, RELEASE)?;
-                }
+    if !name_matches {
+        debug!("Keyboard Ignored: {:?}", keyboard.name());
+        return Ok(None);
+    }
 
-                return Ok(true);
+    // A dry run only reads the keyboard, so it keeps typing as usual
+    if !config.globals.no_emit {
+        // Wait for all keys to be unpressed before grabbing the input device, otherwise
+        // those keys get into a weird state
+        let timeout = config.grab_timeout.unwrap_or(DEFAULT_GRAB_TIMEOUT);
+        let deadline = Instant::now() + Duration::from_millis(timeout as u64);
+        let mut first = true;
+        loop {
+            let key_states = keyboard.get_key_state()?;
+            if key_states.iter().len() == 0 {
+                break;
+            }
+            // Some keys, such as a laptop's Fn key, never report being released
+            if Instant::now() >= deadline {
+                force_release(&mut keyboard, key_states.iter().collect())?;
+                break;
             }
-            _ => {}
+            if first {
+                first = false;
+                warn!("Waiting for keys to be released");
+            }
+            std::thread::sleep(Duration::from_millis(20));
         }
 
-        return Ok(true);
+        keyboard.grab()?;
     }
 
-    if state == PRESS && !keys_down.is_empty() && !keys_down.contains(key) {
-        for origin in keys_down.iter() {
-            if !holds_triggered.contains(origin)
-                && !is_layer_key(origin, kb_config)
-                && !is_modifier(origin)
-                && let Some(remap) = kb_config.mappings.get(origin)
-            {
-                if let Some(hold_keys) = &remap.hold {
-                    send_keys(virt, layout, hold_keys, PRESS)?;
-                }
-
-                holds_triggered.insert(*origin);
-            }
-        }
-
-        return Ok(false);
+    if let Some(name) = keyboard.name() {
+        info!("Keyboard monitored: {}", name);
+    } else {
+        info!("Keyboard monitored");
     }
 
-    Ok(false)
+    Ok(Some(Keyboard {
+        device: keyboard,
+        path: devnode.to_path_buf(),
+    }))
 }
 
-fn feature_layers(
-    virt: &mut Device,
-    kb_config: &KeyboardConfig,
-    layout: &Box<dyn Layout>,
-    key: &KeyCode,
-    state: i32,
-    keys_down: &mut HashSet<KeyCode>,
-    active_layer: &mut Option<String>,
-    holds_triggered: &mut HashSet<KeyCode>,
-) -> Result<bool> {
-    for (layer_name, layer_def) in &kb_config.layers {
-        if layer_def.contains_key(key) {
-            match state {
-                PRESS => {
-                    keys_down.insert(*key);
-                    *active_layer = Some(layer_name.to_owned());
-                }
-                RELEASE => {
-                    keys_down.remove(key);
-
-                    if let Some(_prev_layer) = active_layer.take() {
-                        for held_key in holds_triggered.iter().collect::<Vec<_>>() {
-                            if let Some(remap) = kb_config.mappings.get(held_key)
-                                && let Some(hold_keys) = &remap.hold
-                            {
-                                send_keys(virt, layout, hold_keys, RELEASE)?;
-                            }
-                        }
-                        holds_triggered.clear();
-                    }
-
-                    *active_layer = None;
-                }
-                _ => {}
-            }
-
-            log_layer(layer_name, state);
+/// Releases keys still reported down, so whatever else reads the keyboard doesn't see them held
+/// once it's grabbed
+fn force_release(keyboard: &mut EvDevDevice, keys: Vec<KeyCode>) -> Result<()> {
+    warn!("Releasing keys still down: {:?}", keys);
+    let mut events: Vec<InputEvent> = keys
+        .iter()
+        .map(|key| InputEvent::new(EventType::KEY.0, key.code(), RELEASE))
+        .collect();
+    events.push(InputEvent::new(EventType::SYNCHRONIZATION.0, 0, 0));
+    keyboard.send_events(&events)?;
+    Ok(())
+}
 
-            return Ok(true);
+/// Creates a virtual device sending every keyboard and mouse key, along with any other key the
+/// config refers to, such as gamepad buttons
+pub(crate) fn create_virtual_keyboard(name: &str, config: &Config) -> Result<VirtualDevice> {
+    let mut builder = uinput::default()
+        .map_err(|e| anyhow!("Failed to open /dev/uinput (sudo modprobe uinput): {e}"))?
+        .name(format!("{}{}", name, VIRTUAL_SUFFIX))?
+        // Every standard key, as keys the config doesn't mention pass through, and text,
+        // unicode input and layouts type keys it doesn't list either. Includes the consumer
+        // keys such as KEY_VOLUMEUP, KEY_PLAYPAUSE and KEY_BRIGHTNESSUP
+        .event(uinput::event::Keyboard::All)?
+        .event(Mouse::Left)?
+        .event(Mouse::Right)?
+        .event(Mouse::Middle)?
+        .event(Mouse::Side)?
+        .event(Mouse::Extra)?
+        .event(Mouse::Forward)?
+        .event(Mouse::Back)?
+        .event(Position::X)?
+        .event(Position::Y)?
+        .event(Wheel::Vertical)?
+        .event(Wheel::Horizontal)?;
+    // Keys the config uses beyond those, such as gamepad buttons, are registered on top
+    for key in config.keycodes() {
+        match uinput_event(key) {
+            Some(event) => builder = builder.event(event)?,
+            None => warn!(
+                "{:?} can't be sent by the virtual device, it's dropped",
+                key
+            ),
         }
     }
+    Ok(VirtualDevice(builder.create()?))
+}
 
-    if let Some(layer_name) = active_layer
-        && let Some(layer_map) = kb_config.layers.get(layer_name)
-    {
-        for mapping in layer_map.values() {
-            if let Some(remapped) = mapping.get(key) {
-                send_keys(virt, layout, remapped, state)?;
-                return Ok(true);
-            }
-        }
+/// The uinput event of a key, none for the keys the uinput crate doesn't know
+fn uinput_event(key: KeyCode) -> Option<Event> {
+    fn events<T: Into<Event>>(variants: impl Iterator<Item = T>) -> Vec<Event> {
+        variants.map(Into::into).collect()
     }
 
-    Ok(false)
+    [
+        events(keyboard::Key::iter_variants()),
+        events(keyboard::KeyPad::iter_variants()),
+        events(keyboard::Misc::iter_variants()),
+        events(keyboard::InputAssist::iter_variants()),
+        events(keyboard::Function::iter_variants()),
+        events(keyboard::Braille::iter_variants()),
+        events(keyboard::Numeric::iter_variants()),
+        events(keyboard::TouchPad::iter_variants()),
+        events(keyboard::Camera::iter_variants()),
+        events(keyboard::Attendant::iter_variants()),
+        events(controller::Misc::iter_variants()),
+        events(controller::Mouse::iter_variants()),
+        events(controller::JoyStick::iter_variants()),
+        events(controller::GamePad::iter_variants()),
+        events(controller::Digi::iter_variants()),
+        events(controller::Wheel::iter_variants()),
+        events(controller::DPad::iter_variants()),
+        events(controller::TriggerHappy::iter_variants()),
+    ]
+    .into_iter()
+    .flatten()
+    .find(|event| event.code() == key.code() as i32)
 }
 
-fn send_key(virt: &mut Device, layout: &Box<dyn Layout>, key: &KeyCode, state: i32) -> Result<()> {
-    let resolved_key = layout.from(key);
-    virt.write(EV_KEY, resolved_key.0 as i32, state)?;
-    virt.synchronize()?;
-    log_key(key, state);
-    Ok(())
+/// Runs a keyboard's processor on this thread until the keyboard goes away or the process
+/// shuts down
+pub(crate) fn keyboard_processor(
+    keyboard: Keyboard,
+    config: Config,
+    shutdown: Receiver<()>,
+    commands: Receiver<Command>,
+    recorder: Option<Recorder>,
+    controllers: Controllers,
+    shared: Arc<Shared>,
+) -> Result<()> {
+    let name = keyboard.device.name().unwrap_or_default().to_owned();
+    let sink: Box<dyn EventSink> = if config.globals.no_emit {
+        Box::new(DryRunDevice)
+    } else if let Some(output) = &shared.output {
+        Box::new(output.clone())
+    } else {
+        Box::new(create_virtual_keyboard(&name, &config)?)
+    };
+    let processor = Processor::new(
+        &name,
+        keyboard.path,
+        sink,
+        config,
+        recorder,
+        controllers,
+        shared,
+    )?;
+    drive(processor, keyboard.device, shutdown, commands)
 }
 
-fn send_keys(
-    virt: &mut Device,
-    layout: &Box<dyn Layout>,
-    keys: &Vec<KeyCode>,
-    state: i32,
+/// Drives the processor from this thread, with another reading the device
+#[cfg(not(feature = "tokio"))]
+fn drive(
+    mut processor: Processor,
+    mut device: EvDevDevice,
+    shutdown: Receiver<()>,
+    commands: Receiver<Command>,
 ) -> Result<()> {
-    for key in keys {
-        let resolved_key = layout.from(key);
-        virt.write(EV_KEY, resolved_key.0 as i32, state)?;
-    }
-    virt.synchronize()?;
-    log_keys(keys, state);
-    Ok(())
-}
+    use crossbeam_channel::{at, never, unbounded};
 
-fn log_keys(keys: &[KeyCode], state: i32) {
-    let key_str = keys
-        .iter()
-        .map(|k| format!("{:?}", k).chars().skip(4).collect::<String>())
-        .collect::<Vec<_>>()
-        .join(", ");
-
-    debug!(
-        "{} {}: {}",
-        state_arrow(state),
-        "KEYS".yellow(),
-        key_str.bright_blue(),
-    );
-}
+    let (tx, rx) = unbounded::<InputEvent>();
 
-fn log_key(key: &KeyCode, state: i32) {
-    debug!(
-        "{} {}: {}",
-        state_arrow(state),
-        "KEY".yellow(),
-        &format!("{:?}", key)[4..].bright_blue(),
-    );
-}
+    std::thread::spawn(move || {
+        loop {
+            match device.fetch_events() {
+                Err(_) => {
+                    break;
+                }
+                Ok(events) => {
+                    for event in events {
+                        if tx.send(event).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    });
 
-fn log_layer(layer: &str, state: i32) {
-    debug!(
-        "{} {}: {}",
-        state_arrow(state),
-        "LAYER".purple(),
-        layer.bright_blue(),
-    );
-}
+    loop {
+        let timer = match processor.next_timer() {
+            Some(deadline) => at(deadline),
+            None => never(),
+        };
 
-fn state_arrow(state: i32) -> ColoredString {
-    match state {
-        PRESS => "↓".green().bold(),
-        _ => "↑".red().bold(),
+        select! {
+            recv(rx) -> ev => {
+                let Ok(event) = ev else {
+                    // The device went away, it's picked up again when plugged back in
+                    processor.release()?;
+                    warn!("Keyboard disconnected");
+                    break;
+                };
+                processor.input(&event)?;
+            }
+            recv(shutdown) -> _ => {
+                // The device is ungrabbed when the process exits and its file is closed
+                processor.release()?;
+                processor.restore_leds()?;
+                info!("Released held keys");
+                break;
+            }
+            recv(commands) -> command => {
+                if let Ok(command) = command {
+                    processor.command(command)?;
+                }
+            }
+            recv(timer) -> _ => {
+                processor.timer(Instant::now())?;
+            }
+        }
     }
-}
 
-fn is_modifier(key: &KeyCode) -> bool {
-    matches!(
-        *key,
-        KeyCode::KEY_LEFTSHIFT
-            | KeyCode::KEY_RIGHTSHIFT
-            | KeyCode::KEY_LEFTCTRL
-            | KeyCode::KEY_RIGHTCTRL
-            | KeyCode::KEY_LEFTALT
-            | KeyCode::KEY_RIGHTALT
-            | KeyCode::KEY_LEFTMETA
-            | KeyCode::KEY_RIGHTMETA
-    )
+    Ok(())
 }
 
-fn is_layer_key(key: &KeyCode, kb_config: &KeyboardConfig) -> bool {
-    kb_config
-        .layers
-        .iter()
-        .any(|(_, layer_def)| layer_def.contains_key(key))
+/// Drives the processor from a tokio task on this thread, with commands passed on to it until
+/// the process shuts down, which releases the keys held and restores the LEDs
+#[cfg(feature = "tokio")]
+fn drive(
+    processor: Processor,
+    device: EvDevDevice,
+    shutdown: Receiver<()>,
+    commands: Receiver<Command>,
+) -> Result<()> {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        loop {
+            select! {
+                recv(commands) -> command => {
+                    // Fails once the task has stopped, as the keyboard went away
+                    if let Ok(command) = command
+                        && tx.send(command).is_err()
+                    {
+                        return;
+                    }
+                }
+                // Dropping the sender is what stops the task
+                recv(shutdown) -> _ => return,
+            }
+        }
+    });
+
+    tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .enable_time()
+        .build()?
+        .block_on(crate::processor::run_async(processor, device, rx))
 }
@@ -0,0 +1,129 @@
+use crate::layouts::{Layout, qwerty};
+use evdev::KeyCode as K;
+
+/// French AZERTY, keys typing symbols US Qwerty has elsewhere keep their own names
+pub(crate) struct AzertyLayout;
+
+impl Layout for AzertyLayout {
+    fn to(&self, key: &K) -> K {
+        match *key {
+            K::KEY_4 => K::KEY_APOSTROPHE,
+            K::KEY_6 => K::KEY_MINUS,
+            K::KEY_MINUS => K::KEY_6,
+            K::KEY_Q => K::KEY_A,
+            K::KEY_W => K::KEY_Z,
+            K::KEY_A => K::KEY_Q,
+            K::KEY_SEMICOLON => K::KEY_M,
+            K::KEY_APOSTROPHE => K::KEY_4,
+            K::KEY_Z => K::KEY_W,
+            K::KEY_M => K::KEY_COMMA,
+            K::KEY_COMMA => K::KEY_SEMICOLON,
+            _ => *key,
+        }
+    }
+
+    fn from(&self, key: &K) -> K {
+        match *key {
+            K::KEY_APOSTROPHE => K::KEY_4,
+            K::KEY_MINUS => K::KEY_6,
+            K::KEY_6 => K::KEY_MINUS,
+            K::KEY_A => K::KEY_Q,
+            K::KEY_Z => K::KEY_W,
+            K::KEY_Q => K::KEY_A,
+            K::KEY_M => K::KEY_SEMICOLON,
+            K::KEY_4 => K::KEY_APOSTROPHE,
+            K::KEY_W => K::KEY_Z,
+            K::KEY_COMMA => K::KEY_M,
+            K::KEY_SEMICOLON => K::KEY_COMMA,
+            _ => *key,
+        }
+    }
+
+    /// AZERTY's number row types accents and symbols, its digits need shift
+    fn char_key(&self, c: char) -> Option<(K, bool)> {
+        let (physical, shift) = match c {
+            '&' => (K::KEY_1, false),
+            '1' => (K::KEY_1, true),
+            'é' => (K::KEY_2, false),
+            '2' => (K::KEY_2, true),
+            '"' => (K::KEY_3, false),
+            '3' => (K::KEY_3, true),
+            '\'' => (K::KEY_4, false),
+            '4' => (K::KEY_4, true),
+            '(' => (K::KEY_5, false),
+            '5' => (K::KEY_5, true),
+            '-' => (K::KEY_6, false),
+            '6' => (K::KEY_6, true),
+            'è' => (K::KEY_7, false),
+            '7' => (K::KEY_7, true),
+            '_' => (K::KEY_8, false),
+            '8' => (K::KEY_8, true),
+            'ç' => (K::KEY_9, false),
+            '9' => (K::KEY_9, true),
+            'à' => (K::KEY_0, false),
+            '0' => (K::KEY_0, true),
+            ')' => (K::KEY_MINUS, false),
+            '°' => (K::KEY_MINUS, true),
+            '=' => (K::KEY_EQUAL, false),
+            '+' => (K::KEY_EQUAL, true),
+            '$' => (K::KEY_RIGHTBRACE, false),
+            '£' => (K::KEY_RIGHTBRACE, true),
+            'ù' => (K::KEY_APOSTROPHE, false),
+            '%' => (K::KEY_APOSTROPHE, true),
+            '*' => (K::KEY_BACKSLASH, false),
+            'µ' => (K::KEY_BACKSLASH, true),
+            ',' => (K::KEY_M, false),
+            '?' => (K::KEY_M, true),
+            ';' => (K::KEY_COMMA, false),
+            '.' => (K::KEY_COMMA, true),
+            ':' => (K::KEY_DOT, false),
+            '/' => (K::KEY_DOT, true),
+            '!' => (K::KEY_SLASH, false),
+            '§' => (K::KEY_SLASH, true),
+            '<' => (K::KEY_102ND, false),
+            '>' => (K::KEY_102ND, true),
+            // Letters are where AZERTY has them, the rest need AltGr which isn't typed
+            c if c.is_ascii_alphabetic() || c.is_ascii_whitespace() => return qwerty::char_key(c),
+            _ => return None,
+        };
+        Some((self.to(&physical), shift))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layouts::tests::{
+        BOTTOM_ROW, HOME_ROW, NUMBER_ROW, TOP_ROW, assert_round_trip, named,
+    };
+    use evdev::KeyCode as K;
+
+    #[test]
+    fn main_block_round_trips() {
+        assert_round_trip(
+            named("azerty").as_ref(),
+            &[NUMBER_ROW, TOP_ROW, HOME_ROW, BOTTOM_ROW, &[K::KEY_102ND]],
+        );
+    }
+
+    #[test]
+    fn letters_and_symbols_reach_their_physical_keys() {
+        let layout = named("azerty");
+        for (c, physical, shift) in [
+            ('a', K::KEY_Q, false),
+            ('z', K::KEY_W, false),
+            ('q', K::KEY_A, false),
+            ('w', K::KEY_Z, false),
+            ('m', K::KEY_SEMICOLON, false),
+            ('1', K::KEY_1, true),
+            ('&', K::KEY_1, false),
+            ('-', K::KEY_6, false),
+            (',', K::KEY_M, false),
+            (';', K::KEY_COMMA, false),
+            ('!', K::KEY_SLASH, false),
+        ] {
+            let (key, shifted) = layout.char_key(c).unwrap();
+            assert_eq!((layout.from(&key), shifted), (physical, shift), "{:?}", c);
+        }
+        assert_eq!(layout.char_key('@'), None);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::layouts::{Layout, qwerty};
+use evdev::KeyCode as K;
+
+/// French BÉPO, letters are named by the letter and `é`, `è`, the dead `^` and `à` take the
+/// names of `KEY_SEMICOLON`, `KEY_LEFTBRACE`, `KEY_RIGHTBRACE` and `KEY_SLASH`
+pub(crate) struct BepoLayout;
+
+impl Layout for BepoLayout {
+    fn to(&self, key: &K) -> K {
+        match *key {
+            K::KEY_Q => K::KEY_B,
+            K::KEY_W => K::KEY_SEMICOLON,
+            K::KEY_E => K::KEY_P,
+            K::KEY_R => K::KEY_O,
+            K::KEY_T => K::KEY_LEFTBRACE,
+            K::KEY_Y => K::KEY_RIGHTBRACE,
+            K::KEY_U => K::KEY_V,
+            K::KEY_I => K::KEY_D,
+            K::KEY_O => K::KEY_L,
+            K::KEY_P => K::KEY_J,
+            K::KEY_LEFTBRACE => K::KEY_Z,
+            K::KEY_RIGHTBRACE => K::KEY_W,
+            K::KEY_S => K::KEY_U,
+            K::KEY_D => K::KEY_I,
+            K::KEY_F => K::KEY_E,
+            K::KEY_G => K::KEY_COMMA,
+            K::KEY_H => K::KEY_C,
+            K::KEY_J => K::KEY_T,
+            K::KEY_K => K::KEY_S,
+            K::KEY_L => K::KEY_R,
+            K::KEY_SEMICOLON => K::KEY_N,
+            K::KEY_APOSTROPHE => K::KEY_M,
+            K::KEY_Z => K::KEY_SLASH,
+            K::KEY_X => K::KEY_Y,
+            K::KEY_C => K::KEY_X,
+            K::KEY_V => K::KEY_DOT,
+            K::KEY_B => K::KEY_K,
+            K::KEY_N => K::KEY_APOSTROPHE,
+            K::KEY_M => K::KEY_Q,
+            K::KEY_COMMA => K::KEY_G,
+            K::KEY_DOT => K::KEY_H,
+            K::KEY_SLASH => K::KEY_F,
+            _ => *key,
+        }
+    }
+
+    fn from(&self, key: &K) -> K {
+        match *key {
+            K::KEY_B => K::KEY_Q,
+            K::KEY_SEMICOLON => K::KEY_W,
+            K::KEY_P => K::KEY_E,
+            K::KEY_O => K::KEY_R,
+            K::KEY_LEFTBRACE => K::KEY_T,
+            K::KEY_RIGHTBRACE => K::KEY_Y,
+            K::KEY_V => K::KEY_U,
+            K::KEY_D => K::KEY_I,
+            K::KEY_L => K::KEY_O,
+            K::KEY_J => K::KEY_P,
+            K::KEY_Z => K::KEY_LEFTBRACE,
+            K::KEY_W => K::KEY_RIGHTBRACE,
+            K::KEY_U => K::KEY_S,
+            K::KEY_I => K::KEY_D,
+            K::KEY_E => K::KEY_F,
+            K::KEY_COMMA => K::KEY_G,
+            K::KEY_C => K::KEY_H,
+            K::KEY_T => K::KEY_J,
+            K::KEY_S => K::KEY_K,
+            K::KEY_R => K::KEY_L,
+            K::KEY_N => K::KEY_SEMICOLON,
+            K::KEY_M => K::KEY_APOSTROPHE,
+            K::KEY_SLASH => K::KEY_Z,
+            K::KEY_Y => K::KEY_X,
+            K::KEY_X => K::KEY_C,
+            K::KEY_DOT => K::KEY_V,
+            K::KEY_K => K::KEY_B,
+            K::KEY_APOSTROPHE => K::KEY_N,
+            K::KEY_Q => K::KEY_M,
+            K::KEY_G => K::KEY_COMMA,
+            K::KEY_H => K::KEY_DOT,
+            K::KEY_F => K::KEY_SLASH,
+            _ => *key,
+        }
+    }
+
+    /// Accented letters have keys of their own, the dead `^` is left to the system layout
+    fn char_key(&self, c: char) -> Option<(K, bool)> {
+        let (physical, shift) = match c {
+            '$' => (K::KEY_GRAVE, false),
+            '#' => (K::KEY_GRAVE, true),
+            '"' => (K::KEY_1, false),
+            '1' => (K::KEY_1, true),
+            '«' => (K::KEY_2, false),
+            '2' => (K::KEY_2, true),
+            '»' => (K::KEY_3, false),
+            '3' => (K::KEY_3, true),
+            '(' => (K::KEY_4, false),
+            '4' => (K::KEY_4, true),
+            ')' => (K::KEY_5, false),
+            '5' => (K::KEY_5, true),
+            '@' => (K::KEY_6, false),
+            '6' => (K::KEY_6, true),
+            '+' => (K::KEY_7, false),
+            '7' => (K::KEY_7, true),
+            '-' => (K::KEY_8, false),
+            '8' => (K::KEY_8, true),
+            '/' => (K::KEY_9, false),
+            '9' => (K::KEY_9, true),
+            '*' => (K::KEY_0, false),
+            '0' => (K::KEY_0, true),
+            '=' => (K::KEY_MINUS, false),
+            '°' => (K::KEY_MINUS, true),
+            '%' => (K::KEY_EQUAL, false),
+            '`' => (K::KEY_EQUAL, true),
+            'é' => (K::KEY_W, false),
+            'É' => (K::KEY_W, true),
+            'è' => (K::KEY_T, false),
+            'È' => (K::KEY_T, true),
+            '!' => (K::KEY_Y, true),
+            ',' => (K::KEY_G, false),
+            ';' => (K::KEY_G, true),
+            'ç' => (K::KEY_BACKSLASH, false),
+            'Ç' => (K::KEY_BACKSLASH, true),
+            'ê' => (K::KEY_102ND, false),
+            'Ê' => (K::KEY_102ND, true),
+            'à' => (K::KEY_Z, false),
+            'À' => (K::KEY_Z, true),
+            '.' => (K::KEY_V, false),
+            ':' => (K::KEY_V, true),
+            '\'' => (K::KEY_N, false),
+            '?' => (K::KEY_N, true),
+            // Letters are where BÉPO has them, the rest need AltGr which isn't typed
+            c if c.is_ascii_alphabetic() || c.is_ascii_whitespace() => return qwerty::char_key(c),
+            _ => return None,
+        };
+        Some((self.to(&physical), shift))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layouts::tests::{
+        BOTTOM_ROW, HOME_ROW, NUMBER_ROW, TOP_ROW, assert_round_trip, named,
+    };
+    use evdev::KeyCode as K;
+
+    const HOME_ROW_KEYS: &[K] = &[
+        K::KEY_A,
+        K::KEY_U,
+        K::KEY_I,
+        K::KEY_E,
+        K::KEY_COMMA,
+        K::KEY_C,
+        K::KEY_T,
+        K::KEY_S,
+        K::KEY_R,
+        K::KEY_N,
+        K::KEY_M,
+    ];
+
+    #[test]
+    fn base_block_round_trips() {
+        assert_round_trip(
+            named("bepo").as_ref(),
+            &[NUMBER_ROW, TOP_ROW, HOME_ROW, BOTTOM_ROW],
+        );
+    }
+
+    #[test]
+    fn home_row_types_the_base_layer() {
+        let layout = named("bepo");
+        let home_row: Vec<K> = HOME_ROW.iter().map(|key| layout.to(key)).collect();
+        assert_eq!(home_row, HOME_ROW_KEYS);
+    }
+}
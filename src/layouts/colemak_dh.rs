@@ -0,0 +1,94 @@
+use crate::layouts::Layout;
+use evdev::KeyCode as K;
+
+pub(crate) struct ColemakDhLayout;
+
+impl Layout for ColemakDhLayout {
+    fn to(&self, key: &K) -> K {
+        match *key {
+            K::KEY_Q => K::KEY_Q,
+            K::KEY_W => K::KEY_W,
+            K::KEY_E => K::KEY_F,
+            K::KEY_R => K::KEY_P,
+            K::KEY_T => K::KEY_B,
+            K::KEY_Y => K::KEY_J,
+            K::KEY_U => K::KEY_L,
+            K::KEY_I => K::KEY_U,
+            K::KEY_O => K::KEY_Y,
+            K::KEY_P => K::KEY_SEMICOLON,
+            K::KEY_A => K::KEY_A,
+            K::KEY_S => K::KEY_R,
+            K::KEY_D => K::KEY_S,
+            K::KEY_F => K::KEY_T,
+            K::KEY_G => K::KEY_G,
+            K::KEY_H => K::KEY_M,
+            K::KEY_J => K::KEY_N,
+            K::KEY_K => K::KEY_E,
+            K::KEY_L => K::KEY_I,
+            K::KEY_SEMICOLON => K::KEY_O,
+            K::KEY_Z => K::KEY_Z,
+            K::KEY_X => K::KEY_X,
+            K::KEY_C => K::KEY_C,
+            K::KEY_V => K::KEY_D,
+            K::KEY_B => K::KEY_V,
+            K::KEY_N => K::KEY_K,
+            K::KEY_M => K::KEY_H,
+            _ => *key,
+        }
+    }
+
+    fn from(&self, key: &K) -> K {
+        match *key {
+            K::KEY_Q => K::KEY_Q,
+            K::KEY_W => K::KEY_W,
+            K::KEY_F => K::KEY_E,
+            K::KEY_P => K::KEY_R,
+            K::KEY_B => K::KEY_T,
+            K::KEY_J => K::KEY_Y,
+            K::KEY_L => K::KEY_U,
+            K::KEY_U => K::KEY_I,
+            K::KEY_Y => K::KEY_O,
+            K::KEY_SEMICOLON => K::KEY_P,
+            K::KEY_A => K::KEY_A,
+            K::KEY_R => K::KEY_S,
+            K::KEY_S => K::KEY_D,
+            K::KEY_T => K::KEY_F,
+            K::KEY_G => K::KEY_G,
+            K::KEY_M => K::KEY_H,
+            K::KEY_N => K::KEY_J,
+            K::KEY_E => K::KEY_K,
+            K::KEY_I => K::KEY_L,
+            K::KEY_O => K::KEY_SEMICOLON,
+            K::KEY_Z => K::KEY_Z,
+            K::KEY_X => K::KEY_X,
+            K::KEY_C => K::KEY_C,
+            K::KEY_D => K::KEY_V,
+            K::KEY_V => K::KEY_B,
+            K::KEY_K => K::KEY_N,
+            K::KEY_H => K::KEY_M,
+            _ => *key,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layouts::tests::{BOTTOM_ROW, HOME_ROW, assert_round_trip, named};
+    use evdev::KeyCode as K;
+
+    #[test]
+    fn bottom_rows_round_trip() {
+        assert_round_trip(named("colemak-dh").as_ref(), &[HOME_ROW, BOTTOM_ROW]);
+    }
+
+    #[test]
+    fn d_h_b_m_moved_from_colemak() {
+        let layout = named("colemak-dh");
+        assert_eq!(layout.to(&K::KEY_V), K::KEY_D);
+        assert_eq!(layout.to(&K::KEY_M), K::KEY_H);
+        assert_eq!(layout.to(&K::KEY_T), K::KEY_B);
+        assert_eq!(layout.to(&K::KEY_H), K::KEY_M);
+        assert_eq!(layout.from(&K::KEY_D), K::KEY_V);
+        assert_eq!(layout.from(&K::KEY_H), K::KEY_M);
+    }
+}
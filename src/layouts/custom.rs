@@ -0,0 +1,130 @@
+use crate::layouts::Layout;
+use anyhow::{Context, Result, bail};
+use evdev::KeyCode as K;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Layout loaded from a YAML/JSON file of physical to logical keys
+pub(crate) struct CustomLayout {
+    forward: HashMap<K, K>,
+    reverse: HashMap<K, K>,
+}
+
+impl CustomLayout {
+    pub(crate) fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read layout file {}", path.display()))?;
+        let forward: HashMap<K, K> = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse layout file {}", path.display()))?;
+
+        let mut targets = HashMap::with_capacity(forward.len());
+        let mut reverse = HashMap::with_capacity(forward.len());
+        for (physical, logical) in &forward {
+            if let Some(existing) = targets.insert(*logical, *physical) {
+                bail!(
+                    "Duplicate target {:?} for {:?} and {:?} in layout file {}",
+                    logical,
+                    existing,
+                    physical,
+                    path.display()
+                );
+            }
+            // A key left out still types itself and is sent as is, so mapping AltGr to Alt
+            // doesn't turn every Alt sent into AltGr
+            if forward.contains_key(logical) {
+                reverse.insert(*logical, *physical);
+            }
+        }
+
+        Ok(Self { forward, reverse })
+    }
+}
+
+impl Layout for CustomLayout {
+    fn to(&self, key: &K) -> K {
+        *self.forward.get(key).unwrap_or(key)
+    }
+
+    fn from(&self, key: &K) -> K {
+        *self.reverse.get(key).unwrap_or(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::{PRESS, RELEASE};
+    use crate::io::OutputEvent::{Press, Release};
+    use crate::pipeline::tests::run;
+
+    /// Loads a layout file holding `content`
+    fn load(content: &str) -> Result<CustomLayout> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("layout.yml");
+        fs::write(&path, content).unwrap();
+        CustomLayout::load(&path)
+    }
+
+    #[test]
+    fn swapped_keys_round_trip() {
+        let layout = load("{KEY_A: KEY_B, KEY_B: KEY_A}").unwrap();
+        assert_eq!(layout.to(&K::KEY_A), K::KEY_B);
+        assert_eq!(layout.from(&K::KEY_B), K::KEY_A);
+        assert_eq!(layout.from(&K::KEY_A), K::KEY_B);
+    }
+
+    #[test]
+    fn duplicate_targets_are_rejected() {
+        let error = load("{KEY_A: KEY_X, KEY_B: KEY_X}").err().unwrap();
+        assert!(
+            error.to_string().contains("Duplicate target KEY_X"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn keys_only_mapped_to_are_sent_as_they_are() {
+        let layout = load("{KEY_RIGHTALT: KEY_LEFTALT}").unwrap();
+        assert_eq!(layout.to(&K::KEY_RIGHTALT), K::KEY_LEFTALT);
+        assert_eq!(layout.from(&K::KEY_LEFTALT), K::KEY_LEFTALT);
+        assert_eq!(layout.from(&K::KEY_RIGHTALT), K::KEY_RIGHTALT);
+    }
+
+    #[test]
+    fn right_alt_hold_reaches_the_sink_as_right_alt() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("layout.yml");
+        fs::write(&path, "{KEY_RIGHTALT: KEY_LEFTALT, KEY_Q: KEY_Q}").unwrap();
+        let config = format!(
+            "
+keyboards:
+  Test:
+    layout: {{custom: {}}}
+    layers: {{}}
+    mappings:
+      KEY_F: {{tap: [KEY_F], hold: [KEY_RIGHTALT], hrm_term: 200}}
+",
+            path.display()
+        );
+        let output = run(
+            &config,
+            &[
+                (0, K::KEY_F, PRESS),
+                (20, K::KEY_Q, PRESS),
+                (40, K::KEY_Q, RELEASE),
+                (60, K::KEY_F, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_RIGHTALT),
+                Press(K::KEY_Q),
+                Release(K::KEY_Q),
+                Release(K::KEY_RIGHTALT),
+            ]
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use evdev::KeyCode as K;
+
+/// Qwerty keys in mirror image positions across the middle of the number and letter rows
+const PAIRS: &[(K, K)] = &[
+    (K::KEY_1, K::KEY_0),
+    (K::KEY_2, K::KEY_9),
+    (K::KEY_3, K::KEY_8),
+    (K::KEY_4, K::KEY_7),
+    (K::KEY_5, K::KEY_6),
+    (K::KEY_Q, K::KEY_P),
+    (K::KEY_W, K::KEY_O),
+    (K::KEY_E, K::KEY_I),
+    (K::KEY_R, K::KEY_U),
+    (K::KEY_T, K::KEY_Y),
+    (K::KEY_A, K::KEY_SEMICOLON),
+    (K::KEY_S, K::KEY_L),
+    (K::KEY_D, K::KEY_K),
+    (K::KEY_F, K::KEY_J),
+    (K::KEY_G, K::KEY_H),
+    (K::KEY_Z, K::KEY_SLASH),
+    (K::KEY_X, K::KEY_DOT),
+    (K::KEY_C, K::KEY_COMMA),
+    (K::KEY_V, K::KEY_M),
+    (K::KEY_B, K::KEY_N),
+];
+
+/// Qwerty key in the mirror image position of a Qwerty key on the other hand
+pub(crate) fn mirror(key: &K) -> Option<K> {
+    PAIRS.iter().find_map(|(left, right)| {
+        if left == key {
+            Some(*right)
+        } else if right == key {
+            Some(*left)
+        } else {
+            None
+        }
+    })
+}
@@ -1,21 +1,148 @@
+mod azerty;
+mod bepo;
+mod colemak_dh;
+mod custom;
 mod dvorak;
+mod hands;
+mod mirror;
+mod neo2;
+mod norman;
+mod programmer_dvorak;
 mod qwerty;
+mod qwertz;
+mod workman;
 
+pub(crate) use hands::hand;
+pub(crate) use mirror::mirror;
+
+use crate::config::{LayoutConfig, expand_path};
+use anyhow::Result;
 use evdev::KeyCode;
 
 /// Used for mapping layout definitions
-pub(crate) trait Layout {
+pub trait Layout: Send {
     /// Convert a Qwerty key to the layout's key
     fn to(&self, key: &KeyCode) -> KeyCode;
 
     /// Convert a layout-specific key back to Qwerty
     fn from(&self, key: &KeyCode) -> KeyCode;
+
+    /// Layout key and whether it needs shift to type a character
+    fn char_key(&self, c: char) -> Option<(KeyCode, bool)> {
+        qwerty::char_key(c)
+    }
 }
 
-pub(crate) fn get(layout: &Option<String>) -> Box<dyn Layout> {
-    match layout.as_deref().map(str::to_lowercase).as_deref() {
+pub fn get(layout: Option<&LayoutConfig>) -> Result<Box<dyn Layout>> {
+    let name = match layout {
+        Some(LayoutConfig::Custom { custom }) => {
+            return Ok(Box::new(custom::CustomLayout::load(&expand_path(custom))?));
+        }
+        Some(LayoutConfig::Named(name)) => Some(name.to_lowercase()),
+        None => None,
+    };
+
+    Ok(match name.as_deref() {
+        Some("azerty") => Box::new(azerty::AzertyLayout),
+        Some("bepo") => Box::new(bepo::BepoLayout),
+        Some("colemak-dh") => Box::new(colemak_dh::ColemakDhLayout),
         Some("dvorak") => Box::new(dvorak::DvorakLayout),
+        Some("neo2") => Box::new(neo2::Neo2Layout),
+        Some("norman") => Box::new(norman::NormanLayout),
+        Some("programmer-dvorak") => Box::new(programmer_dvorak::ProgrammerDvorakLayout),
         Some("qwerty") => Box::new(qwerty::QwertyLayout),
+        Some("qwertz") => Box::new(qwertz::QwertzLayout),
+        Some("workman") => Box::new(workman::WorkmanLayout),
         _ => Box::new(qwerty::QwertyLayout),
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use evdev::KeyCode as K;
+    use std::collections::HashSet;
+
+    pub(crate) const NUMBER_ROW: &[K] = &[
+        K::KEY_GRAVE,
+        K::KEY_1,
+        K::KEY_2,
+        K::KEY_3,
+        K::KEY_4,
+        K::KEY_5,
+        K::KEY_6,
+        K::KEY_7,
+        K::KEY_8,
+        K::KEY_9,
+        K::KEY_0,
+        K::KEY_MINUS,
+        K::KEY_EQUAL,
+    ];
+    pub(crate) const TOP_ROW: &[K] = &[
+        K::KEY_Q,
+        K::KEY_W,
+        K::KEY_E,
+        K::KEY_R,
+        K::KEY_T,
+        K::KEY_Y,
+        K::KEY_U,
+        K::KEY_I,
+        K::KEY_O,
+        K::KEY_P,
+        K::KEY_LEFTBRACE,
+        K::KEY_RIGHTBRACE,
+        K::KEY_BACKSLASH,
+    ];
+    pub(crate) const HOME_ROW: &[K] = &[
+        K::KEY_A,
+        K::KEY_S,
+        K::KEY_D,
+        K::KEY_F,
+        K::KEY_G,
+        K::KEY_H,
+        K::KEY_J,
+        K::KEY_K,
+        K::KEY_L,
+        K::KEY_SEMICOLON,
+        K::KEY_APOSTROPHE,
+    ];
+    pub(crate) const BOTTOM_ROW: &[K] = &[
+        K::KEY_Z,
+        K::KEY_X,
+        K::KEY_C,
+        K::KEY_V,
+        K::KEY_B,
+        K::KEY_N,
+        K::KEY_M,
+        K::KEY_COMMA,
+        K::KEY_DOT,
+        K::KEY_SLASH,
+    ];
+
+    /// The layout by the name configs select it with
+    pub(crate) fn named(name: &str) -> Box<dyn Layout> {
+        get(Some(&LayoutConfig::Named(name.to_owned()))).unwrap()
+    }
+
+    /// Checks each Qwerty key converts to a layout key no other one does, which converts back
+    pub(crate) fn assert_round_trip(layout: &dyn Layout, rows: &[&[K]]) {
+        let mut seen = HashSet::new();
+        for key in rows.iter().flat_map(|row| row.iter()) {
+            let to = layout.to(key);
+            assert!(
+                seen.insert(to),
+                "{:?} converts to {:?} as another key does",
+                key,
+                to
+            );
+            assert_eq!(layout.from(&to), *key, "{:?} doesn't convert back", to);
+        }
+    }
+
+    #[test]
+    fn qwerty_and_unknown_names_round_trip() {
+        let rows = [NUMBER_ROW, TOP_ROW, HOME_ROW, BOTTOM_ROW];
+        assert_round_trip(named("qwerty").as_ref(), &rows);
+        assert_round_trip(named("no such layout").as_ref(), &rows);
     }
 }
@@ -0,0 +1,159 @@
+use crate::layouts::{Layout, qwerty};
+use evdev::KeyCode as K;
+
+/// German Neo2 base layer, letters are named by the letter and `ü`, `ö`, `ä` and `ß` take the
+/// names of `KEY_LEFTBRACE`, `KEY_SEMICOLON`, `KEY_APOSTROPHE` and `KEY_SLASH`
+pub(crate) struct Neo2Layout;
+
+impl Layout for Neo2Layout {
+    fn to(&self, key: &K) -> K {
+        match *key {
+            K::KEY_Q => K::KEY_X,
+            K::KEY_W => K::KEY_V,
+            K::KEY_E => K::KEY_L,
+            K::KEY_R => K::KEY_C,
+            K::KEY_T => K::KEY_W,
+            K::KEY_Y => K::KEY_K,
+            K::KEY_U => K::KEY_H,
+            K::KEY_I => K::KEY_G,
+            K::KEY_O => K::KEY_F,
+            K::KEY_P => K::KEY_Q,
+            K::KEY_LEFTBRACE => K::KEY_SLASH,
+            K::KEY_A => K::KEY_U,
+            K::KEY_S => K::KEY_I,
+            K::KEY_D => K::KEY_A,
+            K::KEY_F => K::KEY_E,
+            K::KEY_G => K::KEY_O,
+            K::KEY_H => K::KEY_S,
+            K::KEY_J => K::KEY_N,
+            K::KEY_K => K::KEY_R,
+            K::KEY_L => K::KEY_T,
+            K::KEY_SEMICOLON => K::KEY_D,
+            K::KEY_APOSTROPHE => K::KEY_Y,
+            K::KEY_Z => K::KEY_LEFTBRACE,
+            K::KEY_X => K::KEY_SEMICOLON,
+            K::KEY_C => K::KEY_APOSTROPHE,
+            K::KEY_V => K::KEY_P,
+            K::KEY_B => K::KEY_Z,
+            K::KEY_N => K::KEY_B,
+            K::KEY_SLASH => K::KEY_J,
+            _ => *key,
+        }
+    }
+
+    fn from(&self, key: &K) -> K {
+        match *key {
+            K::KEY_X => K::KEY_Q,
+            K::KEY_V => K::KEY_W,
+            K::KEY_L => K::KEY_E,
+            K::KEY_C => K::KEY_R,
+            K::KEY_W => K::KEY_T,
+            K::KEY_K => K::KEY_Y,
+            K::KEY_H => K::KEY_U,
+            K::KEY_G => K::KEY_I,
+            K::KEY_F => K::KEY_O,
+            K::KEY_Q => K::KEY_P,
+            K::KEY_SLASH => K::KEY_LEFTBRACE,
+            K::KEY_U => K::KEY_A,
+            K::KEY_I => K::KEY_S,
+            K::KEY_A => K::KEY_D,
+            K::KEY_E => K::KEY_F,
+            K::KEY_O => K::KEY_G,
+            K::KEY_S => K::KEY_H,
+            K::KEY_N => K::KEY_J,
+            K::KEY_R => K::KEY_K,
+            K::KEY_T => K::KEY_L,
+            K::KEY_D => K::KEY_SEMICOLON,
+            K::KEY_Y => K::KEY_APOSTROPHE,
+            K::KEY_LEFTBRACE => K::KEY_Z,
+            K::KEY_SEMICOLON => K::KEY_X,
+            K::KEY_APOSTROPHE => K::KEY_C,
+            K::KEY_P => K::KEY_V,
+            K::KEY_Z => K::KEY_B,
+            K::KEY_B => K::KEY_N,
+            K::KEY_J => K::KEY_SLASH,
+            _ => *key,
+        }
+    }
+
+    /// Only the first two levels, dead keys such as `^` and `´` are left to the system layout
+    fn char_key(&self, c: char) -> Option<(K, bool)> {
+        let (physical, shift) = match c {
+            '1' => (K::KEY_1, false),
+            '°' => (K::KEY_1, true),
+            '2' => (K::KEY_2, false),
+            '§' => (K::KEY_2, true),
+            '3' => (K::KEY_3, false),
+            'ℓ' => (K::KEY_3, true),
+            '4' => (K::KEY_4, false),
+            '»' => (K::KEY_4, true),
+            '5' => (K::KEY_5, false),
+            '«' => (K::KEY_5, true),
+            '6' => (K::KEY_6, false),
+            '$' => (K::KEY_6, true),
+            '7' => (K::KEY_7, false),
+            '€' => (K::KEY_7, true),
+            '8' => (K::KEY_8, false),
+            '„' => (K::KEY_8, true),
+            '9' => (K::KEY_9, false),
+            '“' => (K::KEY_9, true),
+            '0' => (K::KEY_0, false),
+            '”' => (K::KEY_0, true),
+            '-' => (K::KEY_MINUS, false),
+            '—' => (K::KEY_MINUS, true),
+            'ß' => (K::KEY_LEFTBRACE, false),
+            'ẞ' => (K::KEY_LEFTBRACE, true),
+            'ü' => (K::KEY_Z, false),
+            'Ü' => (K::KEY_Z, true),
+            'ö' => (K::KEY_X, false),
+            'Ö' => (K::KEY_X, true),
+            'ä' => (K::KEY_C, false),
+            'Ä' => (K::KEY_C, true),
+            ',' => (K::KEY_COMMA, false),
+            '–' => (K::KEY_COMMA, true),
+            '.' => (K::KEY_DOT, false),
+            '•' => (K::KEY_DOT, true),
+            // Letters are where Neo2 has them, the rest need Mod3 or Mod4 which aren't typed
+            c if c.is_ascii_alphabetic() || c.is_ascii_whitespace() => return qwerty::char_key(c),
+            _ => return None,
+        };
+        Some((self.to(&physical), shift))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layouts::tests::{
+        BOTTOM_ROW, HOME_ROW, NUMBER_ROW, TOP_ROW, assert_round_trip, named,
+    };
+    use evdev::KeyCode as K;
+
+    const HOME_ROW_KEYS: &[K] = &[
+        K::KEY_U,
+        K::KEY_I,
+        K::KEY_A,
+        K::KEY_E,
+        K::KEY_O,
+        K::KEY_S,
+        K::KEY_N,
+        K::KEY_R,
+        K::KEY_T,
+        K::KEY_D,
+        K::KEY_Y,
+    ];
+
+    #[test]
+    fn base_block_round_trips() {
+        assert_round_trip(
+            named("neo2").as_ref(),
+            &[NUMBER_ROW, TOP_ROW, HOME_ROW, BOTTOM_ROW],
+        );
+    }
+
+    #[test]
+    fn home_row_types_the_base_layer() {
+        let layout = named("neo2");
+        let home_row: Vec<K> = HOME_ROW.iter().map(|key| layout.to(key)).collect();
+        assert_eq!(home_row, HOME_ROW_KEYS);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::layouts::{Layout, qwerty};
+use evdev::KeyCode as K;
+
+/// Dvorak with a number row of symbols for programming, typing digits with shift
+pub(crate) struct ProgrammerDvorakLayout;
+
+impl Layout for ProgrammerDvorakLayout {
+    fn to(&self, key: &K) -> K {
+        match *key {
+            K::KEY_2 => K::KEY_LEFTBRACE,
+            K::KEY_6 => K::KEY_EQUAL,
+            K::KEY_0 => K::KEY_RIGHTBRACE,
+            K::KEY_MINUS => K::KEY_2,
+            K::KEY_EQUAL => K::KEY_6,
+            K::KEY_RIGHTBRACE => K::KEY_0,
+            K::KEY_Q => K::KEY_SEMICOLON,
+            K::KEY_W => K::KEY_COMMA,
+            K::KEY_E => K::KEY_DOT,
+            K::KEY_R => K::KEY_P,
+            K::KEY_T => K::KEY_Y,
+            K::KEY_Y => K::KEY_F,
+            K::KEY_U => K::KEY_G,
+            K::KEY_I => K::KEY_C,
+            K::KEY_O => K::KEY_R,
+            K::KEY_P => K::KEY_L,
+            K::KEY_LEFTBRACE => K::KEY_SLASH,
+            K::KEY_A => K::KEY_A,
+            K::KEY_S => K::KEY_O,
+            K::KEY_D => K::KEY_E,
+            K::KEY_F => K::KEY_U,
+            K::KEY_G => K::KEY_I,
+            K::KEY_H => K::KEY_D,
+            K::KEY_J => K::KEY_H,
+            K::KEY_K => K::KEY_T,
+            K::KEY_L => K::KEY_N,
+            K::KEY_SEMICOLON => K::KEY_S,
+            K::KEY_APOSTROPHE => K::KEY_MINUS,
+            K::KEY_Z => K::KEY_APOSTROPHE,
+            K::KEY_X => K::KEY_Q,
+            K::KEY_C => K::KEY_J,
+            K::KEY_V => K::KEY_K,
+            K::KEY_B => K::KEY_X,
+            K::KEY_N => K::KEY_B,
+            K::KEY_M => K::KEY_M,
+            K::KEY_COMMA => K::KEY_W,
+            K::KEY_DOT => K::KEY_V,
+            K::KEY_SLASH => K::KEY_Z,
+            _ => *key,
+        }
+    }
+
+    fn from(&self, key: &K) -> K {
+        match *key {
+            K::KEY_LEFTBRACE => K::KEY_2,
+            K::KEY_EQUAL => K::KEY_6,
+            K::KEY_RIGHTBRACE => K::KEY_0,
+            K::KEY_2 => K::KEY_MINUS,
+            K::KEY_6 => K::KEY_EQUAL,
+            K::KEY_0 => K::KEY_RIGHTBRACE,
+            K::KEY_SEMICOLON => K::KEY_Q,
+            K::KEY_COMMA => K::KEY_W,
+            K::KEY_DOT => K::KEY_E,
+            K::KEY_P => K::KEY_R,
+            K::KEY_Y => K::KEY_T,
+            K::KEY_F => K::KEY_Y,
+            K::KEY_G => K::KEY_U,
+            K::KEY_C => K::KEY_I,
+            K::KEY_R => K::KEY_O,
+            K::KEY_L => K::KEY_P,
+            K::KEY_SLASH => K::KEY_LEFTBRACE,
+            K::KEY_A => K::KEY_A,
+            K::KEY_O => K::KEY_S,
+            K::KEY_E => K::KEY_D,
+            K::KEY_U => K::KEY_F,
+            K::KEY_I => K::KEY_G,
+            K::KEY_D => K::KEY_H,
+            K::KEY_H => K::KEY_J,
+            K::KEY_T => K::KEY_K,
+            K::KEY_N => K::KEY_L,
+            K::KEY_S => K::KEY_SEMICOLON,
+            K::KEY_MINUS => K::KEY_APOSTROPHE,
+            K::KEY_APOSTROPHE => K::KEY_Z,
+            K::KEY_Q => K::KEY_X,
+            K::KEY_J => K::KEY_C,
+            K::KEY_K => K::KEY_V,
+            K::KEY_X => K::KEY_B,
+            K::KEY_B => K::KEY_N,
+            K::KEY_M => K::KEY_M,
+            K::KEY_W => K::KEY_COMMA,
+            K::KEY_V => K::KEY_DOT,
+            K::KEY_Z => K::KEY_SLASH,
+            _ => *key,
+        }
+    }
+
+    /// Keys typing a character, as the number row's symbols and digits don't have keys of
+    /// their own under the names the other layouts use
+    fn char_key(&self, c: char) -> Option<(K, bool)> {
+        let (physical, shift) = match c {
+            '$' => (K::KEY_GRAVE, false),
+            '~' => (K::KEY_GRAVE, true),
+            '&' => (K::KEY_1, false),
+            '%' => (K::KEY_1, true),
+            '[' => (K::KEY_2, false),
+            '7' => (K::KEY_2, true),
+            '{' => (K::KEY_3, false),
+            '5' => (K::KEY_3, true),
+            '}' => (K::KEY_4, false),
+            '3' => (K::KEY_4, true),
+            '(' => (K::KEY_5, false),
+            '1' => (K::KEY_5, true),
+            '=' => (K::KEY_6, false),
+            '9' => (K::KEY_6, true),
+            '*' => (K::KEY_7, false),
+            '0' => (K::KEY_7, true),
+            ')' => (K::KEY_8, false),
+            '2' => (K::KEY_8, true),
+            '+' => (K::KEY_9, false),
+            '4' => (K::KEY_9, true),
+            ']' => (K::KEY_0, false),
+            '6' => (K::KEY_0, true),
+            '!' => (K::KEY_MINUS, false),
+            '8' => (K::KEY_MINUS, true),
+            '#' => (K::KEY_EQUAL, false),
+            '`' => (K::KEY_EQUAL, true),
+            ';' => (K::KEY_Q, false),
+            ':' => (K::KEY_Q, true),
+            ',' => (K::KEY_W, false),
+            '<' => (K::KEY_W, true),
+            '.' => (K::KEY_E, false),
+            '>' => (K::KEY_E, true),
+            '/' => (K::KEY_LEFTBRACE, false),
+            '?' => (K::KEY_LEFTBRACE, true),
+            '@' => (K::KEY_RIGHTBRACE, false),
+            '^' => (K::KEY_RIGHTBRACE, true),
+            '\\' => (K::KEY_BACKSLASH, false),
+            '|' => (K::KEY_BACKSLASH, true),
+            '-' => (K::KEY_APOSTROPHE, false),
+            '_' => (K::KEY_APOSTROPHE, true),
+            '\'' => (K::KEY_Z, false),
+            '"' => (K::KEY_Z, true),
+            // Letters and whitespace are where Dvorak has them
+            _ => return qwerty::char_key(c),
+        };
+        Some((self.to(&physical), shift))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layouts::tests::{
+        BOTTOM_ROW, HOME_ROW, NUMBER_ROW, TOP_ROW, assert_round_trip, named,
+    };
+    use evdev::KeyCode as K;
+
+    #[test]
+    fn main_block_round_trips() {
+        assert_round_trip(
+            named("programmer-dvorak").as_ref(),
+            &[NUMBER_ROW, TOP_ROW, HOME_ROW, BOTTOM_ROW],
+        );
+    }
+
+    #[test]
+    fn symbols_and_digits_reach_their_physical_keys() {
+        let layout = named("programmer-dvorak");
+        for (c, physical, shift) in [
+            ('[', K::KEY_2, false),
+            ('7', K::KEY_2, true),
+            ('=', K::KEY_6, false),
+            ('9', K::KEY_6, true),
+            (']', K::KEY_0, false),
+            ('6', K::KEY_0, true),
+            ('!', K::KEY_MINUS, false),
+            ('#', K::KEY_EQUAL, false),
+            ('@', K::KEY_RIGHTBRACE, false),
+            ('-', K::KEY_APOSTROPHE, false),
+            ('\'', K::KEY_Z, false),
+            ('e', K::KEY_D, false),
+        ] {
+            let (key, shifted) = layout.char_key(c).unwrap();
+            assert_eq!((layout.from(&key), shifted), (physical, shift), "{:?}", c);
+        }
+    }
+}
@@ -12,3 +12,86 @@ impl Layout for QwertyLayout {
         *key
     }
 }
+
+/// Key and whether it's shifted for a character typed on US Qwerty
+pub(crate) fn char_key(c: char) -> Option<(K, bool)> {
+    let key = match c.to_ascii_lowercase() {
+        'a' => K::KEY_A,
+        'b' => K::KEY_B,
+        'c' => K::KEY_C,
+        'd' => K::KEY_D,
+        'e' => K::KEY_E,
+        'f' => K::KEY_F,
+        'g' => K::KEY_G,
+        'h' => K::KEY_H,
+        'i' => K::KEY_I,
+        'j' => K::KEY_J,
+        'k' => K::KEY_K,
+        'l' => K::KEY_L,
+        'm' => K::KEY_M,
+        'n' => K::KEY_N,
+        'o' => K::KEY_O,
+        'p' => K::KEY_P,
+        'q' => K::KEY_Q,
+        'r' => K::KEY_R,
+        's' => K::KEY_S,
+        't' => K::KEY_T,
+        'u' => K::KEY_U,
+        'v' => K::KEY_V,
+        'w' => K::KEY_W,
+        'x' => K::KEY_X,
+        'y' => K::KEY_Y,
+        'z' => K::KEY_Z,
+        _ => {
+            return match c {
+                '1' => Some((K::KEY_1, false)),
+                '2' => Some((K::KEY_2, false)),
+                '3' => Some((K::KEY_3, false)),
+                '4' => Some((K::KEY_4, false)),
+                '5' => Some((K::KEY_5, false)),
+                '6' => Some((K::KEY_6, false)),
+                '7' => Some((K::KEY_7, false)),
+                '8' => Some((K::KEY_8, false)),
+                '9' => Some((K::KEY_9, false)),
+                '0' => Some((K::KEY_0, false)),
+                '!' => Some((K::KEY_1, true)),
+                '@' => Some((K::KEY_2, true)),
+                '#' => Some((K::KEY_3, true)),
+                '$' => Some((K::KEY_4, true)),
+                '%' => Some((K::KEY_5, true)),
+                '^' => Some((K::KEY_6, true)),
+                '&' => Some((K::KEY_7, true)),
+                '*' => Some((K::KEY_8, true)),
+                '(' => Some((K::KEY_9, true)),
+                ')' => Some((K::KEY_0, true)),
+                ' ' => Some((K::KEY_SPACE, false)),
+                '\n' => Some((K::KEY_ENTER, false)),
+                '\t' => Some((K::KEY_TAB, false)),
+                '-' => Some((K::KEY_MINUS, false)),
+                '_' => Some((K::KEY_MINUS, true)),
+                '=' => Some((K::KEY_EQUAL, false)),
+                '+' => Some((K::KEY_EQUAL, true)),
+                '[' => Some((K::KEY_LEFTBRACE, false)),
+                '{' => Some((K::KEY_LEFTBRACE, true)),
+                ']' => Some((K::KEY_RIGHTBRACE, false)),
+                '}' => Some((K::KEY_RIGHTBRACE, true)),
+                '\\' => Some((K::KEY_BACKSLASH, false)),
+                '|' => Some((K::KEY_BACKSLASH, true)),
+                ';' => Some((K::KEY_SEMICOLON, false)),
+                ':' => Some((K::KEY_SEMICOLON, true)),
+                '\'' => Some((K::KEY_APOSTROPHE, false)),
+                '"' => Some((K::KEY_APOSTROPHE, true)),
+                ',' => Some((K::KEY_COMMA, false)),
+                '<' => Some((K::KEY_COMMA, true)),
+                '.' => Some((K::KEY_DOT, false)),
+                '>' => Some((K::KEY_DOT, true)),
+                '/' => Some((K::KEY_SLASH, false)),
+                '?' => Some((K::KEY_SLASH, true)),
+                '`' => Some((K::KEY_GRAVE, false)),
+                '~' => Some((K::KEY_GRAVE, true)),
+                _ => None,
+            };
+        }
+    };
+    Some((key, c.is_ascii_uppercase()))
+}
@@ -0,0 +1,113 @@
+use crate::layouts::{Layout, qwerty};
+use evdev::KeyCode as K;
+
+/// German QWERTZ, keys typing symbols US Qwerty has elsewhere keep their own names
+pub(crate) struct QwertzLayout;
+
+impl Layout for QwertzLayout {
+    fn to(&self, key: &K) -> K {
+        match *key {
+            K::KEY_MINUS => K::KEY_SLASH,
+            K::KEY_Y => K::KEY_Z,
+            K::KEY_Z => K::KEY_Y,
+            K::KEY_SLASH => K::KEY_MINUS,
+            _ => *key,
+        }
+    }
+
+    fn from(&self, key: &K) -> K {
+        match *key {
+            K::KEY_SLASH => K::KEY_MINUS,
+            K::KEY_Z => K::KEY_Y,
+            K::KEY_Y => K::KEY_Z,
+            K::KEY_MINUS => K::KEY_SLASH,
+            _ => *key,
+        }
+    }
+
+    /// Umlauts and ß have keys of their own, the symbols US Qwerty has next to them don't
+    fn char_key(&self, c: char) -> Option<(K, bool)> {
+        let (physical, shift) = match c {
+            '1' => (K::KEY_1, false),
+            '!' => (K::KEY_1, true),
+            '2' => (K::KEY_2, false),
+            '"' => (K::KEY_2, true),
+            '3' => (K::KEY_3, false),
+            '§' => (K::KEY_3, true),
+            '4' => (K::KEY_4, false),
+            '$' => (K::KEY_4, true),
+            '5' => (K::KEY_5, false),
+            '%' => (K::KEY_5, true),
+            '6' => (K::KEY_6, false),
+            '&' => (K::KEY_6, true),
+            '7' => (K::KEY_7, false),
+            '/' => (K::KEY_7, true),
+            '8' => (K::KEY_8, false),
+            '(' => (K::KEY_8, true),
+            '9' => (K::KEY_9, false),
+            ')' => (K::KEY_9, true),
+            '0' => (K::KEY_0, false),
+            '=' => (K::KEY_0, true),
+            '°' => (K::KEY_GRAVE, true),
+            'ß' => (K::KEY_MINUS, false),
+            '?' => (K::KEY_MINUS, true),
+            'ü' => (K::KEY_LEFTBRACE, false),
+            'Ü' => (K::KEY_LEFTBRACE, true),
+            '+' => (K::KEY_RIGHTBRACE, false),
+            '*' => (K::KEY_RIGHTBRACE, true),
+            'ö' => (K::KEY_SEMICOLON, false),
+            'Ö' => (K::KEY_SEMICOLON, true),
+            'ä' => (K::KEY_APOSTROPHE, false),
+            'Ä' => (K::KEY_APOSTROPHE, true),
+            '#' => (K::KEY_BACKSLASH, false),
+            '\'' => (K::KEY_BACKSLASH, true),
+            ',' => (K::KEY_COMMA, false),
+            ';' => (K::KEY_COMMA, true),
+            '.' => (K::KEY_DOT, false),
+            ':' => (K::KEY_DOT, true),
+            '-' => (K::KEY_SLASH, false),
+            '_' => (K::KEY_SLASH, true),
+            '<' => (K::KEY_102ND, false),
+            '>' => (K::KEY_102ND, true),
+            // Letters are where QWERTZ has them, the rest need AltGr which isn't typed
+            c if c.is_ascii_alphabetic() || c.is_ascii_whitespace() => return qwerty::char_key(c),
+            _ => return None,
+        };
+        Some((self.to(&physical), shift))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layouts::tests::{
+        BOTTOM_ROW, HOME_ROW, NUMBER_ROW, TOP_ROW, assert_round_trip, named,
+    };
+    use evdev::KeyCode as K;
+
+    #[test]
+    fn main_block_round_trips() {
+        assert_round_trip(
+            named("qwertz").as_ref(),
+            &[NUMBER_ROW, TOP_ROW, HOME_ROW, BOTTOM_ROW, &[K::KEY_102ND]],
+        );
+    }
+
+    #[test]
+    fn letters_and_symbols_reach_their_physical_keys() {
+        let layout = named("qwertz");
+        for (c, physical, shift) in [
+            ('z', K::KEY_Y, false),
+            ('y', K::KEY_Z, false),
+            ('ß', K::KEY_MINUS, false),
+            ('-', K::KEY_SLASH, false),
+            ('_', K::KEY_SLASH, true),
+            ('(', K::KEY_8, true),
+            ('ü', K::KEY_LEFTBRACE, false),
+            ('<', K::KEY_102ND, false),
+        ] {
+            let (key, shifted) = layout.char_key(c).unwrap();
+            assert_eq!((layout.from(&key), shifted), (physical, shift), "{:?}", c);
+        }
+        assert_eq!(layout.char_key('@'), None);
+    }
+}
@@ -0,0 +1,109 @@
+use crate::layouts::Layout;
+use evdev::KeyCode as K;
+
+pub(crate) struct WorkmanLayout;
+
+impl Layout for WorkmanLayout {
+    fn to(&self, key: &K) -> K {
+        match *key {
+            K::KEY_Q => K::KEY_Q,
+            K::KEY_W => K::KEY_D,
+            K::KEY_E => K::KEY_R,
+            K::KEY_R => K::KEY_W,
+            K::KEY_T => K::KEY_B,
+            K::KEY_Y => K::KEY_J,
+            K::KEY_U => K::KEY_F,
+            K::KEY_I => K::KEY_U,
+            K::KEY_O => K::KEY_P,
+            K::KEY_P => K::KEY_SEMICOLON,
+            K::KEY_A => K::KEY_A,
+            K::KEY_S => K::KEY_S,
+            K::KEY_D => K::KEY_H,
+            K::KEY_F => K::KEY_T,
+            K::KEY_G => K::KEY_G,
+            K::KEY_H => K::KEY_Y,
+            K::KEY_J => K::KEY_N,
+            K::KEY_K => K::KEY_E,
+            K::KEY_L => K::KEY_O,
+            K::KEY_SEMICOLON => K::KEY_I,
+            K::KEY_Z => K::KEY_Z,
+            K::KEY_X => K::KEY_X,
+            K::KEY_C => K::KEY_M,
+            K::KEY_V => K::KEY_C,
+            K::KEY_B => K::KEY_V,
+            K::KEY_N => K::KEY_K,
+            K::KEY_M => K::KEY_L,
+            _ => *key,
+        }
+    }
+
+    fn from(&self, key: &K) -> K {
+        match *key {
+            K::KEY_Q => K::KEY_Q,
+            K::KEY_D => K::KEY_W,
+            K::KEY_R => K::KEY_E,
+            K::KEY_W => K::KEY_R,
+            K::KEY_B => K::KEY_T,
+            K::KEY_J => K::KEY_Y,
+            K::KEY_F => K::KEY_U,
+            K::KEY_U => K::KEY_I,
+            K::KEY_P => K::KEY_O,
+            K::KEY_SEMICOLON => K::KEY_P,
+            K::KEY_A => K::KEY_A,
+            K::KEY_S => K::KEY_S,
+            K::KEY_H => K::KEY_D,
+            K::KEY_T => K::KEY_F,
+            K::KEY_G => K::KEY_G,
+            K::KEY_Y => K::KEY_H,
+            K::KEY_N => K::KEY_J,
+            K::KEY_E => K::KEY_K,
+            K::KEY_O => K::KEY_L,
+            K::KEY_I => K::KEY_SEMICOLON,
+            K::KEY_Z => K::KEY_Z,
+            K::KEY_X => K::KEY_X,
+            K::KEY_M => K::KEY_C,
+            K::KEY_C => K::KEY_V,
+            K::KEY_V => K::KEY_B,
+            K::KEY_K => K::KEY_N,
+            K::KEY_L => K::KEY_M,
+            _ => *key,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layouts::tests::{
+        BOTTOM_ROW, HOME_ROW, NUMBER_ROW, TOP_ROW, assert_round_trip, named,
+    };
+    use evdev::KeyCode as K;
+
+    #[test]
+    fn main_block_round_trips() {
+        assert_round_trip(
+            named("workman").as_ref(),
+            &[NUMBER_ROW, TOP_ROW, HOME_ROW, BOTTOM_ROW],
+        );
+    }
+
+    #[test]
+    fn home_row_types_ashtgyneoi() {
+        let layout = named("workman");
+        let home_row: Vec<K> = HOME_ROW[..10].iter().map(|key| layout.to(key)).collect();
+        assert_eq!(
+            home_row,
+            [
+                K::KEY_A,
+                K::KEY_S,
+                K::KEY_H,
+                K::KEY_T,
+                K::KEY_G,
+                K::KEY_Y,
+                K::KEY_N,
+                K::KEY_E,
+                K::KEY_O,
+                K::KEY_I,
+            ]
+        );
+    }
+}
@@ -0,0 +1,10 @@
+//! The remapping engine behind OxideKeys, without the devices, control socket and recording of
+//! the binary. Feed `features::KeyEvent`s to a `pipeline::Pipeline` through a
+//! `features::Context` writing to any `io::EventSink`, calling its timers once
+//! `next_timer` passes, or run scripted events through a config with `pipeline::simulate`.
+
+pub mod config;
+pub mod features;
+pub mod io;
+pub mod layouts;
+pub mod pipeline;
@@ -1,49 +1,163 @@
-mod config;
+mod app;
+mod args;
+mod device;
 mod keyboard;
-mod layouts;
+mod processor;
+mod record;
+mod socket;
 
 use crate::{
-    config::config,
-    keyboard::{keyboard_processor, open_keyboard_devices},
+    args::Args,
+    keyboard::{
+        MERGED_NAME, added_keyboards, create_virtual_keyboard, keyboard_processor, list_devices,
+        monitor_keyboards, open_keyboard_devices,
+    },
+};
+use crate::{
+    device::SharedDevice,
+    record::{Recorder, replay},
+    socket::{Controllers, Shared},
 };
 use anyhow::Result;
+use crossbeam_channel::{bounded, select, unbounded};
+use flexi_logger::{Cleanup, Criterion, FileSpec, Logger, LoggerHandle, Naming, detailed_format};
+use log::info;
+use oxidekeys::config::{LogConfig, config, expand_path};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
 
 fn main() -> Result<()> {
-    env_logger::init();
-    let config = config()?;
-    let keyboards = open_keyboard_devices(&config)?;
-
-    if keyboards.len() > 1 {
-        if let Some(keyboard) = keyboards.into_iter().next() {
-            if let Err(e) = keyboard_processor(keyboard, &config) {
-                eprintln!("Error processing keyboard: {}", e);
-                return Err(e);
-            }
-        } else {
-            eprintln!("No keyboard found in the list.");
-            return Err(anyhow::anyhow!("No keyboard found"));
-        }
+    let args = Args::parse()?;
+    // Needs no config, it's for writing one
+    if args.list_devices {
+        return list_devices();
+    }
+    let mut config = config(args.config.as_deref())?;
+    config.globals.no_emit |= args.dry_run;
+    config.device = args.device;
+    let _logger = init_logging(config.log.as_ref())?;
+    config.log_migrated();
+
+    if let Some(path) = &args.replay {
+        return replay(path, &config);
+    }
+    let mut record = args.record;
+
+    // Watching before scanning so a keyboard plugged in between isn't missed
+    let monitor = monitor_keyboards()?;
+    let mut keyboards = open_keyboard_devices(&config)?;
+
+    // Dropping the sender disconnects every keyboard's receiver, so all of them shut down
+    let (shutdown_tx, shutdown_rx) = bounded::<()>(0);
+    let shutdown_tx = Mutex::new(Some(shutdown_tx));
+    ctrlc::set_handler(move || {
+        info!("Shutting down");
+        shutdown_tx.lock().unwrap().take();
+    })?;
+
+    let controllers = Controllers::default();
+    let output = if config.merge_output && !config.globals.no_emit {
+        Some(SharedDevice::new(create_virtual_keyboard(
+            MERGED_NAME,
+            &config,
+        )?))
     } else {
-        let mut handles = Vec::new();
+        None
+    };
+    let shared = Arc::new(Shared {
+        profile: Mutex::new(config.default_profile()),
+        output,
+        config: Mutex::new(config.clone()),
+        config_path: args.config.clone(),
+        ..Default::default()
+    });
+    if let Some(path) = &config.socket {
+        socket::serve(&expand_path(path), controllers.clone(), shared.clone())?;
+    }
+    if let Some(command) = &config.app_command {
+        let interval = Duration::from_millis(config.app_interval.unwrap_or(500) as u64);
+        app::watch(command.clone(), interval, controllers.clone());
+    }
+
+    let mut handles = Vec::new();
 
+    // New keyboards are polled for until shutdown, each gets its own thread
+    loop {
+        handles.retain(|handle: &thread::JoinHandle<()>| !handle.is_finished());
         for keyboard in keyboards {
-            let config = config.clone();
+            // Only the first keyboard is recorded, so the recording is of one keyboard's config
+            let recorder = match record.take() {
+                Some(path) => Some(Recorder::create(
+                    &path,
+                    keyboard.device.name().unwrap_or_default(),
+                    shared.profile.lock().unwrap().as_deref(),
+                )?),
+                None => None,
+            };
+            // The config is the reloaded one, if it has been
+            let config = shared.config.lock().unwrap().clone();
+            let shutdown = shutdown_rx.clone();
+            let (commands_tx, commands) = unbounded();
+            controllers.lock().unwrap().push(commands_tx);
+            let controllers = controllers.clone();
+            let shared = shared.clone();
             let handle = thread::spawn(move || {
-                if let Err(e) = keyboard_processor(keyboard, &config) {
+                if let Err(e) = keyboard_processor(
+                    keyboard,
+                    config,
+                    shutdown,
+                    commands,
+                    recorder,
+                    controllers,
+                    shared,
+                ) {
                     eprintln!("Thread error processing keyboard: {}", e);
                 }
             });
             handles.push(handle);
         }
 
-        for handle in handles {
-            if let Err(e) = handle.join() {
-                eprintln!("Thread join error: {:?}", e);
-                return Err(anyhow::anyhow!("Thread join error: {:?}", e));
+        select! {
+            recv(shutdown_rx) -> _ => break,
+            default(Duration::from_millis(250)) => {
+                keyboards = added_keyboards(&monitor, &shared.config.lock().unwrap());
             }
         }
     }
 
+    for handle in handles {
+        if let Err(e) = handle.join() {
+            eprintln!("Thread join error: {:?}", e);
+            return Err(anyhow::anyhow!("Thread join error: {:?}", e));
+        }
+    }
+
+    if let Some(path) = &config.socket {
+        let _ = std::fs::remove_file(expand_path(path));
+    }
+
     Ok(())
 }
+
+/// Logs to stderr, or to a file rotated once it reaches 10 MB when one is configured
+fn init_logging(log: Option<&LogConfig>) -> Result<Option<LoggerHandle>> {
+    let Some(log) = log else {
+        env_logger::init();
+        return Ok(None);
+    };
+
+    // Key logs are colored for the terminal, the escape codes would clutter the file
+    colored::control::set_override(false);
+    let handle = Logger::try_with_env_or_str(log.level.as_str())?
+        .log_to_file(FileSpec::try_from(expand_path(&log.file))?)
+        .format_for_files(detailed_format)
+        .append()
+        .rotate(
+            Criterion::Size(10 * 1024 * 1024),
+            Naming::Numbers,
+            Cleanup::KeepLogFiles(3),
+        )
+        .start()?;
+    Ok(Some(handle))
+}
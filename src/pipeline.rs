@@ -0,0 +1,743 @@
+use crate::config::{Features, KeyboardConfig, app_mappings};
+use crate::features::{
+    AutoShiftFeature, CapsWordFeature, CombosFeature, ComposeFeature, Context, DualFunctionFeature,
+    Feature, Handled, KeyEvent, KeyLockFeature, LayersFeature, MouseKeysFeature, PRESS, RELEASE,
+    REPEAT, RepeatKeyFeature, Stats, SwapHandsFeature, TapDanceFeature, update_leds,
+};
+use crate::io::{MockDevice, Output, OutputEvent};
+use crate::layouts;
+use anyhow::Result;
+use evdev::KeyCode;
+use log::{info, warn};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+/// Kernel autorepeat timings, for a keyboard that sets only one of `repeat_delay_ms` and
+/// `repeat_rate_ms`
+const DEFAULT_REPEAT_DELAY: u16 = 250;
+const DEFAULT_REPEAT_RATE: u16 = 33;
+
+/// The enabled features in processing order, events not handled by any pass through
+pub struct Pipeline {
+    stages: Vec<Stage>,
+    enabled: Features,
+    /// Whether the shared bypass flag was set when last checked
+    bypassed: bool,
+    /// Disabled keys held down, blocked until released even if their mapping changes meanwhile
+    disabled: HashSet<KeyCode>,
+    /// Key repeated in place of the kernel's autorepeat, and when it next repeats
+    repeating: Option<(KeyCode, Instant)>,
+    watchdog: Watchdog,
+    /// Counters of the features before they last started over, kept for the life of the process
+    earlier: BTreeMap<&'static str, Stats>,
+}
+
+/// Safety net for keys left pressed on the virtual device after their release was lost
+struct Watchdog {
+    max_hold: Option<Duration>,
+    /// Physical keys held down
+    physical: HashSet<KeyCode>,
+    /// Keys pressed on the virtual device, and since when
+    held: HashMap<KeyCode, Instant>,
+}
+
+struct Stage {
+    name: &'static str,
+    feature: Box<dyn Feature>,
+    /// Key presses the feature handled
+    consumed: u64,
+}
+
+impl Pipeline {
+    pub fn new(enabled: &Features, max_hold: Option<u16>) -> Self {
+        let feature_enabled = |name: &str| *enabled.get(name).unwrap_or(&false);
+
+        let mut stages = Vec::new();
+        let mut push = |name, feature: Box<dyn Feature>| {
+            stages.push(Stage {
+                name,
+                feature,
+                consumed: 0,
+            })
+        };
+        // Mirrors keys before anything else sees them
+        if feature_enabled("swap_hands") {
+            push("swap_hands", Box::<SwapHandsFeature>::default());
+        }
+        if feature_enabled("combos") {
+            push("combos", Box::<CombosFeature>::default());
+        }
+        if feature_enabled("tapdance") {
+            push("tapdance", Box::<TapDanceFeature>::default());
+        }
+        if feature_enabled("caps_word") {
+            push("caps_word", Box::<CapsWordFeature>::default());
+        }
+        if feature_enabled("keylock") {
+            push("keylock", Box::<KeyLockFeature>::default());
+        }
+        if feature_enabled("compose") {
+            push("compose", Box::<ComposeFeature>::default());
+        }
+        if feature_enabled("auto_shift") {
+            push("auto_shift", Box::<AutoShiftFeature>::default());
+        }
+        if feature_enabled("repeat_key") {
+            push("repeat_key", Box::<RepeatKeyFeature>::default());
+        }
+        if feature_enabled("mouse_keys") {
+            push("mouse_keys", Box::<MouseKeysFeature>::default());
+        }
+        if feature_enabled("layers") {
+            push("layers", Box::<LayersFeature>::default());
+        }
+        if feature_enabled("dual_function") {
+            push(
+                "dual_function",
+                Box::new(DualFunctionFeature::new(feature_enabled("exec"))),
+            );
+        }
+
+        Self {
+            stages,
+            enabled: enabled.clone(),
+            bypassed: false,
+            disabled: HashSet::new(),
+            repeating: None,
+            watchdog: Watchdog {
+                max_hold: max_hold.map(|ms| Duration::from_millis(ms as u64)),
+                physical: HashSet::new(),
+                held: HashMap::new(),
+            },
+            earlier: BTreeMap::new(),
+        }
+    }
+
+    /// Starts over with the features enabled now, keeping their counters
+    pub fn restart(&mut self, enabled: &Features, max_hold: Option<u16>) {
+        let earlier = self.stats();
+        *self = Self::new(enabled, max_hold);
+        self.earlier = earlier;
+    }
+
+    pub fn bypassed(&self) -> bool {
+        self.bypassed
+    }
+
+    /// Catches up with the shared bypass flag. Engaging it releases everything held and turns
+    /// every layer off, and the features start over either way
+    pub fn sync_bypass(&mut self, ctx: &mut Context) -> Result<()> {
+        let bypass = ctx.bypass.load(Ordering::Relaxed);
+        if bypass == self.bypassed {
+            return Ok(());
+        }
+        self.bypassed = bypass;
+
+        if bypass {
+            ctx.output.release_all()?;
+            ctx.keys_down.clear();
+            ctx.holds_triggered.clear();
+            ctx.active_layers.clear();
+            ctx.replay.clear();
+            update_leds(ctx);
+            info!("Bypass on, keys pass through");
+        } else {
+            info!("Bypass off");
+        }
+        self.earlier = self.stats();
+        self.stages = Self::new(&self.enabled, None).stages;
+        Ok(())
+    }
+
+    /// The earliest instant any feature, or the stuck key watchdog, needs its timer called
+    pub fn next_timer(&self) -> Option<Instant> {
+        self.stages
+            .iter()
+            .filter_map(|stage| stage.feature.next_timer())
+            .chain(self.repeating.map(|(_, next)| next))
+            .chain(self.watchdog_deadline())
+            .min()
+    }
+
+    /// When the longest held key counts as stuck, if no key is held and no feature latched one
+    fn watchdog_deadline(&self) -> Option<Instant> {
+        let watchdog = &self.watchdog;
+        if !watchdog.physical.is_empty() || self.stages.iter().any(|s| s.feature.latched()) {
+            return None;
+        }
+        Some(*watchdog.held.values().min()? + watchdog.max_hold?)
+    }
+
+    /// Keeps up with the keys pressed on the virtual device
+    fn track_held(&mut self, ctx: &Context, now: Instant) {
+        let held = &mut self.watchdog.held;
+        held.retain(|key, _| ctx.output.is_pressed(key));
+        for key in ctx.output.pressed() {
+            held.entry(key).or_insert(now);
+        }
+    }
+
+    pub fn process_event(&mut self, ctx: &mut Context, event: KeyEvent) -> Result<()> {
+        match event.state {
+            PRESS => self.watchdog.physical.insert(event.key),
+            RELEASE => self.watchdog.physical.remove(&event.key),
+            _ => false,
+        };
+
+        // With its own timings the last key pressed is repeated here instead of by the kernel,
+        // bypass leaves the kernel's repeats alone
+        if let Some((delay, _)) = repeat_timing(ctx).filter(|_| !self.bypassed) {
+            match event.state {
+                PRESS => self.repeating = Some((event.key, event.time + delay)),
+                RELEASE => {
+                    if self.repeating.is_some_and(|(key, _)| key == event.key) {
+                        self.repeating = None;
+                    }
+                }
+                _ => return Ok(()),
+            }
+        } else {
+            self.repeating = None;
+        }
+
+        self.process(ctx, event)?;
+        self.track_held(ctx, event.time);
+        Ok(())
+    }
+
+    fn process(&mut self, ctx: &mut Context, event: KeyEvent) -> Result<()> {
+        // Sent as the physical key it came from, whatever any feature would do with it
+        if ctx.config.passthrough.contains(&event.key) {
+            return ctx.output.send_key(&event.key, event.state);
+        }
+
+        if ctx
+            .config
+            .mappings
+            .get(&event.key)
+            .and_then(|remap| remap.bypass_toggle)
+            == Some(true)
+        {
+            if event.state == PRESS {
+                let bypass = &ctx.bypass;
+                bypass.store(!bypass.load(Ordering::Relaxed), Ordering::Relaxed);
+            }
+            return self.sync_bypass(ctx);
+        }
+
+        self.sync_bypass(ctx)?;
+        if self.bypassed {
+            return ctx.output.send_key(&event.key, event.state);
+        }
+
+        // Whether a key is blocked is decided on press, so its release goes the same way
+        let disabled = match event.state {
+            PRESS => {
+                ctx.config
+                    .mappings
+                    .get(&event.key)
+                    .and_then(|remap| remap.disable)
+                    == Some(true)
+                    && self.disabled.insert(event.key)
+            }
+            RELEASE => self.disabled.remove(&event.key),
+            _ => self.disabled.contains(&event.key),
+        };
+        if disabled {
+            return Ok(());
+        }
+
+        process_event(&mut self.stages, ctx, event)
+    }
+
+    /// Each feature's counters, along with the key presses it handled, since the process started
+    pub fn stats(&self) -> BTreeMap<&'static str, Stats> {
+        let mut all = self.earlier.clone();
+        for stage in &self.stages {
+            let mut stats = stage.feature.stats();
+            stats.insert("consumed", stage.consumed);
+            let total = all.entry(stage.name).or_default();
+            for (counter, count) in stats {
+                *total.entry(counter).or_default() += count;
+            }
+        }
+        all
+    }
+
+    /// Calls the timers that are due, events they release only go to the features after them
+    pub fn process_timer_event(&mut self, ctx: &mut Context, now: Instant) -> Result<()> {
+        let stages = &mut self.stages;
+        for index in 0..stages.len() {
+            if stages[index]
+                .feature
+                .next_timer()
+                .is_some_and(|deadline| deadline <= now)
+            {
+                stages[index].feature.on_timer(ctx, now)?;
+                process_replay(&mut stages[index + 1..], ctx)?;
+                send_deferred(ctx)?;
+            }
+        }
+
+        if let Some((key, next)) = self.repeating.filter(|(_, next)| *next <= now)
+            && let Some((_, rate)) = repeat_timing(ctx)
+        {
+            let repeat = KeyEvent {
+                key,
+                state: REPEAT,
+                time: now,
+            };
+            // Repeats missed while busy are skipped rather than sent in a burst
+            let next = next + rate;
+            self.repeating = Some((key, if next <= now { now + rate } else { next }));
+            self.process(ctx, repeat)?;
+        }
+        self.track_held(ctx, now);
+
+        if self
+            .watchdog_deadline()
+            .is_some_and(|deadline| deadline <= now)
+            && let Some(max_hold) = self.watchdog.max_hold
+        {
+            let stuck: Vec<KeyCode> = self
+                .watchdog
+                .held
+                .iter()
+                .filter(|(_, since)| **since + max_hold <= now)
+                .map(|(key, _)| *key)
+                .collect();
+            for key in stuck {
+                warn!(
+                    "Released {:?}, pressed for over {}ms with no key held",
+                    key,
+                    max_hold.as_millis()
+                );
+                ctx.output.force_release(&key)?;
+                self.watchdog.held.remove(&key);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Delay before the pipeline's own repeats and the interval between them, when the keyboard
+/// sets either instead of leaving repeats to the kernel
+fn repeat_timing(ctx: &Context) -> Option<(Duration, Duration)> {
+    let config = &ctx.config;
+    if config.repeat_delay_ms.is_none() && config.repeat_rate_ms.is_none() {
+        return None;
+    }
+    let delay = config.repeat_delay_ms.unwrap_or(DEFAULT_REPEAT_DELAY);
+    let rate = config.repeat_rate_ms.unwrap_or(DEFAULT_REPEAT_RATE).max(1);
+    Some((
+        Duration::from_millis(delay as u64),
+        Duration::from_millis(rate as u64),
+    ))
+}
+
+/// Runs scripted events through a pipeline writing to a mock device, calling the timers due
+/// between them at the time they were due, and returns what it wrote
+pub fn simulate(
+    config: KeyboardConfig,
+    features: &Features,
+    events: &[KeyEvent],
+) -> Result<Vec<OutputEvent>> {
+    let device = MockDevice::default();
+    let mut ctx = mock_context(config, &device)?;
+    let mut pipeline = Pipeline::new(features, ctx.config.max_hold);
+
+    for event in events {
+        while let Some(deadline) = pipeline.next_timer().filter(|d| *d <= event.time) {
+            pipeline.process_timer_event(&mut ctx, deadline)?;
+        }
+        pipeline.process_event(&mut ctx, *event)?;
+    }
+    // Repeating timers such as mouse keys would run forever, so they stop a second after
+    if let Some(end) = events
+        .last()
+        .map(|event| event.time + Duration::from_secs(1))
+    {
+        while let Some(deadline) = pipeline.next_timer().filter(|d| *d <= end) {
+            pipeline.process_timer_event(&mut ctx, deadline)?;
+        }
+    }
+
+    Ok(device.events())
+}
+
+/// Context of a keyboard writing to a mock device, with every app's mappings left out
+fn mock_context(mut config: KeyboardConfig, device: &MockDevice) -> Result<Context> {
+    config.mappings = app_mappings(&config.mappings, None);
+    let layout = layouts::get(config.layout.as_ref())?;
+    let mut ctx = Context {
+        output: Output::new(Box::new(device.clone()), layout),
+        config,
+        keys_down: HashSet::new(),
+        holds_triggered: HashSet::new(),
+        active_layers: HashSet::new(),
+        replay: Vec::new(),
+        deferred: Vec::new(),
+        leds: None,
+        app: None,
+        profile: None,
+        reload: false,
+        bypass: Arc::default(),
+    };
+    ctx.output.set_same_frame(ctx.config.same_frame);
+    Ok(ctx)
+}
+
+/// Runs an event through the features in order, passing it through if none handled it
+fn process_event(stages: &mut [Stage], ctx: &mut Context, event: KeyEvent) -> Result<()> {
+    let mut key_handled = false;
+
+    for index in 0..stages.len() {
+        let handled = stages[index].feature.on_event(ctx, &event)?;
+        if event.state == PRESS && handled != Handled::No {
+            stages[index].consumed += 1;
+        }
+        // Events held back by this feature come before the current one for the features after it
+        process_replay(&mut stages[index + 1..], ctx)?;
+
+        match handled {
+            Handled::No => {}
+            Handled::Yes => key_handled = true,
+            Handled::Stop => return send_deferred(ctx),
+        }
+    }
+
+    if !key_handled {
+        ctx.output.send_key(&event.key, event.state)?;
+    }
+
+    send_deferred(ctx)
+}
+
+/// Sends the keys layers pressed, once the features after them have had the event
+fn send_deferred(ctx: &mut Context) -> Result<()> {
+    for keys in std::mem::take(&mut ctx.deferred) {
+        if !keys.is_empty() {
+            ctx.output.send_keys(&keys, PRESS)?;
+        }
+    }
+    Ok(())
+}
+
+fn process_replay(stages: &mut [Stage], ctx: &mut Context) -> Result<()> {
+    for event in std::mem::take(&mut ctx.replay) {
+        process_event(stages, ctx, event)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::{Config, LayoutConfig, from_yaml};
+    use crate::io::OutputEvent::{Press, Release, Repeat};
+    use evdev::KeyCode as K;
+
+    /// Key events, each at its milliseconds after the first
+    pub(crate) fn script(events: &[(u64, KeyCode, i32)]) -> Vec<KeyEvent> {
+        let start = Instant::now();
+        events
+            .iter()
+            .map(|&(ms, key, state)| KeyEvent {
+                key,
+                state,
+                time: start + Duration::from_millis(ms),
+            })
+            .collect()
+    }
+
+    /// Runs the script through the keyboard named `Test` of a YAML config
+    pub(crate) fn run(yaml: &str, events: &[(u64, KeyCode, i32)]) -> Vec<OutputEvent> {
+        let config = from_yaml(yaml).unwrap();
+        let (features, keyboard) = config.keyboard(None, "Test");
+        simulate(keyboard, &features, &script(events)).unwrap()
+    }
+
+    /// Runs the script through the default config's keyboard, in Qwerty
+    fn run_default(events: &[(u64, KeyCode, i32)]) -> Vec<OutputEvent> {
+        let config = Config::default();
+        let (features, mut keyboard) = config.keyboard(None, "AT Translated Set 2 keyboard");
+        keyboard.layout = Some(LayoutConfig::Named("qwerty".into()));
+        simulate(keyboard, &features, &script(events)).unwrap()
+    }
+
+    #[test]
+    fn space_tapped_alone_types_space() {
+        let output = run_default(&[(0, K::KEY_SPACE, PRESS), (50, K::KEY_SPACE, RELEASE)]);
+        assert_eq!(output, [Press(K::KEY_SPACE), Release(K::KEY_SPACE)]);
+    }
+
+    #[test]
+    fn space_overlapped_holds_shift() {
+        let output = run_default(&[
+            (0, K::KEY_SPACE, PRESS),
+            (30, K::KEY_A, PRESS),
+            (60, K::KEY_A, RELEASE),
+            (90, K::KEY_SPACE, RELEASE),
+        ]);
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_LEFTSHIFT),
+                Press(K::KEY_A),
+                Release(K::KEY_A),
+                Release(K::KEY_LEFTSHIFT),
+            ]
+        );
+    }
+
+    #[test]
+    fn configured_hold_fires_past_its_hold_term() {
+        let yaml = "
+keyboards:
+  Test:
+    layout: qwerty
+    layers: {}
+    mappings:
+      KEY_F:
+        tap: [KEY_F]
+        hold: [KEY_LEFTCTRL]
+        hrm_term: 200
+";
+        let output = run(yaml, &[(0, K::KEY_F, PRESS), (300, K::KEY_F, RELEASE)]);
+        assert_eq!(output, [Press(K::KEY_LEFTCTRL), Release(K::KEY_LEFTCTRL)]);
+    }
+
+    #[test]
+    fn unmapped_keys_pass_through() {
+        let output = run_default(&[(0, K::KEY_A, PRESS), (50, K::KEY_A, RELEASE)]);
+        assert_eq!(output, [Press(K::KEY_A), Release(K::KEY_A)]);
+    }
+
+    #[test]
+    fn keyboards_override_the_global_features() {
+        let config = from_yaml(
+            "
+features:
+  auto_shift: true
+  dual_function: true
+  layers: true
+keyboards:
+  Split:
+    layout: qwerty
+  Laptop:
+    layout: qwerty
+    features:
+      auto_shift: false
+      dual_function: false
+",
+        )
+        .unwrap();
+        let stages = |name| {
+            let (features, keyboard) = config.keyboard(None, name);
+            let pipeline = Pipeline::new(&features, keyboard.max_hold);
+            pipeline.stats().into_keys().collect::<Vec<_>>()
+        };
+
+        let split = stages("Split");
+        assert!(split.contains(&"auto_shift"));
+        assert!(split.contains(&"dual_function"));
+        let laptop = stages("Laptop");
+        assert!(!laptop.contains(&"auto_shift"));
+        assert!(!laptop.contains(&"dual_function"));
+        assert!(laptop.contains(&"layers"));
+    }
+
+    #[test]
+    fn counters_survive_bypass_and_restarts() {
+        let config = from_yaml(
+            "
+keyboards:
+  Test:
+    layout: qwerty
+    layers: {}
+    mappings:
+      KEY_F: {tap: [KEY_F], hold: [KEY_LEFTCTRL]}
+",
+        )
+        .unwrap();
+        let (features, keyboard) = config.keyboard(None, "Test");
+        let device = MockDevice::default();
+        let mut ctx = mock_context(keyboard, &device).unwrap();
+        let mut pipeline = Pipeline::new(&features, None);
+        let tap = |pipeline: &mut Pipeline, ctx: &mut Context| {
+            for event in script(&[(0, K::KEY_F, PRESS), (50, K::KEY_F, RELEASE)]) {
+                pipeline.process_event(ctx, event).unwrap();
+            }
+        };
+        let counters = |pipeline: &Pipeline| {
+            let stats = &pipeline.stats()["dual_function"];
+            (stats["taps"], stats["consumed"])
+        };
+
+        tap(&mut pipeline, &mut ctx);
+        assert_eq!(counters(&pipeline), (1, 1));
+
+        for bypass in [true, false] {
+            ctx.bypass.store(bypass, Ordering::Relaxed);
+            pipeline.sync_bypass(&mut ctx).unwrap();
+        }
+        tap(&mut pipeline, &mut ctx);
+        assert_eq!(counters(&pipeline), (2, 2));
+
+        pipeline.restart(&features, None);
+        tap(&mut pipeline, &mut ctx);
+        assert_eq!(counters(&pipeline), (3, 3));
+    }
+
+    #[test]
+    fn held_keys_repeat_at_the_global_timings() {
+        let yaml = "
+globals:
+  repeat_delay_ms: 100
+  repeat_rate_ms: 50
+keyboards:
+  Test:
+    layout: qwerty
+    layers: {}
+    mappings: {}
+";
+        let output = run(yaml, &[(0, K::KEY_J, PRESS), (220, K::KEY_J, RELEASE)]);
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_J),
+                Repeat(K::KEY_J),
+                Repeat(K::KEY_J),
+                Repeat(K::KEY_J),
+                Release(K::KEY_J)
+            ]
+        );
+
+        // A keyboard's own delay wins, and the rate stays the global one
+        let own_delay = yaml.replace("mappings: {}", "mappings: {}\n    repeat_delay_ms: 180");
+        let output = run(
+            &own_delay,
+            &[(0, K::KEY_J, PRESS), (220, K::KEY_J, RELEASE)],
+        );
+        assert_eq!(
+            output,
+            [Press(K::KEY_J), Repeat(K::KEY_J), Release(K::KEY_J)]
+        );
+    }
+
+    const REPEATING: &str = "
+globals:
+  repeat_delay_ms: 100
+  repeat_rate_ms: 50
+keyboards:
+  Test:
+    layout: qwerty
+    mappings:
+      KEY_F:
+        tap: [KEY_F]
+        hold: [KEY_LEFTCTRL]
+        hrm_term: 200
+      KEY_Q:
+        chord: [KEY_LEFTCTRL, KEY_C]
+    layers:
+      Navigation:
+        KEY_RIGHTALT:
+          KEY_J: [KEY_DOWN]
+";
+
+    #[test]
+    fn held_layer_keys_repeat_what_the_layer_sends() {
+        let output = run(
+            REPEATING,
+            &[
+                (0, K::KEY_RIGHTALT, PRESS),
+                (10, K::KEY_J, PRESS),
+                (230, K::KEY_J, RELEASE),
+                (240, K::KEY_RIGHTALT, RELEASE),
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_DOWN),
+                Repeat(K::KEY_DOWN),
+                Repeat(K::KEY_DOWN),
+                Repeat(K::KEY_DOWN),
+                Release(K::KEY_DOWN)
+            ]
+        );
+    }
+
+    #[test]
+    fn held_chords_repeat_only_their_keys() {
+        let output = run(REPEATING, &[(0, K::KEY_Q, PRESS), (220, K::KEY_Q, RELEASE)]);
+        assert_eq!(
+            output,
+            [
+                Press(K::KEY_LEFTCTRL),
+                Press(K::KEY_C),
+                Repeat(K::KEY_C),
+                Repeat(K::KEY_C),
+                Repeat(K::KEY_C),
+                Release(K::KEY_C),
+                Release(K::KEY_LEFTCTRL)
+            ]
+        );
+    }
+
+    #[test]
+    fn keys_with_nothing_to_repeat_yet_do_not_repeat() {
+        // Still deciding between tap and hold
+        let output = run(REPEATING, &[(0, K::KEY_F, PRESS), (150, K::KEY_F, RELEASE)]);
+        assert_eq!(output, [Press(K::KEY_F), Release(K::KEY_F)]);
+
+        let output = run(
+            REPEATING,
+            &[(0, K::KEY_RIGHTALT, PRESS), (220, K::KEY_RIGHTALT, RELEASE)],
+        );
+        assert_eq!(output, []);
+
+        let long_delay = REPEATING.replace("repeat_delay_ms: 100", "repeat_delay_ms: 500");
+        let output = run(
+            &long_delay,
+            &[(0, K::KEY_J, PRESS), (400, K::KEY_J, RELEASE)],
+        );
+        assert_eq!(output, [Press(K::KEY_J), Release(K::KEY_J)]);
+    }
+
+    #[test]
+    fn disabled_key_sends_nothing() {
+        let yaml = "
+keyboards:
+  Test:
+    layout: qwerty
+    layers: {}
+    mappings:
+      KEY_CAPSLOCK: {disable: true}
+      KEY_F: {tap: [KEY_F], hold: [KEY_LEFTCTRL]}
+";
+        let output = run(
+            yaml,
+            &[
+                (0, K::KEY_CAPSLOCK, PRESS),
+                (300, K::KEY_CAPSLOCK, REPEAT),
+                (350, K::KEY_CAPSLOCK, RELEASE),
+            ],
+        );
+        assert_eq!(output, []);
+
+        // Nor does it count as another key pressed while a dual-function key is held
+        let output = run(
+            yaml,
+            &[
+                (0, K::KEY_F, PRESS),
+                (20, K::KEY_CAPSLOCK, PRESS),
+                (40, K::KEY_CAPSLOCK, RELEASE),
+                (60, K::KEY_F, RELEASE),
+            ],
+        );
+        assert_eq!(output, [Press(K::KEY_F), Release(K::KEY_F)]);
+    }
+}
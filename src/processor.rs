@@ -0,0 +1,489 @@
+use crate::device::Leds;
+use crate::record::Recorder;
+use crate::socket::{self, Command, Controllers, KeyboardStatus, LayerCommand, Shared};
+use anyhow::Result;
+use evdev::{EventType, InputEvent, KeyCode};
+use log::{info, warn};
+use oxidekeys::config::{Config, Features, KeyboardConfig, Mappings, app_mappings};
+use oxidekeys::features::{Context, KeyEvent, PRESS, RELEASE, update_leds};
+use oxidekeys::io::{EventSink, LedSink, Output};
+use oxidekeys::layouts;
+use oxidekeys::pipeline::Pipeline;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+
+/// Everything a keyboard does with its events, commands and timers, apart from how they get
+/// to it. Whatever drives it reads the device, feeds each event to `input`, each command to
+/// `command`, and calls `timer` once `next_timer` has passed
+pub struct Processor {
+    name: String,
+    config: Config,
+    ctx: Context,
+    pipeline: Pipeline,
+    /// Mappings of the keyboard, including the ones limited to some apps
+    mappings: Mappings,
+    /// Layout keys the physical keys held were pressed as
+    inputs: HashMap<KeyCode, KeyCode>,
+    events: u64,
+    /// Event node of the keyboard, for its LEDs, none in a dry run
+    leds_path: Option<PathBuf>,
+    recorder: Option<Recorder>,
+    controllers: Controllers,
+    /// State shared with the other keyboards
+    shared: Arc<Shared>,
+}
+
+impl Processor {
+    /// Processor of the keyboard `name` at the event node `path`, writing to `sink`
+    pub fn new(
+        name: &str,
+        path: PathBuf,
+        sink: Box<dyn EventSink>,
+        config: Config,
+        recorder: Option<Recorder>,
+        controllers: Controllers,
+        shared: Arc<Shared>,
+    ) -> Result<Self> {
+        // LEDs are left alone in a dry run
+        let leds_path = (!config.globals.no_emit).then_some(path);
+        let profile = shared.profile.lock().unwrap().clone();
+        let (features, keyboard_config) = config.keyboard(profile.as_deref(), name);
+        let layout = layouts::get(keyboard_config.layout.as_ref())?;
+        // Mappings limited to some apps are left out until the app is focused
+        let mappings = keyboard_config.mappings.clone();
+        let mut ctx = Context {
+            output: Output::new(sink, layout),
+            leds: open_leds(leds_path.as_deref(), &keyboard_config),
+            config: keyboard_config,
+            keys_down: HashSet::new(),
+            holds_triggered: HashSet::new(),
+            active_layers: HashSet::new(),
+            replay: Vec::new(),
+            deferred: Vec::new(),
+            app: None,
+            profile: None,
+            reload: false,
+            bypass: shared.bypass.clone(),
+        };
+        ctx.config.mappings = app_mappings(&mappings, None);
+        ctx.output.set_same_frame(ctx.config.same_frame);
+        let pipeline = Pipeline::new(&features, ctx.config.max_hold);
+
+        Ok(Self {
+            name: name.to_owned(),
+            config,
+            ctx,
+            pipeline,
+            mappings,
+            inputs: HashMap::new(),
+            events: 0,
+            leds_path,
+            recorder,
+            controllers,
+            shared,
+        })
+    }
+
+    /// When `timer` next needs to be called
+    pub fn next_timer(&self) -> Option<Instant> {
+        self.pipeline.next_timer()
+    }
+
+    /// Processes an event read from the keyboard, anything but a key is ignored
+    pub fn input(&mut self, event: &InputEvent) -> Result<()> {
+        if event.event_type() != EventType::KEY {
+            return Ok(());
+        }
+        self.events += 1;
+        let state = event.value();
+        let key_raw = KeyCode(event.code());
+        let layout = self.ctx.output.layout();
+        // Keys release as whatever they pressed as, even if the layout switched between
+        let key_layout = match state {
+            PRESS => {
+                let key_layout = layout.to(&key_raw);
+                self.inputs.insert(key_raw, key_layout);
+                key_layout
+            }
+            RELEASE => self
+                .inputs
+                .remove(&key_raw)
+                .unwrap_or_else(|| layout.to(&key_raw)),
+            _ => self
+                .inputs
+                .get(&key_raw)
+                .copied()
+                .unwrap_or_else(|| layout.to(&key_raw)),
+        };
+        let event = KeyEvent {
+            key: key_layout,
+            state,
+            time: event_time(event),
+        };
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(&event)?;
+        }
+        let bypassed = self.pipeline.bypassed();
+        self.pipeline.process_event(&mut self.ctx, event)?;
+        // A bypass key flipped the shared flag, the other keyboards release what they hold
+        if self.pipeline.bypassed() != bypassed {
+            socket::notify_bypass(&self.controllers);
+        }
+        self.after_event();
+        Ok(())
+    }
+
+    /// Calls the timers that are due
+    pub fn timer(&mut self, now: Instant) -> Result<()> {
+        self.pipeline.process_timer_event(&mut self.ctx, now)?;
+        self.after_event();
+        Ok(())
+    }
+
+    /// Applies a command from the control socket, another keyboard or the focused app
+    pub fn command(&mut self, command: Command) -> Result<()> {
+        match command {
+            Command::Profile(profile) => {
+                let (features, keyboard_config) = self.config.keyboard(Some(&profile), &self.name);
+                self.restart(&features, keyboard_config)?;
+                info!("Profile switched: {}", profile);
+            }
+            Command::Reload(new_config) => {
+                self.config = *new_config;
+                let profile = self.shared.profile.lock().unwrap().clone();
+                let (features, keyboard_config) =
+                    self.config.keyboard(profile.as_deref(), &self.name);
+                self.restart(&features, keyboard_config)?;
+            }
+            Command::Bypass => self.pipeline.sync_bypass(&mut self.ctx)?,
+            command => self.run_command(command),
+        }
+        self.after_event();
+        Ok(())
+    }
+
+    /// Releases every key held, for when the keyboard goes away or the process exits
+    pub fn release(&mut self) -> Result<()> {
+        self.ctx.output.release_all()
+    }
+
+    /// Puts the keyboard's LEDs back the way they were
+    pub fn restore_leds(&mut self) -> Result<()> {
+        if let Some(leds) = self.ctx.leds.as_mut() {
+            leds.restore()?;
+        }
+        Ok(())
+    }
+
+    /// Passes on the profile switch or reload a mapping asked for
+    fn after_event(&mut self) {
+        // Every keyboard switches, this one included once it gets the command
+        if let Some(profile) = self.ctx.profile.take() {
+            socket::switch_profile(&self.controllers, &self.shared, &profile);
+        }
+        // A config that doesn't load is logged and the running one kept
+        if std::mem::take(&mut self.ctx.reload) {
+            let _ = socket::reload(&self.controllers, &self.shared);
+        }
+    }
+
+    /// Starts the keyboard over with a new config, releasing everything held and turning every
+    /// layer off
+    fn restart(&mut self, features: &Features, keyboard_config: KeyboardConfig) -> Result<()> {
+        let ctx = &mut self.ctx;
+        self.mappings = keyboard_config.mappings.clone();
+        self.pipeline.restart(features, keyboard_config.max_hold);
+        ctx.output.release_all()?;
+        if let Some(leds) = ctx.leds.as_mut() {
+            leds.restore()?;
+        }
+        ctx.output
+            .set_layout(layouts::get(keyboard_config.layout.as_ref())?);
+        ctx.output.set_same_frame(keyboard_config.same_frame);
+        ctx.leds = open_leds(self.leds_path.as_deref(), &keyboard_config);
+        ctx.config = keyboard_config;
+        ctx.config.mappings = app_mappings(&self.mappings, ctx.app.as_deref());
+        ctx.keys_down.clear();
+        ctx.holds_triggered.clear();
+        ctx.active_layers.clear();
+        ctx.replay.clear();
+        Ok(())
+    }
+
+    /// Applies a control socket or focused app command to this keyboard
+    fn run_command(&mut self, command: Command) {
+        let ctx = &mut self.ctx;
+        match command {
+            Command::Layer(action, layer_name) => {
+                if !ctx.config.layers.contains_key(&layer_name) {
+                    return;
+                }
+                let on = match action {
+                    LayerCommand::On => true,
+                    LayerCommand::Off => false,
+                    LayerCommand::Toggle => !ctx.active_layers.contains(&layer_name),
+                };
+                if on {
+                    ctx.active_layers.insert(layer_name);
+                } else {
+                    ctx.active_layers.remove(&layer_name);
+                }
+                update_leds(ctx);
+            }
+            Command::Status(reply) => {
+                let mut layers: Vec<String> = ctx.active_layers.iter().cloned().collect();
+                layers.sort();
+                let _ = reply.send(KeyboardStatus {
+                    name: self.name.clone(),
+                    layers,
+                    held: ctx.output.pressed(),
+                    events: self.events,
+                    stats: self.pipeline.stats(),
+                });
+            }
+            Command::App(app) => {
+                if ctx.app != app {
+                    ctx.config.mappings = app_mappings(&self.mappings, app.as_deref());
+                    ctx.app = app;
+                }
+            }
+            // Restart the keyboard or its features, handled by `command`
+            Command::Profile(_) | Command::Bypass | Command::Reload(_) => {}
+        }
+    }
+}
+
+/// Drives the processor from a tokio task instead of a thread, reading the device through
+/// `AsyncFd` and waiting for timers with `sleep_until`. Runs until the keyboard goes away, or
+/// every command sender is dropped. Either way, or if processing fails, the keys held are
+/// released and the LEDs restored
+#[cfg(feature = "tokio")]
+pub async fn run_async(
+    processor: Processor,
+    device: evdev::Device,
+    commands: tokio::sync::mpsc::UnboundedReceiver<Command>,
+) -> Result<()> {
+    drive(processor, device.into_event_stream()?, commands).await
+}
+
+/// Where `run_async` reads the keyboard's events from
+#[cfg(feature = "tokio")]
+trait EventSource {
+    fn next_event(&mut self) -> impl Future<Output = std::io::Result<InputEvent>> + Send;
+}
+
+#[cfg(feature = "tokio")]
+impl EventSource for evdev::EventStream {
+    async fn next_event(&mut self) -> std::io::Result<InputEvent> {
+        evdev::EventStream::next_event(self).await
+    }
+}
+
+#[cfg(feature = "tokio")]
+async fn drive(
+    processor: Processor,
+    mut events: impl EventSource,
+    mut commands: tokio::sync::mpsc::UnboundedReceiver<Command>,
+) -> Result<()> {
+    let processor = Arc::new(std::sync::Mutex::new(processor));
+    let result = drive_loop(&processor, &mut events, &mut commands).await;
+    let released = blocking(&processor, |processor| {
+        let released = processor.release();
+        // The device may be gone already, which doesn't stop the keys being released
+        if let Err(e) = processor.restore_leds() {
+            warn!("Failed to restore keyboard LEDs: {}", e);
+        }
+        released
+    })
+    .await;
+    if result.is_ok() && released.is_ok() {
+        info!("Released held keys");
+    }
+    result.and(released)
+}
+
+#[cfg(feature = "tokio")]
+async fn drive_loop(
+    processor: &Arc<std::sync::Mutex<Processor>>,
+    events: &mut impl EventSource,
+    commands: &mut tokio::sync::mpsc::UnboundedReceiver<Command>,
+) -> Result<()> {
+    loop {
+        let timer = processor.lock().unwrap().next_timer();
+        let deadline = timer.unwrap_or_else(Instant::now);
+
+        tokio::select! {
+            event = events.next_event() => {
+                let Ok(event) = event else {
+                    warn!("Keyboard disconnected");
+                    return Ok(());
+                };
+                blocking(processor, move |processor| processor.input(&event)).await?;
+            }
+            command = commands.recv() => {
+                let Some(command) = command else {
+                    return Ok(());
+                };
+                blocking(processor, move |processor| processor.command(command)).await?;
+            }
+            _ = tokio::time::sleep_until(deadline.into()), if timer.is_some() => {
+                blocking(processor, |processor| processor.timer(Instant::now())).await?;
+            }
+        }
+    }
+}
+
+/// Runs a call into the processor on tokio's blocking pool, as a macro's delays sleep the
+/// thread writing it
+#[cfg(feature = "tokio")]
+async fn blocking<T: Send + 'static>(
+    processor: &Arc<std::sync::Mutex<Processor>>,
+    call: impl FnOnce(&mut Processor) -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let processor = processor.clone();
+    tokio::task::spawn_blocking(move || call(&mut processor.lock().unwrap())).await?
+}
+
+/// Opens the keyboard's LEDs if any of its layers light one
+fn open_leds(path: Option<&Path>, keyboard_config: &KeyboardConfig) -> Option<Box<dyn LedSink>> {
+    let path = path?;
+    let uses_leds = keyboard_config
+        .layers
+        .values()
+        .any(|layer| layer.led.is_some());
+    uses_leds.then(|| Leds::open(path)).and_then(|leds| {
+        leds.inspect_err(|e| warn!("Failed to open keyboard LEDs: {}", e))
+            .ok()
+            .map(|leds| Box::new(leds) as Box<dyn LedSink>)
+    })
+}
+
+/// Converts the kernel's wall clock timestamp to an instant, so time spent queued still counts
+fn event_time(event: &InputEvent) -> Instant {
+    let now = Instant::now();
+    SystemTime::now()
+        .duration_since(event.timestamp())
+        .ok()
+        .and_then(|age| now.checked_sub(age))
+        .unwrap_or(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn processor_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Processor>();
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn async_driver_can_be_spawned() {
+        use tokio::sync::mpsc::UnboundedReceiver;
+
+        fn assert_send<F: Future + Send>(
+            _: fn(Processor, evdev::Device, UnboundedReceiver<Command>) -> F,
+        ) {
+        }
+        assert_send(run_async);
+    }
+
+    #[cfg(feature = "tokio")]
+    impl EventSource for tokio::sync::mpsc::UnboundedReceiver<InputEvent> {
+        async fn next_event(&mut self) -> std::io::Result<InputEvent> {
+            self.recv()
+                .await
+                .ok_or_else(|| std::io::ErrorKind::NotConnected.into())
+        }
+    }
+
+    /// Drives a processor writing to a mock device with the key events, then with the event
+    /// channel closed if `disconnect`, otherwise with the command channel closed
+    #[cfg(feature = "tokio")]
+    async fn drive_mock(
+        events: &[(KeyCode, i32)],
+        disconnect: bool,
+    ) -> Vec<oxidekeys::io::OutputEvent> {
+        use oxidekeys::io::MockDevice;
+        use tokio::sync::mpsc::unbounded_channel;
+
+        let config: Config = serde_yaml::from_str(
+            "
+keyboards:
+  Test:
+    layout: qwerty
+    mappings: {}
+    layers: {}
+",
+        )
+        .unwrap();
+        let device = MockDevice::default();
+        let processor = Processor::new(
+            "Test",
+            PathBuf::from("/dev/input/event0"),
+            Box::new(device.clone()),
+            config,
+            None,
+            Controllers::default(),
+            Arc::new(Shared::default()),
+        )
+        .unwrap();
+        let (event_tx, event_rx) = unbounded_channel();
+        let (command_tx, command_rx) = unbounded_channel();
+        for (key, state) in events {
+            event_tx
+                .send(InputEvent::new(EventType::KEY.0, key.0, *state))
+                .unwrap();
+        }
+        let task = tokio::spawn(drive(processor, event_rx, command_rx));
+        // The events are processed before either channel is seen closed
+        while device.events().len() < events.len() {
+            tokio::task::yield_now().await;
+        }
+        if disconnect {
+            drop(event_tx);
+        } else {
+            drop(command_tx);
+        }
+        task.await.unwrap().unwrap();
+        device.events()
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn async_driver_passes_events_through_and_releases_on_disconnect() {
+        use oxidekeys::io::OutputEvent::{Press, Release};
+
+        let output = drive_mock(
+            &[
+                (KeyCode::KEY_A, PRESS),
+                (KeyCode::KEY_A, RELEASE),
+                (KeyCode::KEY_B, PRESS),
+            ],
+            true,
+        )
+        .await;
+        assert_eq!(
+            output,
+            [
+                Press(KeyCode::KEY_A),
+                Release(KeyCode::KEY_A),
+                Press(KeyCode::KEY_B),
+                Release(KeyCode::KEY_B),
+            ]
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn async_driver_releases_held_keys_once_commands_close() {
+        use oxidekeys::io::OutputEvent::{Press, Release};
+
+        let output = drive_mock(&[(KeyCode::KEY_A, PRESS)], false).await;
+        assert_eq!(output, [Press(KeyCode::KEY_A), Release(KeyCode::KEY_A)]);
+    }
+}
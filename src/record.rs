@@ -0,0 +1,158 @@
+use anyhow::{Context, Result, bail};
+use evdev::KeyCode;
+use log::info;
+use oxidekeys::config::Config;
+use oxidekeys::features::KeyEvent;
+use oxidekeys::io::OutputEvent;
+use oxidekeys::pipeline::simulate;
+use std::fs::{self, File};
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Writes the key events a keyboard's features receive, the first line is the keyboard's name
+/// and, after a tab, the profile it was recorded in if any, then each event is its microseconds
+/// since the first event, key code and state:
+///
+/// ```text
+/// Keychron K3
+/// 0 58 1
+/// 84211 30 1
+/// ```
+pub struct Recorder {
+    file: LineWriter<File>,
+    start: Option<Instant>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, name: &str, profile: Option<&str>) -> Result<Self> {
+        let mut file = LineWriter::new(File::create(path)?);
+        match profile {
+            Some(profile) => writeln!(file, "{}\t{}", name, profile)?,
+            None => writeln!(file, "{}", name)?,
+        }
+        info!("Recording {} to {}", name, path.display());
+        Ok(Self { file, start: None })
+    }
+
+    pub fn record(&mut self, event: &KeyEvent) -> Result<()> {
+        let start = *self.start.get_or_insert(event.time);
+        let offset = event.time.saturating_duration_since(start).as_micros();
+        // Codes rather than names, as keys evdev has no name for would not parse back
+        writeln!(self.file, "{} {} {}", offset, event.key.code(), event.state)?;
+        Ok(())
+    }
+}
+
+/// Runs a recording through the recorded keyboard's features at its original timing,
+/// logging what would have been typed
+pub fn replay(path: &Path, config: &Config) -> Result<()> {
+    for event in replayed(path, config)? {
+        info!("{:?}", event);
+    }
+
+    Ok(())
+}
+
+fn replayed(path: &Path, config: &Config) -> Result<Vec<OutputEvent>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read recording {}", path.display()))?;
+    let mut lines = content.lines();
+    let header = lines.next().unwrap_or_default();
+    // Recordings without a profile are replayed in the one used on startup
+    let (name, profile) = match header.split_once('\t') {
+        Some((name, profile)) => (name, Some(profile.to_owned())),
+        None => (header, config.default_profile()),
+    };
+    let (features, keyboard_config) = config.keyboard(profile.as_deref(), name);
+
+    let start = Instant::now();
+    let mut events = Vec::new();
+    for (number, line) in lines.enumerate() {
+        match parse_event(line, start) {
+            Some(event) => events.push(event),
+            // Line numbers count the name line
+            None => bail!("Invalid recording line {}: {}", number + 2, line),
+        }
+    }
+
+    info!("Replaying {} events of {}", events.len(), name);
+    simulate(keyboard_config, &features, &events)
+}
+
+fn parse_event(line: &str, start: Instant) -> Option<KeyEvent> {
+    let mut fields = line.split_whitespace();
+    let offset = fields.next()?.parse().ok()?;
+    // Older recordings name the keys
+    let key = fields.next()?;
+    let key = match key.parse() {
+        Ok(code) => KeyCode::new(code),
+        Err(_) => KeyCode::from_str(key).ok()?,
+    };
+    let state = fields.next()?.parse().ok()?;
+    Some(KeyEvent {
+        key,
+        state,
+        time: start + Duration::from_micros(offset),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev::KeyCode as K;
+    use oxidekeys::io::OutputEvent::{Press, Release};
+
+    #[test]
+    fn recordings_replay_in_their_profile_at_their_timing() {
+        let config: Config = serde_yaml::from_str(
+            "
+keyboards:
+  Test:
+    layout: qwerty
+    layers: {}
+    mappings: {}
+profiles:
+  typing:
+    keyboards:
+      Test:
+        layout: qwerty
+        layers: {}
+        mappings:
+          KEY_F:
+            tap: [KEY_F]
+            hold: [KEY_LEFTCTRL]
+            hrm_term: 200
+",
+        )
+        .unwrap();
+        // Not a key evdev has a name for
+        let unnamed = K::new(0x2f0);
+        let start = Instant::now();
+        let events = [
+            (0, K::KEY_F, 1),
+            (300, K::KEY_F, 0),
+            (400, unnamed, 1),
+            (450, unnamed, 0),
+        ];
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut recorder = Recorder::create(file.path(), "Test", Some("typing")).unwrap();
+        for (ms, key, state) in events {
+            let time = start + Duration::from_millis(ms);
+            recorder.record(&KeyEvent { key, state, time }).unwrap();
+        }
+        drop(recorder);
+
+        // Held past its hold term, as it was when recorded
+        assert_eq!(
+            replayed(file.path(), &config).unwrap(),
+            [
+                Press(K::KEY_LEFTCTRL),
+                Release(K::KEY_LEFTCTRL),
+                Press(unnamed),
+                Release(unnamed),
+            ]
+        );
+    }
+}
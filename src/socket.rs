@@ -0,0 +1,299 @@
+use crate::device::SharedDevice;
+use anyhow::{Result, bail};
+use crossbeam_channel::{Sender, bounded};
+use evdev::KeyCode;
+use log::{debug, info, warn};
+use oxidekeys::config::{Config, config};
+use oxidekeys::features::Stats;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{fs, thread};
+
+/// Command from the control socket, handled by each keyboard's thread
+#[derive(Debug)]
+pub enum Command {
+    Layer(LayerCommand, String),
+    /// Replies with a snapshot of the keyboard's state
+    Status(Sender<KeyboardStatus>),
+    /// The focused application changed
+    App(Option<String>),
+    /// Rebuilds the keyboard from the profile
+    Profile(String),
+    /// The shared bypass flag changed
+    Bypass,
+    /// Rebuilds the keyboard from the reloaded config
+    Reload(Box<Config>),
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyboardStatus {
+    pub name: String,
+    pub layers: Vec<String>,
+    /// Keys held on the virtual device
+    pub held: Vec<KeyCode>,
+    /// Key events read from the device
+    pub events: u64,
+    /// Counters of each enabled feature
+    pub stats: BTreeMap<&'static str, Stats>,
+}
+
+#[derive(Debug, Serialize)]
+struct Status {
+    uptime_secs: u64,
+    profile: Option<String>,
+    bypass: bool,
+    keyboards: Vec<KeyboardStatus>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum LayerCommand {
+    On,
+    Off,
+    Toggle,
+}
+
+/// Command senders of the running keyboards
+pub type Controllers = Arc<Mutex<Vec<Sender<Command>>>>;
+
+/// State shared by every keyboard, keyboards plugged in later start with it
+#[derive(Default)]
+pub struct Shared {
+    /// Profile the keyboards run
+    pub profile: Mutex<Option<String>>,
+    /// Keys pass through untouched while set
+    pub bypass: Arc<AtomicBool>,
+    /// Virtual device every keyboard writes to with `merge_output`, otherwise each has its own
+    pub output: Option<SharedDevice>,
+    /// Config the keyboards run, and keyboards plugged in later start with
+    pub config: Mutex<Config>,
+    /// Where the config is reloaded from, the default location when unset
+    pub config_path: Option<PathBuf>,
+}
+
+/// Switches every running keyboard to the profile, and the ones plugged in later
+pub fn switch_profile(controllers: &Controllers, shared: &Shared, profile: &str) {
+    info!("Switching to profile {}", profile);
+    *shared.profile.lock().unwrap() = Some(profile.to_owned());
+    controllers
+        .lock()
+        .unwrap()
+        .retain(|tx| tx.send(Command::Profile(profile.to_owned())).is_ok());
+}
+
+/// Tells every running keyboard the bypass flag changed, so they release what they hold
+pub fn notify_bypass(controllers: &Controllers) {
+    controllers
+        .lock()
+        .unwrap()
+        .retain(|tx| tx.send(Command::Bypass).is_ok());
+}
+
+/// Reads the config again and restarts every running keyboard with it, keeping the running
+/// config when the new one doesn't load. What's set up once on startup, such as the virtual
+/// devices, stays as it was
+pub fn reload(controllers: &Controllers, shared: &Shared) -> Result<()> {
+    let mut new_config = config(shared.config_path.as_deref())
+        .inspect_err(|e| warn!("Config not reloaded: {:#}", e))?;
+    new_config.log_migrated();
+    let mut current = shared.config.lock().unwrap();
+    new_config.globals.no_emit = current.globals.no_emit;
+    new_config.merge_output = current.merge_output;
+    new_config.device = current.device.clone();
+    *current = new_config.clone();
+
+    info!("Config reloaded");
+    controllers.lock().unwrap().retain(|tx| {
+        tx.send(Command::Reload(Box::new(new_config.clone())))
+            .is_ok()
+    });
+    Ok(())
+}
+
+/// Listens for line commands on a Unix socket:
+/// `layer on <name>`, `layer off <name>`, `layer toggle <name>`, `profile <name>`,
+/// `bypass on`, `bypass off`, `bypass toggle`, `reload` and `status`
+pub fn serve(path: &Path, controllers: Controllers, shared: Arc<Shared>) -> Result<()> {
+    let started = Instant::now();
+    // A socket left behind by an earlier run would fail the bind
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    info!("Listening on {}", path.display());
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let controllers = controllers.clone();
+                    let shared = shared.clone();
+                    thread::spawn(move || {
+                        let control = Control {
+                            controllers: &controllers,
+                            shared: &shared,
+                            started,
+                        };
+                        if let Err(e) = handle_client(stream, &control) {
+                            debug!("Socket client error: {}", e);
+                        }
+                    });
+                }
+                Err(e) => warn!("Socket accept error: {}", e),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// What a client's commands act on
+struct Control<'a> {
+    controllers: &'a Controllers,
+    shared: &'a Shared,
+    started: Instant,
+}
+
+fn handle_client(stream: UnixStream, control: &Control) -> Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        let reply = match run(line.trim(), control) {
+            Ok(reply) => reply,
+            Err(e) => format!("error: {}", e),
+        };
+        writeln!(writer, "{}", reply)?;
+    }
+    Ok(())
+}
+
+fn run(line: &str, control: &Control) -> Result<String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    match words.as_slice() {
+        ["profile", name @ ..] if !name.is_empty() => {
+            let name = name.join(" ");
+            if !control
+                .shared
+                .config
+                .lock()
+                .unwrap()
+                .profiles
+                .contains_key(&name)
+            {
+                bail!("unknown profile '{}'", name);
+            }
+            switch_profile(control.controllers, control.shared, &name);
+            Ok("ok".to_owned())
+        }
+        ["bypass", action] => {
+            let bypass = &control.shared.bypass;
+            let on = match *action {
+                "on" => true,
+                "off" => false,
+                "toggle" => !bypass.load(Ordering::Relaxed),
+                _ => bail!("unknown bypass action '{}'", action),
+            };
+            bypass.store(on, Ordering::Relaxed);
+            notify_bypass(control.controllers);
+            Ok("ok".to_owned())
+        }
+        ["layer", action, name @ ..] if !name.is_empty() => {
+            let action = match *action {
+                "on" => LayerCommand::On,
+                "off" => LayerCommand::Off,
+                "toggle" => LayerCommand::Toggle,
+                _ => bail!("unknown layer action '{}'", action),
+            };
+            let name = name.join(" ");
+            // Keyboards that went away are dropped
+            control
+                .controllers
+                .lock()
+                .unwrap()
+                .retain(|tx| tx.send(Command::Layer(action, name.clone())).is_ok());
+            Ok("ok".to_owned())
+        }
+        ["reload"] => {
+            reload(control.controllers, control.shared)?;
+            Ok("ok".to_owned())
+        }
+        ["status"] => {
+            // Each keyboard answers from its own thread, so the snapshot never blocks its events.
+            // The senders are copied out first, a keyboard handling a command of its own may
+            // need the lock before it gets to answer
+            let senders = control.controllers.lock().unwrap().clone();
+            let mut gone = Vec::new();
+            let mut replies = Vec::new();
+            for tx in senders {
+                let (reply_tx, reply_rx) = bounded(1);
+                match tx.send(Command::Status(reply_tx)) {
+                    Ok(()) => replies.push(reply_rx),
+                    Err(_) => gone.push(tx),
+                }
+            }
+            if !gone.is_empty() {
+                control
+                    .controllers
+                    .lock()
+                    .unwrap()
+                    .retain(|tx| !gone.iter().any(|gone| gone.same_channel(tx)));
+            }
+            let deadline = Instant::now() + Duration::from_secs(1);
+            let keyboards = replies
+                .iter()
+                .filter_map(|reply_rx| reply_rx.recv_deadline(deadline).ok())
+                .collect();
+            let status = Status {
+                uptime_secs: control.started.elapsed().as_secs(),
+                profile: control.shared.profile.lock().unwrap().clone(),
+                bypass: control.shared.bypass.load(Ordering::Relaxed),
+                keyboards,
+            };
+            Ok(serde_json::to_string(&status)?)
+        }
+        _ => bail!("unknown command '{}'", line),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::unbounded;
+
+    #[test]
+    fn status_leaves_the_controllers_unlocked_while_keyboards_answer() {
+        let controllers = Controllers::default();
+        let shared = Shared::default();
+        let (tx, rx) = unbounded();
+        controllers.lock().unwrap().push(tx);
+        let keyboard_controllers = controllers.clone();
+        thread::spawn(move || {
+            if let Ok(Command::Status(reply)) = rx.recv() {
+                // As a keyboard switching profile right then would, before answering
+                drop(keyboard_controllers.lock().unwrap());
+                let _ = reply.send(KeyboardStatus {
+                    name: "Test".to_owned(),
+                    layers: Vec::new(),
+                    held: Vec::new(),
+                    events: 0,
+                    stats: BTreeMap::new(),
+                });
+            }
+        });
+
+        let control = Control {
+            controllers: &controllers,
+            shared: &shared,
+            started: Instant::now(),
+        };
+        let reply = run("status", &control).unwrap();
+        let status: serde_json::Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(status["keyboards"][0]["name"], "Test");
+    }
+}